@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS MessageRevision;
+ALTER TABLE Message DROP COLUMN edited_at;
\ No newline at end of file
@@ -0,0 +1,13 @@
+-- Add up migration script here
+ALTER TABLE Message
+    ADD COLUMN edited_at TIMESTAMP NULL DEFAULT NULL;
+
+CREATE TABLE MessageRevision (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    message_id BIGINT UNSIGNED NOT NULL,
+    -- A previous body of the message, and when it was set
+    body VARCHAR(1000) NOT NULL,
+    time_set TIMESTAMP NOT NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (message_id) REFERENCES Message(id)
+);
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE RoomMember DROP COLUMN role;
+ALTER TABLE Message DROP FOREIGN KEY fk_message_deleted_by;
+ALTER TABLE Message
+    DROP COLUMN deleted_by,
+    DROP COLUMN deleted_at;
\ No newline at end of file
@@ -0,0 +1,19 @@
+-- Add up migration script here
+ALTER TABLE Message
+    ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL,
+    ADD COLUMN deleted_by BIGINT UNSIGNED NULL DEFAULT NULL,
+    ADD CONSTRAINT fk_message_deleted_by FOREIGN KEY (deleted_by) REFERENCES User(id);
+
+-- Owners and admins may moderate a room
+ALTER TABLE RoomMember
+    ADD COLUMN role ENUM("OWNER", "ADMIN", "MEMBER") NOT NULL DEFAULT "MEMBER";
+
+-- Existing rooms have no owner yet, so the member with the lowest user id is
+-- made the owner of each, letting them moderate it
+UPDATE RoomMember rm
+INNER JOIN (
+    SELECT room_id, MIN(user_id) AS user_id
+    FROM RoomMember
+    GROUP BY room_id
+) first_member ON first_member.room_id = rm.room_id AND first_member.user_id = rm.user_id
+SET rm.role = 'OWNER';
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE MessageReaction (
+    message_id BIGINT UNSIGNED NOT NULL,
+    user_id BIGINT UNSIGNED NOT NULL,
+    -- Binary collation so that distinct emoji are never considered equal
+    emoji VARCHAR(32) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL,
+    time_set TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (message_id, user_id, emoji),
+    FOREIGN KEY (message_id) REFERENCES Message(id),
+    FOREIGN KEY (user_id) REFERENCES User(id)
+);
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- Add down migration script here
+ALTER TABLE Message
+    DROP FOREIGN KEY fk_message_thread_root,
+    DROP FOREIGN KEY fk_message_reply_to;
+
+ALTER TABLE Message
+    DROP INDEX idx_message_thread,
+    DROP COLUMN thread_root_id,
+    DROP COLUMN reply_to;
\ No newline at end of file
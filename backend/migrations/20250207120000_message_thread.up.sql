@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE Message
+    ADD COLUMN reply_to BIGINT UNSIGNED NULL,
+    ADD COLUMN thread_root_id BIGINT UNSIGNED NULL,
+    ADD INDEX idx_message_thread (thread_root_id, id),
+    ADD CONSTRAINT fk_message_reply_to FOREIGN KEY (reply_to) REFERENCES Message(id),
+    ADD CONSTRAINT fk_message_thread_root FOREIGN KEY (thread_root_id) REFERENCES Message(id);
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE Message DROP INDEX ft_message_body;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE Message ADD FULLTEXT INDEX ft_message_body (body);
\ No newline at end of file
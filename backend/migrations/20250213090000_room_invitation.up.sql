@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE RoomInvitation (
+    room_id BIGINT UNSIGNED NOT NULL,
+    user_id BIGINT UNSIGNED NOT NULL,
+    inviter_id BIGINT UNSIGNED NOT NULL,
+    time_sent TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (room_id, user_id),
+    FOREIGN KEY (room_id) REFERENCES Room(id),
+    FOREIGN KEY (user_id) REFERENCES User(id),
+    FOREIGN KEY (inviter_id) REFERENCES User(id)
+);
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE TABLE RoomInviteCode (
+    code CHAR(32) NOT NULL,
+    room_id BIGINT UNSIGNED NOT NULL,
+    creator_id BIGINT UNSIGNED NOT NULL,
+    time_created TIMESTAMP NOT NULL DEFAULT NOW(),
+    -- No expiry or usage limit when NULL
+    expires_at TIMESTAMP NULL,
+    max_uses BIGINT UNSIGNED NULL,
+    uses BIGINT UNSIGNED NOT NULL DEFAULT 0,
+    PRIMARY KEY (code),
+    FOREIGN KEY (room_id) REFERENCES Room(id),
+    FOREIGN KEY (creator_id) REFERENCES User(id)
+);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS DirectRoom;
+ALTER TABLE Room DROP COLUMN kind;
\ No newline at end of file
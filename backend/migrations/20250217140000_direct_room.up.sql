@@ -0,0 +1,14 @@
+-- Add up migration script here
+ALTER TABLE Room ADD COLUMN kind ENUM("GROUP", "DIRECT") NOT NULL DEFAULT "GROUP";
+
+-- The direct room of each pair of users, stored with user_id < other_user_id
+CREATE TABLE DirectRoom (
+    user_id BIGINT UNSIGNED NOT NULL,
+    other_user_id BIGINT UNSIGNED NOT NULL,
+    room_id BIGINT UNSIGNED NOT NULL,
+    PRIMARY KEY (user_id, other_user_id),
+    UNIQUE (room_id),
+    FOREIGN KEY (user_id) REFERENCES User(id),
+    FOREIGN KEY (other_user_id) REFERENCES User(id),
+    FOREIGN KEY (room_id) REFERENCES Room(id)
+);
\ No newline at end of file
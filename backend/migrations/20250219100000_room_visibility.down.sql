@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX room_visibility ON Room;
+ALTER TABLE Room DROP COLUMN visibility;
\ No newline at end of file
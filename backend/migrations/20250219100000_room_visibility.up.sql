@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE Room ADD COLUMN visibility ENUM("PRIVATE", "PUBLIC") NOT NULL DEFAULT "PRIVATE";
+CREATE INDEX room_visibility ON Room (visibility, id);
\ No newline at end of file
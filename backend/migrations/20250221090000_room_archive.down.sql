@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE Room DROP COLUMN archived_at;
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Rooms kept once their last member leaves, under the archive policy
+ALTER TABLE Room ADD COLUMN archived_at TIMESTAMP NULL DEFAULT NULL;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE RoomMember DROP COLUMN last_read_message_id;
\ No newline at end of file
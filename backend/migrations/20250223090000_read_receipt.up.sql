@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Messages with ids up to and including this have been read by the member
+ALTER TABLE RoomMember ADD COLUMN last_read_message_id BIGINT UNSIGNED NULL DEFAULT NULL;
\ No newline at end of file
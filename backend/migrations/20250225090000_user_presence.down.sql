@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE User
+    DROP COLUMN last_seen_at,
+    DROP COLUMN last_seen_visibility;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- Updated on authenticated activity, at most once a minute
+ALTER TABLE User
+    ADD COLUMN last_seen_at TIMESTAMP NULL DEFAULT NULL,
+    ADD COLUMN last_seen_visibility ENUM("EVERYONE", "FRIENDS") NOT NULL DEFAULT "EVERYONE";
\ No newline at end of file
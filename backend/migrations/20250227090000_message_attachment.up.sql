@@ -0,0 +1,19 @@
+-- Add up migration script here
+CREATE TABLE Attachment (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    room_id BIGINT UNSIGNED NOT NULL,
+    uploader_id BIGINT UNSIGNED NOT NULL,
+    -- NULL until the upload is sent with a message
+    message_id BIGINT UNSIGNED NULL,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(127) NOT NULL,
+    size BIGINT UNSIGNED NOT NULL,
+    -- Key of the contents in attachment storage, see AttachmentStorage
+    storage_key CHAR(36) NOT NULL,
+    has_thumbnail BOOLEAN NOT NULL DEFAULT FALSE,
+    time_uploaded TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id),
+    FOREIGN KEY (room_id) REFERENCES Room(id),
+    FOREIGN KEY (uploader_id) REFERENCES User(id),
+    FOREIGN KEY (message_id) REFERENCES Message(id)
+);
\ No newline at end of file
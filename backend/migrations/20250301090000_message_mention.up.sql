@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TABLE MessageMention (
+    message_id BIGINT UNSIGNED NOT NULL,
+    -- The mentioned user, who was a member of the room when the message was sent
+    user_id BIGINT UNSIGNED NOT NULL,
+    PRIMARY KEY (message_id, user_id),
+    INDEX idx_mention_user (user_id, message_id),
+    FOREIGN KEY (message_id) REFERENCES Message(id),
+    FOREIGN KEY (user_id) REFERENCES User(id)
+);
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE MessagePin (
+    message_id BIGINT UNSIGNED NOT NULL,
+    room_id BIGINT UNSIGNED NOT NULL,
+    pinned_by BIGINT UNSIGNED NOT NULL,
+    time_pinned TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (message_id),
+    INDEX idx_pin_room (room_id, time_pinned),
+    FOREIGN KEY (message_id) REFERENCES Message(id),
+    FOREIGN KEY (room_id) REFERENCES Room(id),
+    FOREIGN KEY (pinned_by) REFERENCES User(id)
+);
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE UserToken DROP COLUMN last_used;
\ No newline at end of file
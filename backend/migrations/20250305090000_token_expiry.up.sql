@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE UserToken
+    ADD COLUMN last_used TIMESTAMP NOT NULL DEFAULT NOW();
+
+UPDATE UserToken SET last_used = time_set;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DELETE FROM UserToken;
+
+ALTER TABLE UserToken
+    CHANGE COLUMN token_hash token CHAR(36) NOT NULL;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- Existing tokens were stored in plaintext, so they are all logged out
+DELETE FROM UserToken;
+
+ALTER TABLE UserToken
+    CHANGE COLUMN token token_hash CHAR(64) NOT NULL;
\ No newline at end of file
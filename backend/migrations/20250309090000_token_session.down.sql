@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE UserToken
+    DROP INDEX idx_token_session,
+    DROP COLUMN session_id,
+    DROP COLUMN ip_address;
\ No newline at end of file
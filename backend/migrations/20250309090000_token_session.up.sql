@@ -0,0 +1,10 @@
+-- Add up migration script here
+ALTER TABLE UserToken
+    ADD COLUMN session_id CHAR(32) NULL,
+    ADD COLUMN ip_address VARCHAR(45) NULL;
+
+UPDATE UserToken SET session_id = REPLACE(UUID(), '-', '');
+
+ALTER TABLE UserToken
+    MODIFY COLUMN session_id CHAR(32) NOT NULL,
+    ADD UNIQUE INDEX idx_token_session (session_id);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS LoginChallenge;
+DROP TABLE IF EXISTS UserRecoveryCode;
+DROP TABLE IF EXISTS UserTotp;
\ No newline at end of file
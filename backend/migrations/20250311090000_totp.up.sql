@@ -0,0 +1,28 @@
+-- Add up migration script here
+CREATE TABLE UserTotp (
+    user_id BIGINT UNSIGNED NOT NULL,
+    secret VARBINARY(20) NOT NULL,
+    -- Unset until the user confirms enrolment with a code
+    confirmed_at TIMESTAMP NULL DEFAULT NULL,
+    -- The last time step a code was accepted for, so codes cannot be reused
+    last_used_step BIGINT UNSIGNED NULL DEFAULT NULL,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES User(id)
+);
+
+CREATE TABLE UserRecoveryCode (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    user_id BIGINT UNSIGNED NOT NULL,
+    code_hash VARCHAR(256) NOT NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES User(id)
+);
+
+CREATE TABLE LoginChallenge (
+    challenge_hash CHAR(64) NOT NULL,
+    user_id BIGINT UNSIGNED NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    failed_attempts INT UNSIGNED NOT NULL DEFAULT 0,
+    PRIMARY KEY (challenge_hash),
+    FOREIGN KEY (user_id) REFERENCES User(id)
+);
\ No newline at end of file
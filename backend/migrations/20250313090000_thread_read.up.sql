@@ -0,0 +1,21 @@
+-- Add up migration script here
+CREATE TABLE ThreadRead (
+    thread_root_id BIGINT UNSIGNED NOT NULL,
+    user_id BIGINT UNSIGNED NOT NULL,
+    -- Replies in the thread with ids up to and including this have been read
+    last_read_message_id BIGINT UNSIGNED NOT NULL,
+    PRIMARY KEY (thread_root_id, user_id),
+    FOREIGN KEY (thread_root_id) REFERENCES Message(id),
+    FOREIGN KEY (user_id) REFERENCES User(id)
+);
+
+-- Replies were marked as read through the room, so carry that over for the
+-- threads members were mentioned in
+INSERT INTO ThreadRead (thread_root_id, user_id, last_read_message_id)
+SELECT m.thread_root_id, mm.user_id, MAX(m.id)
+FROM MessageMention mm
+INNER JOIN Message m ON m.id = mm.message_id
+INNER JOIN RoomMember rm ON rm.room_id = m.room_id AND rm.user_id = mm.user_id
+WHERE m.thread_root_id IS NOT NULL
+AND m.id <= rm.last_read_message_id
+GROUP BY m.thread_root_id, mm.user_id;
\ No newline at end of file
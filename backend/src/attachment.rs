@@ -0,0 +1,96 @@
+use std::io::{self, Cursor};
+
+use image::ImageFormat;
+use uuid::Uuid;
+
+use crate::storage::AttachmentStorage;
+
+/// Thumbnails fit within a square of this many pixels
+const THUMBNAIL_SIZE: u32 = 256;
+/// Thumbnails are stored alongside the attachment, under its key with this
+/// suffix
+const THUMBNAIL_KEY_SUFFIX: &str = ".thumb";
+
+/// Where a newly uploaded attachment was stored
+pub struct StoredAttachment {
+    pub storage_key: String,
+    pub has_thumbnail: bool
+}
+
+/// Enforces the size and type limits of attachments, and keeps their contents
+/// and thumbnails in the configured storage.
+pub struct AttachmentService {
+    storage: Box<dyn AttachmentStorage>,
+    /// The largest accepted attachment, in bytes
+    pub max_size: usize,
+    /// Accepted MIME types, such as `image/png`
+    allowed_types: Vec<String>
+}
+
+impl AttachmentService {
+    pub fn new(storage: Box<dyn AttachmentStorage>, max_size: usize, allowed_types: Vec<String>) -> Self {
+        AttachmentService { storage, max_size, allowed_types }
+    }
+
+    pub fn is_allowed_type(&self, content_type: &str) -> bool {
+        self.allowed_types.iter().any(|allowed| allowed.eq_ignore_ascii_case(content_type))
+    }
+
+    /// Check that `contents` plausibly are of `content_type`. Only images can
+    /// be recognised, other types are taken on trust and are always served
+    /// as downloads.
+    pub fn matches_content(content_type: &str, contents: &[u8]) -> bool {
+        if !content_type.starts_with("image/") {
+            return true
+        }
+        image::guess_format(contents).is_ok_and(|format| format.to_mime_type() == content_type)
+    }
+
+    /// Store the `contents` of a new attachment under a generated key, along
+    /// with a thumbnail if it is an image. Blocks, see `AttachmentStorage`.
+    pub fn store(&self, contents: &[u8], content_type: &str) -> io::Result<StoredAttachment> {
+        let storage_key = Uuid::new_v4().to_string();
+        self.storage.put(&storage_key, contents)?;
+
+        // An image that cannot be decoded is kept without a thumbnail
+        let thumbnail = match content_type.starts_with("image/") {
+            true => Self::thumbnail(contents),
+            false => None,
+        };
+        let has_thumbnail = match thumbnail {
+            Some(thumbnail) => {
+                let thumbnail_key = format!("{}{}", storage_key, THUMBNAIL_KEY_SUFFIX);
+                self.storage.put(&thumbnail_key, &thumbnail).is_ok()
+            },
+            None => false,
+        };
+
+        Ok(StoredAttachment { storage_key, has_thumbnail })
+    }
+
+    /// Read the contents of the attachment stored under `storage_key`, or its
+    /// thumbnail. Blocks, see `AttachmentStorage`.
+    pub fn read(&self, storage_key: &str, thumbnail: bool) -> io::Result<Vec<u8>> {
+        match thumbnail {
+            true => self.storage.get(&format!("{}{}", storage_key, THUMBNAIL_KEY_SUFFIX)),
+            false => self.storage.get(storage_key),
+        }
+    }
+
+    /// Remove the attachment stored under `storage_key`, and its thumbnail if
+    /// any. Blocks, see `AttachmentStorage`.
+    pub fn remove(&self, storage_key: &str) -> io::Result<()> {
+        self.storage.delete(&format!("{}{}", storage_key, THUMBNAIL_KEY_SUFFIX))?;
+        self.storage.delete(storage_key)
+    }
+
+    /// Scale down an image to fit the thumbnail size, encoded as PNG
+    fn thumbnail(contents: &[u8]) -> Option<Vec<u8>> {
+        let image = image::load_from_memory(contents).ok()?;
+        let mut thumbnail = Vec::new();
+        image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Png)
+            .ok()?;
+        Some(thumbnail)
+    }
+}
@@ -1,21 +1,50 @@
 use log::warn;
 use sqlx::{
     MySql,
-    Pool
+    Pool,
+    Transaction
 };
 use sqlx::mysql::MySqlPoolOptions;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use uuid::Uuid;
 
 use common::{
+    markdown,
+    AuthToken,
     ChatMessage,
+    ChatMessageRevision,
+    ChatPinnedMessage,
     ChatRoom,
     UserInfo
 };
 
 use crate::models::{
+    DBAttachment,
     DBAuthInfo,
+    DBLastSeen,
+    DBLastSeenVisibility,
+    DBMemberRoom,
+    DBMessage,
+    DBPinnedMessage,
+    DBReactionCount,
+    DBRecoveryCode,
+    DBRoom,
+    DBRoomInvitation,
+    DBRoomInviteCode,
+    DBRoomKind,
+    DBRoomLeave,
+    DBRoomListing,
     DBRoomMember,
-    DBUser
+    DBRoomRole,
+    DBRoomVisibility,
+    DBSession,
+    DBTotp,
+    DBUser,
+    EmptyRoomPolicy,
+    MessageSearchParam,
+    SessionPolicy
 };
 
 type DBResult<T> = Result<T, DatabaseServiceError>;
@@ -49,21 +78,39 @@ impl std::fmt::Display for DatabaseServiceError {
 }
 
 pub struct DatabaseService {
-    conn_pool: Pool<MySql>
+    conn_pool: Pool<MySql>,
+    session_policy: SessionPolicy,
+    /// Secret key that auth tokens are hashed with before being stored
+    token_key: Vec<u8>
 }
 
 impl DatabaseService {
     /// Create and initialise the database service, connecting to the MySQL database
-    /// at the provided `url`.
+    /// at the provided `url`. Auth tokens expire according to `session_policy`,
+    /// and are stored hashed with `token_key`.
     /// 
     /// # Panics
     /// A panic occurs if a connection cannot be established.
-    pub async fn new(url: &str) -> Self {
+    pub async fn new(url: &str, session_policy: SessionPolicy, token_key: &[u8]) -> Self {
         let pool = MySqlPoolOptions::new().connect(url)
             .await
             .expect("Failed to connect to the database");
 
-        DatabaseService { conn_pool: pool }
+        DatabaseService { conn_pool: pool, session_policy, token_key: token_key.to_vec() }
+    }
+
+    /// The policy by which auth tokens expire.
+    pub fn session_policy(&self) -> &SessionPolicy {
+        &self.session_policy
+    }
+
+    /// The keyed hash of `token` that is stored in place of the token, so that
+    /// the contents of the database cannot be used to authenticate.
+    fn token_hash(&self, token: &AuthToken) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.token_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(&token.0);
+        hex::encode(mac.finalize().into_bytes())
     }
 
     /// Test the current connection to the database by performing a simple query.
@@ -123,115 +170,1728 @@ impl DatabaseService {
         Ok(qr?)
     }
 
-    /// Retrieve the User record from the connected database with the provided
-    /// `user_id`.
-    pub async fn user_get_by_id(&self, user_id: &u64) -> DBResult<DBUser> {
+    /// Retrieve the User record from the connected database with the provided
+    /// `user_id`.
+    pub async fn user_get_by_id(&self, user_id: &u64) -> DBResult<DBUser> {
+        let qr = sqlx::query_as!(
+            DBUser,
+            "SELECT *
+            FROM User
+            WHERE id = ?;",
+            user_id)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        Ok(qr?)
+    }
+
+    /// Create an entry in the UserToken table, mapping an auth `token` to a
+    /// `user_id` granting authorization. New entries are given a random
+    /// session id, which existing entries keep.
+    pub async fn user_set_token(
+        &self,
+        user_id: &u64,
+        token: &AuthToken,
+        user_agent: &str,
+        ip_address: Option<&str>
+    ) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "INSERT INTO UserToken (token_hash, session_id, user_id, user_agent, ip_address) VALUES (?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE user_agent = ?, ip_address = ?;",
+            self.token_hash(token),
+            Uuid::new_v4().simple().to_string(),
+            user_id,
+            user_agent,
+            ip_address,
+            user_agent,
+            ip_address)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() > 0 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::KeyAlreadyExists),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Find the session of the provided `token` (if present), including the
+    /// user_id it is associated with.
+    pub async fn user_get_session(&self, token: &AuthToken) -> DBResult<DBSession> {
+        let qr = sqlx::query_as!(
+            DBSession,
+            "SELECT session_id, user_id, time_set, last_used
+            FROM UserToken
+            WHERE token_hash = ?",
+            self.token_hash(token)
+        ).fetch_one(&self.conn_pool)
+        .await;
+
+        match qr {
+            Ok(session) => Ok(session),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Find the session specified by `session_id` (if present), such as to
+    /// check that a connection opened with it is still logged in.
+    pub async fn user_get_session_by_id(&self, session_id: &str) -> DBResult<DBSession> {
+        let qr = sqlx::query_as!(
+            DBSession,
+            "SELECT session_id, user_id, time_set, last_used
+            FROM UserToken
+            WHERE session_id = ?",
+            session_id
+        ).fetch_one(&self.conn_pool)
+        .await;
+
+        match qr {
+            Ok(session) => Ok(session),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record that the provided `token` was used now, sliding its idle timeout
+    /// forward. Only updated once the last use is a minute old, to limit writes.
+    pub async fn user_touch_token(&self, token: &AuthToken) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE UserToken
+            SET last_used = NOW()
+            WHERE token_hash = ?
+            AND last_used < NOW() - INTERVAL 1 MINUTE",
+            self.token_hash(token))
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the provided `token`, regardless of the user it belongs to.
+    pub async fn user_expire_token(&self, token: &AuthToken) -> DBResult<()> {
+        let qr = sqlx::query!("DELETE FROM UserToken WHERE token_hash = ?", self.token_hash(token))
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() > 0 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Remove all tokens that have expired under the session policy. Returns
+    /// the number of tokens removed.
+    pub async fn user_purge_expired_tokens(&self) -> DBResult<u64> {
+        let qr = sqlx::query!(
+            "DELETE FROM UserToken
+            WHERE time_set <= NOW() - INTERVAL ? SECOND
+            OR last_used <= NOW() - INTERVAL ? SECOND",
+            self.session_policy.lifetime_secs,
+            self.session_policy.idle_timeout_secs)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r)  => Ok(r.rows_affected()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the provided `token` from mapping to `user_id`, effectively
+    /// logging out the user from their current client.
+    pub async fn user_remove_token(&self, user_id: &u64, token: &AuthToken) -> DBResult<()> {
+        let qr = sqlx::query!("DELETE FROM UserToken WHERE user_id = ? AND token_hash = ?",
+            user_id,
+            self.token_hash(token))
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() > 0 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Retrieve information about the associated auth tokens for a provided `user_id`.
+    /// `token` is used to determine which of the returned tokens is a made by the
+    /// requesting user.
+    pub async fn user_get_associated_tokens(&self, user_id: &u64, token: &AuthToken) -> DBResult<Vec<DBAuthInfo>> {
+        let qr = sqlx::query_as!(
+            DBAuthInfo,
+            "SELECT session_id, user_agent, ip_address, time_set, last_used,
+                IF(token_hash = ?, true, false) as 'is_requester: _'
+            FROM UserToken
+            WHERE user_id = ?
+            ORDER BY time_set",
+            self.token_hash(token),
+            user_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(info) => Ok(info),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the token of the session specified by `session_id`, if it belongs
+    /// to `user_id`, logging out that client.
+    pub async fn user_revoke_session(&self, user_id: &u64, session_id: &str) -> DBResult<()> {
+        let qr = sqlx::query!("DELETE FROM UserToken WHERE user_id = ? AND session_id = ?",
+            user_id,
+            session_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() > 0 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Remove all tokens associated with the provided `user_id`.
+    pub async fn user_clear_tokens_by_id(&self, user_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!("DELETE FROM UserToken WHERE user_id = ?", user_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        println!("user_clear_tokens_by_id: {:?}", qr);
+
+        match qr {
+            Ok(_)  => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Create a login challenge for `user_id`, to be answered with a second
+    /// factor within `lifetime_secs`. Only the hash of `challenge` is stored,
+    /// as with tokens. Returns when the challenge expires.
+    pub async fn user_create_login_challenge(
+        &self,
+        user_id: &u64,
+        challenge: &AuthToken,
+        lifetime_secs: &u64
+    ) -> DBResult<DateTime<Utc>> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        sqlx::query!(
+            "INSERT INTO LoginChallenge (challenge_hash, user_id, expires_at)
+            VALUES (?, ?, NOW() + INTERVAL ? SECOND)",
+            self.token_hash(challenge),
+            user_id,
+            lifetime_secs)
+            .execute(&mut *tx)
+            .await?;
+
+        let qr = sqlx::query!(
+            "SELECT expires_at
+            FROM LoginChallenge
+            WHERE challenge_hash = ?",
+            self.token_hash(challenge))
+            .fetch_one(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(r) => {
+                tx.commit().await?;
+                Ok(r.expires_at)
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Find the user that the provided `challenge` was created for, if it has
+    /// not expired and has failed fewer than `max_failed_attempts` times.
+    pub async fn user_get_login_challenge(&self, challenge: &AuthToken, max_failed_attempts: &u32) -> DBResult<u64> {
+        let qr = sqlx::query!(
+            "SELECT user_id
+            FROM LoginChallenge
+            WHERE challenge_hash = ?
+            AND expires_at > NOW()
+            AND failed_attempts < ?",
+            self.token_hash(challenge),
+            max_failed_attempts)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) => Ok(r.user_id),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record a wrong code being given for the provided `challenge`.
+    pub async fn user_fail_login_challenge(&self, challenge: &AuthToken) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE LoginChallenge
+            SET failed_attempts = failed_attempts + 1
+            WHERE challenge_hash = ?",
+            self.token_hash(challenge))
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the provided `challenge` once it has been answered. Fails with
+    /// `NoResult` if it was already removed, so each challenge is only
+    /// answered once.
+    pub async fn user_remove_login_challenge(&self, challenge: &AuthToken) -> DBResult<()> {
+        let qr = sqlx::query!("DELETE FROM LoginChallenge WHERE challenge_hash = ?", self.token_hash(challenge))
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove all login challenges that have expired. Returns the number of
+    /// challenges removed.
+    pub async fn user_purge_expired_login_challenges(&self) -> DBResult<u64> {
+        let qr = sqlx::query!("DELETE FROM LoginChallenge WHERE expires_at <= NOW()")
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r)  => Ok(r.rows_affected()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve the TOTP secret of the user specified by `user_id`, whether or
+    /// not its enrolment has been confirmed.
+    pub async fn user_get_totp(&self, user_id: &u64) -> DBResult<DBTotp> {
+        let qr = sqlx::query_as!(
+            DBTotp,
+            "SELECT secret, confirmed_at, last_used_step
+            FROM UserTotp
+            WHERE user_id = ?",
+            user_id)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        Ok(qr?)
+    }
+
+    /// Start enrolling `user_id` in TOTP with the provided `secret`, replacing
+    /// any enrolment that has not been confirmed. A confirmed enrolment is left
+    /// as it is.
+    pub async fn user_set_totp_secret(&self, user_id: &u64, secret: &[u8]) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "INSERT INTO UserTotp (user_id, secret) VALUES (?, ?)
+            ON DUPLICATE KEY UPDATE
+                secret = IF(confirmed_at IS NULL, VALUES(secret), secret),
+                last_used_step = IF(confirmed_at IS NULL, NULL, last_used_step)",
+            user_id,
+            secret)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Enable TOTP for `user_id`, recording that the code for `step` was used
+    /// to confirm it. Replaces any recovery codes with `recovery_code_hashes`.
+    pub async fn user_confirm_totp(&self, user_id: &u64, step: &u64, recovery_code_hashes: &[String]) -> DBResult<()> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        let qr = sqlx::query!(
+            "UPDATE UserTotp
+            SET confirmed_at = NOW(), last_used_step = ?
+            WHERE user_id = ?
+            AND confirmed_at IS NULL",
+            step,
+            user_id)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => {},
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        }
+
+        sqlx::query!("DELETE FROM UserRecoveryCode WHERE user_id = ?", user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for code_hash in recovery_code_hashes {
+            sqlx::query!(
+                "INSERT INTO UserRecoveryCode (user_id, code_hash)
+                VALUES (?, ?)",
+                user_id,
+                code_hash)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record that the code for `step` was used by `user_id`. Fails with
+    /// `NoResult` if a code for the same or a later step was already used,
+    /// so that codes cannot be replayed.
+    pub async fn user_use_totp_step(&self, user_id: &u64, step: &u64) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE UserTotp
+            SET last_used_step = ?
+            WHERE user_id = ?
+            AND (last_used_step IS NULL OR last_used_step < ?)",
+            step,
+            user_id,
+            step)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Turn off TOTP for `user_id`, removing their secret and recovery codes.
+    pub async fn user_disable_totp(&self, user_id: &u64) -> DBResult<()> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        sqlx::query!("DELETE FROM UserRecoveryCode WHERE user_id = ?", user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let qr = sqlx::query!("DELETE FROM UserTotp WHERE user_id = ?", user_id)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => {
+                tx.commit().await?;
+                Ok(())
+            },
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve the unused recovery codes of `user_id`, as argon2 hashes.
+    pub async fn user_get_recovery_codes(&self, user_id: &u64) -> DBResult<Vec<DBRecoveryCode>> {
+        let qr = sqlx::query_as!(
+            DBRecoveryCode,
+            "SELECT id, code_hash
+            FROM UserRecoveryCode
+            WHERE user_id = ?",
+            user_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        Ok(qr?)
+    }
+
+    /// Use up the recovery code specified by `code_id`. Fails with `NoResult`
+    /// if it was already used.
+    pub async fn user_use_recovery_code(&self, code_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!("DELETE FROM UserRecoveryCode WHERE id = ?", code_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn user_update_password_hash(&self, user_id: &u64, password_hash: String) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE User
+            SET password_hash = ?
+            WHERE id = ?",
+            password_hash,
+            user_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record that the user specified by `user_id` is active now. The last
+    /// seen time is only updated once it is a minute old, to limit writes.
+    pub async fn user_record_activity(&self, user_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE User
+            SET last_seen_at = NOW()
+            WHERE id = ?
+            AND (last_seen_at IS NULL OR last_seen_at < NOW() - INTERVAL 1 MINUTE)",
+            user_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Get who can see when the user specified by `user_id` was last seen.
+    pub async fn user_get_last_seen_visibility(&self, user_id: &u64) -> DBResult<DBLastSeenVisibility> {
+        let qr = sqlx::query!(
+            "SELECT last_seen_visibility AS 'last_seen_visibility: DBLastSeenVisibility'
+            FROM User
+            WHERE id = ?",
+            user_id)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        Ok(qr?.last_seen_visibility)
+    }
+
+    /// Set who can see when the user specified by `user_id` was last seen.
+    pub async fn user_set_last_seen_visibility(&self, user_id: &u64, visibility: DBLastSeenVisibility) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE User
+            SET last_seen_visibility = ?
+            WHERE id = ?",
+            visibility,
+            user_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /*  Chat room management  */
+
+    /// Get a list of chat rooms that the user specified by `user_id` are
+    /// members of. Direct rooms are named after their other member.
+    /// 
+    /// Each room includes the number of messages from other members that the
+    /// user has not read. Thread replies are not counted, as rooms are read
+    /// up to their latest message outside of threads.
+    pub async fn chat_room_list_for_user(&self, user_id: &u64) -> DBResult<Vec<ChatRoom>> {
+        let qr = sqlx::query_as!(
+            DBMemberRoom,
+            "SELECT r.id, r.kind AS 'kind: DBRoomKind', r.visibility AS 'visibility: DBRoomVisibility',
+                IF(r.kind = 'DIRECT', COALESCE((
+                    SELECT u.username
+                    FROM RoomMember m
+                    INNER JOIN User u ON u.id = m.user_id
+                    WHERE m.room_id = r.id
+                    AND m.user_id != me.user_id
+                    LIMIT 1
+                ), r.name), r.name) AS 'name!',
+                (
+                    SELECT COUNT(*)
+                    FROM Message msg
+                    WHERE msg.room_id = r.id
+                    AND msg.sender_id != me.user_id
+                    AND msg.deleted_at IS NULL
+                    AND msg.thread_root_id IS NULL
+                    AND msg.id > COALESCE(me.last_read_message_id, 0)
+                ) AS 'unread_count!'
+            FROM Room r
+            INNER JOIN RoomMember me ON me.room_id = r.id
+            WHERE me.user_id = ?;",
+            user_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(rooms) => Ok(rooms.into_iter().map(Into::into).collect()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve the chat room specified by `room_id`.
+    pub async fn chat_room_get(&self, room_id: &u64) -> DBResult<ChatRoom> {
+        let qr = sqlx::query_as!(
+            DBRoom,
+            "SELECT id, name, kind AS 'kind: DBRoomKind', visibility AS 'visibility: DBRoomVisibility'
+            FROM Room
+            WHERE id = ?",
+            room_id)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        Ok(qr?.into())
+    }
+
+    /// Get the id of the direct room between the users specified by `user_id`
+    /// and `other_id`.
+    pub async fn chat_room_get_direct(&self, user_id: &u64, other_id: &u64) -> DBResult<u64> {
+        let qr = sqlx::query!(
+            "SELECT room_id
+            FROM DirectRoom
+            WHERE user_id = ?
+            AND other_user_id = ?",
+            user_id.min(other_id),
+            user_id.max(other_id))
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        Ok(qr?.room_id)
+    }
+
+    /// Create the direct room between the users specified by `user_id` and
+    /// `other_id`, with both users as members. Returns the room's `id` on
+    /// success, or `KeyAlreadyExists` if the users already have a direct room.
+    pub async fn chat_room_create_direct(&self, user_id: &u64, other_id: &u64) -> DBResult<u64> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        let qr = sqlx::query!(
+            "INSERT INTO Room (name, kind) VALUES ('', ?);",
+            DBRoomKind::Direct)
+            .execute(&mut *tx)
+            .await;
+
+        let room_id = match qr {
+            Ok(r) if r.rows_affected() == 1 => r.last_insert_id(),
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        };
+
+        let qr = sqlx::query!(
+            "INSERT INTO DirectRoom (user_id, other_user_id, room_id) VALUES (?, ?, ?);",
+            user_id.min(other_id),
+            user_id.max(other_id),
+            room_id)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(_) => {},
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => return Err(DatabaseServiceError::KeyAlreadyExists),
+            Err(e) => return Err(e.into()),
+        }
+
+        let qr = sqlx::query!(
+            "INSERT INTO RoomMember (room_id, user_id, role) VALUES (?, ?, ?), (?, ?, ?);",
+            room_id,
+            user_id,
+            DBRoomRole::Member,
+            room_id,
+            other_id,
+            DBRoomRole::Member)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 2 => {
+                tx.commit().await?;
+                Ok(room_id)
+            },
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Create a new chat room with the specified `room_name` returning the
+    /// rooms `id` on success.
+    pub async fn chat_room_create(&self, room_name: &str) -> DBResult<u64> {
+        let qr = sqlx::query!(
+            "INSERT INTO Room (Name) VALUES (?);",
+            room_name)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(r.last_insert_id()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn chat_room_change_name(&self, room_id: &u64, name: &str) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE Room
+            SET name = ?
+            WHERE id = ?",
+            name,
+            room_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() > 0 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Set the visibility of the chat room specified by `room_id`.
+    pub async fn chat_room_set_visibility(&self, room_id: &u64, visibility: DBRoomVisibility) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE Room
+            SET visibility = ?
+            WHERE id = ?",
+            visibility,
+            room_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve up to `limit` public chat rooms with ids greater than
+    /// `after_id`, in id order. Only rooms with `search_term` in their name
+    /// are included.
+    /// 
+    /// Whether each room has the user specified by `user_id` as a member is
+    /// included. This `user_id` is intended to be of the requesting user.
+    pub async fn chat_room_directory(&self, user_id: &u64, search_term: &str, after_id: &u64, limit: &u64) -> DBResult<Vec<DBRoomListing>> {
+        let qr = sqlx::query_as!(
+            DBRoomListing,
+            "SELECT r.id, r.name, r.visibility AS 'visibility: DBRoomVisibility',
+                (SELECT COUNT(*) FROM RoomMember m WHERE m.room_id = r.id) AS 'member_count!',
+                IF((SELECT COUNT(*) FROM RoomMember m WHERE m.room_id = r.id AND m.user_id = ?) > 0, true, false) AS 'is_member: _'
+            FROM Room r
+            WHERE r.visibility = 'PUBLIC'
+            AND r.id > ?
+            AND r.name LIKE ?
+            ORDER BY r.id
+            LIMIT ?",
+            user_id,
+            after_id,
+            format!("%{}%", search_term),
+            limit)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(rooms) => Ok(rooms),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Add the user specified by `user_id` to the public chat room specified
+    /// by `room_id` as a member, resolving any pending invitation they have to
+    /// the room. `NoResult` is returned if the room is not public.
+    pub async fn chat_room_join_public(&self, room_id: &u64, user_id: &u64) -> DBResult<()> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        let qr = sqlx::query!(
+            "INSERT INTO RoomMember (room_id, user_id, role)
+            SELECT id, ?, ?
+            FROM Room
+            WHERE id = ?
+            AND visibility = 'PUBLIC'",
+            user_id,
+            DBRoomRole::Member,
+            room_id)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => {},
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        }
+
+        let qr = sqlx::query!(
+            "DELETE FROM RoomInvitation
+            WHERE room_id = ?
+            AND user_id = ?",
+            room_id,
+            user_id)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(tx.commit().await?),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Add the user specified by `user_id` to the chat room specified by
+    /// `room_id` with the provided `role`.
+    pub async fn chat_room_add_user(&self, room_id: &u64, user_id: &u64, role: DBRoomRole) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "INSERT INTO RoomMember (room_id, user_id, role) VALUES (?, ?, ?);",
+            room_id,
+            user_id,
+            role)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(e)  => {
+                // debug!("{:?}", e);
+                Err(DatabaseServiceError::NoResult)
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Change the role of the user specified by `user_id` in the chat room
+    /// specified by `room_id`. `NoResult` is returned if the user is not a
+    /// member, or already has the `role`.
+    pub async fn chat_room_set_role(&self, room_id: &u64, user_id: &u64, role: DBRoomRole) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE RoomMember SET role = ? WHERE room_id = ? AND user_id = ?;",
+            role,
+            room_id,
+            user_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the user specified by `user_id` from the chat room specified
+    /// by `room_id`
+    pub async fn chat_room_remove_user(&self, room_id: &u64, user_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "DELETE FROM RoomMember
+            WHERE room_id = ?
+            AND user_id = ?",
+            room_id,
+            user_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Remove the user specified by `user_id` from the chat room specified by
+    /// `room_id`. When the owner leaves, ownership passes to the admin with
+    /// the lowest user id, or the member with the lowest user id if there are
+    /// no admins. When the last member leaves, the room is archived or deleted
+    /// according to the `policy`. `NoResult` is returned if the user is not a
+    /// member.
+    pub async fn chat_room_leave(&self, room_id: &u64, user_id: &u64, policy: EmptyRoomPolicy) -> DBResult<DBRoomLeave> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        let leaving = sqlx::query!(
+            "SELECT role AS 'role: DBRoomRole'
+            FROM RoomMember
+            WHERE room_id = ?
+            AND user_id = ?
+            FOR UPDATE",
+            room_id,
+            user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            "DELETE FROM RoomMember
+            WHERE room_id = ?
+            AND user_id = ?",
+            room_id,
+            user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let qr = sqlx::query!(
+            "SELECT user_id
+            FROM RoomMember
+            WHERE room_id = ?
+            ORDER BY role = 'ADMIN' DESC, user_id
+            LIMIT 1
+            FOR UPDATE",
+            room_id)
+            .fetch_one(&mut *tx)
+            .await;
+
+        let successor_id = match qr {
+            Ok(r) => Some(r.user_id),
+            Err(sqlx::Error::RowNotFound) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let outcome = match (successor_id, leaving.role) {
+            (Some(successor_id), DBRoomRole::Owner) => {
+                sqlx::query!(
+                    "UPDATE RoomMember SET role = ? WHERE room_id = ? AND user_id = ?;",
+                    DBRoomRole::Owner,
+                    room_id,
+                    successor_id)
+                    .execute(&mut *tx)
+                    .await?;
+                DBRoomLeave::OwnershipTransferred(successor_id)
+            },
+            (Some(_), _) => DBRoomLeave::Left,
+            (None, _) => {
+                let deleted_keys = match policy {
+                    EmptyRoomPolicy::Archive => {
+                        Self::chat_room_archive(&mut tx, room_id).await?;
+                        Vec::new()
+                    },
+                    EmptyRoomPolicy::Delete => Self::chat_room_delete(&mut tx, room_id).await?,
+                };
+                DBRoomLeave::Emptied(deleted_keys)
+            },
+        };
+
+        tx.commit().await?;
+        Ok(outcome)
+    }
+
+    /// Archive the empty chat room specified by `room_id`. The room and its
+    /// messages are kept, but its invitations and invite codes are removed and
+    /// it is made private, so that it cannot be joined again.
+    async fn chat_room_archive(tx: &mut Transaction<'_, MySql>, room_id: &u64) -> DBResult<()> {
+        sqlx::query!(
+            "UPDATE Room
+            SET archived_at = NOW(), visibility = 'PRIVATE'
+            WHERE id = ?",
+            room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM RoomInvitation WHERE room_id = ?", room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM RoomInviteCode WHERE room_id = ?", room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete the empty chat room specified by `room_id`, along with its
+    /// messages and everything referring to them. Returns the storage keys of
+    /// the room's attachments, whose contents are left for the caller to
+    /// remove.
+    async fn chat_room_delete(tx: &mut Transaction<'_, MySql>, room_id: &u64) -> DBResult<Vec<String>> {
+        let storage_keys = sqlx::query!(
+            "SELECT storage_key
+            FROM Attachment
+            WHERE room_id = ?",
+            room_id)
+            .fetch_all(&mut **tx)
+            .await?
+            .into_iter()
+            .map(|r| r.storage_key)
+            .collect();
+
+        sqlx::query!("DELETE FROM Attachment WHERE room_id = ?", room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!(
+            "DELETE mr FROM MessageReaction mr
+            INNER JOIN Message m ON m.id = mr.message_id
+            WHERE m.room_id = ?",
+            room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!(
+            "DELETE mr FROM MessageRevision mr
+            INNER JOIN Message m ON m.id = mr.message_id
+            WHERE m.room_id = ?",
+            room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!(
+            "DELETE mm FROM MessageMention mm
+            INNER JOIN Message m ON m.id = mm.message_id
+            WHERE m.room_id = ?",
+            room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!(
+            "DELETE tr FROM ThreadRead tr
+            INNER JOIN Message m ON m.id = tr.thread_root_id
+            WHERE m.room_id = ?",
+            room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM MessagePin WHERE room_id = ?", room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        // Replies refer to other messages in the room, which are deleted together
+        sqlx::query!(
+            "UPDATE Message
+            SET reply_to = NULL, thread_root_id = NULL
+            WHERE room_id = ?",
+            room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM Message WHERE room_id = ?", room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM RoomInvitation WHERE room_id = ?", room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM RoomInviteCode WHERE room_id = ?", room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM Room WHERE id = ?", room_id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(storage_keys)
+    }
+
+    /// Get when each member of the chat room specified by `room_id` was last
+    /// seen, and whether the user specified by `viewer_id` may see it. Members
+    /// that only show their last seen time to friends hide it from viewers
+    /// that are not their friends.
+    pub async fn chat_room_get_last_seen(&self, room_id: &u64, viewer_id: &u64) -> DBResult<Vec<DBLastSeen>> {
+        let qr = sqlx::query_as!(
+            DBLastSeen,
+            "SELECT u.id AS 'user_id', u.last_seen_at,
+                IF(u.id = ? OR u.last_seen_visibility = 'EVERYONE' OR (
+                    SELECT COUNT(*)
+                    FROM UserAssociation a
+                    INNER JOIN UserAssociation b ON b.user_id = a.other_user_id AND b.other_user_id = a.user_id
+                    WHERE a.user_id = u.id
+                    AND a.other_user_id = ?
+                    AND a.association = 'FRIEND'
+                    AND b.association = 'FRIEND'
+                ) > 0, true, false) AS 'last_seen_visible: _'
+            FROM User u
+            INNER JOIN RoomMember rm ON u.id = rm.user_id
+            WHERE rm.room_id = ?",
+            viewer_id,
+            viewer_id,
+            room_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(last_seen) => Ok(last_seen),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record that the user specified by `user_id` has read the messages in
+    /// the chat room specified by `room_id`, up to and including the message
+    /// specified by `message_id`. Messages are never marked as unread again.
+    pub async fn chat_room_mark_read(&self, room_id: &u64, user_id: &u64, message_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "UPDATE RoomMember
+            SET last_read_message_id = GREATEST(COALESCE(last_read_message_id, 0), ?)
+            WHERE room_id = ?
+            AND user_id = ?",
+            message_id,
+            room_id,
+            user_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record that the user specified by `user_id` has read the replies in the
+    /// thread started by `thread_root_id`, up to and including the reply
+    /// specified by `message_id`. The room's own read marker is left alone,
+    /// so that other threads are not marked as read.
+    pub async fn chat_thread_mark_read(&self, thread_root_id: &u64, user_id: &u64, message_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "INSERT INTO ThreadRead (thread_root_id, user_id, last_read_message_id)
+            VALUES (?, ?, ?)
+            ON DUPLICATE KEY UPDATE last_read_message_id = GREATEST(last_read_message_id, VALUES(last_read_message_id))",
+            thread_root_id,
+            user_id,
+            message_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve the members of the chat room specified by `room_id` that have
+    /// read up to at least the message specified by `message_id`.
+    pub async fn chat_room_get_readers(&self, room_id: &u64, message_id: &u64) -> DBResult<Vec<UserInfo>> {
+        let qr = sqlx::query_as!(
+            UserInfo,
+            "SELECT u.id, u.username
+            FROM User u
+            INNER JOIN RoomMember rm ON u.id = rm.user_id
+            WHERE rm.room_id = ?
+            AND rm.last_read_message_id >= ?",
+            room_id,
+            message_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(readers) => Ok(readers),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve the members of the chat room specified by `room_id` that have
+    /// read the thread started by `thread_root_id` up to at least the reply
+    /// specified by `message_id`.
+    pub async fn chat_thread_get_readers(&self, room_id: &u64, thread_root_id: &u64, message_id: &u64) -> DBResult<Vec<UserInfo>> {
+        let qr = sqlx::query_as!(
+            UserInfo,
+            "SELECT u.id, u.username
+            FROM User u
+            INNER JOIN RoomMember rm ON u.id = rm.user_id
+            INNER JOIN ThreadRead tr ON tr.user_id = rm.user_id
+            WHERE rm.room_id = ?
+            AND tr.thread_root_id = ?
+            AND tr.last_read_message_id >= ?",
+            room_id,
+            thread_root_id,
+            message_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(readers) => Ok(readers),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve a list of users that are members of a room specified by
+    /// `room_id`. User info includes user IDs, usernames and room roles.
+    pub async fn chat_room_get_users(&self, room_id: &u64) -> DBResult<Vec<DBRoomMember>> {
+        let qr = sqlx::query_as!(
+            DBRoomMember,
+            "SELECT u.id AS 'user_id', u.username AS 'username', rm.role AS 'role: DBRoomRole'
+            FROM User u
+            INNER JOIN RoomMember rm ON u.id = rm.user_id
+            WHERE rm.room_id = ?",
+            room_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(members) if !members.is_empty() => Ok(members),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+
+    /// Invite the user specified by `user_id` to the chat room specified by
+    /// `room_id` on behalf of `inviter_id`. Inviting an already invited user
+    /// replaces the pending invitation.
+    pub async fn chat_room_invite_user(&self, room_id: &u64, user_id: &u64, inviter_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "INSERT INTO RoomInvitation (room_id, user_id, inviter_id)
+            VALUES (?, ?, ?)
+            ON DUPLICATE KEY UPDATE inviter_id = VALUES(inviter_id), time_sent = NOW()",
+            room_id,
+            user_id,
+            inviter_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() > 0 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Get the pending chat room invitations of the user specified by
+    /// `user_id`, newest first.
+    pub async fn chat_room_list_invitations(&self, user_id: &u64) -> DBResult<Vec<DBRoomInvitation>> {
+        let qr = sqlx::query_as!(
+            DBRoomInvitation,
+            "SELECT r.id AS 'room_id', r.name AS 'room_name', r.visibility AS 'room_visibility: DBRoomVisibility',
+                u.id AS 'inviter_id', u.username AS 'inviter_username', i.time_sent
+            FROM RoomInvitation i
+            INNER JOIN Room r ON r.id = i.room_id
+            INNER JOIN User u ON u.id = i.inviter_id
+            WHERE i.user_id = ?
+            ORDER BY i.time_sent DESC",
+            user_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(invitations) => Ok(invitations),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Accept the invitation of the user specified by `user_id` to the chat
+    /// room specified by `room_id`, adding them to the room as a member.
+    /// `NoResult` is returned if there is no pending invitation.
+    pub async fn chat_room_accept_invitation(&self, room_id: &u64, user_id: &u64) -> DBResult<()> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        let qr = sqlx::query!(
+            "DELETE FROM RoomInvitation
+            WHERE room_id = ?
+            AND user_id = ?",
+            room_id,
+            user_id)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => {},
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        }
+
+        let qr = sqlx::query!(
+            "INSERT IGNORE INTO RoomMember (room_id, user_id, role) VALUES (?, ?, ?);",
+            room_id,
+            user_id,
+            DBRoomRole::Member)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(tx.commit().await?),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Decline the invitation of the user specified by `user_id` to the chat
+    /// room specified by `room_id`.
+    pub async fn chat_room_decline_invitation(&self, room_id: &u64, user_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "DELETE FROM RoomInvitation
+            WHERE room_id = ?
+            AND user_id = ?",
+            room_id,
+            user_id)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Create an invite code for the chat room specified by `room_id` on
+    /// behalf of `creator_id`. `None` for `expires_at` or `max_uses` leaves
+    /// the code without an expiry or usage limit.
+    pub async fn chat_room_create_invite_code(
+        &self,
+        code: &str,
+        room_id: &u64,
+        creator_id: &u64,
+        expires_at: &Option<DateTime<Utc>>,
+        max_uses: &Option<u64>
+    ) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "INSERT INTO RoomInviteCode (code, room_id, creator_id, expires_at, max_uses)
+            VALUES (?, ?, ?, ?, ?)",
+            code,
+            room_id,
+            creator_id,
+            expires_at,
+            max_uses)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve the invite code matching `code`, whether or not it can still
+    /// be used.
+    pub async fn chat_room_get_invite_code(&self, code: &str) -> DBResult<DBRoomInviteCode> {
+        let qr = sqlx::query_as!(
+            DBRoomInviteCode,
+            "SELECT code, room_id, time_created, expires_at, max_uses, uses
+            FROM RoomInviteCode
+            WHERE code = ?",
+            code)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        Ok(qr?)
+    }
+
+    /// Get the invite codes of the chat room specified by `room_id`, newest
+    /// first. Expired and used up codes are included.
+    pub async fn chat_room_list_invite_codes(&self, room_id: &u64) -> DBResult<Vec<DBRoomInviteCode>> {
+        let qr = sqlx::query_as!(
+            DBRoomInviteCode,
+            "SELECT code, room_id, time_created, expires_at, max_uses, uses
+            FROM RoomInviteCode
+            WHERE room_id = ?
+            ORDER BY time_created DESC",
+            room_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(codes) => Ok(codes),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Revoke the invite `code` of the chat room specified by `room_id`.
+    pub async fn chat_room_revoke_invite_code(&self, room_id: &u64, code: &str) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "DELETE FROM RoomInviteCode
+            WHERE room_id = ?
+            AND code = ?",
+            room_id,
+            code)
+            .execute(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Use the invite `code` to add the user specified by `user_id` to the
+    /// code's chat room as a member, resolving any pending invitation they
+    /// have to the room. `NoResult` is returned if the code has expired or
+    /// has no uses left.
+    pub async fn chat_room_redeem_invite_code(&self, code: &str, room_id: &u64, user_id: &u64) -> DBResult<()> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        let qr = sqlx::query!(
+            "UPDATE RoomInviteCode
+            SET uses = uses + 1
+            WHERE code = ?
+            AND room_id = ?
+            AND (expires_at IS NULL OR expires_at > NOW())
+            AND (max_uses IS NULL OR uses < max_uses)",
+            code,
+            room_id)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => {},
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        }
+
+        let qr = sqlx::query!(
+            "INSERT INTO RoomMember (room_id, user_id, role) VALUES (?, ?, ?);",
+            room_id,
+            user_id,
+            DBRoomRole::Member)
+            .execute(&mut *tx)
+            .await;
+
+        if let Err(e) = qr {
+            return Err(e.into())
+        }
+
+        let qr = sqlx::query!(
+            "DELETE FROM RoomInvitation
+            WHERE room_id = ?
+            AND user_id = ?",
+            room_id,
+            user_id)
+            .execute(&mut *tx)
+            .await;
+
+        match qr {
+            Ok(_)  => Ok(tx.commit().await?),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /*  Chat interaction */
+
+    /// Retrieve up to `limit` of the latest messages in the chat room specified
+    /// by `room_id` with ids less than `before_id`. The messages are ordered
+    /// with the oldest/earliest message first, and newest/latest message last.
+    /// 
+    /// Deleted messages are included with their body removed. Replies are not
+    /// included, see `chat_room_read_thread`.
+    pub async fn chat_room_read_messages_before(&self, room_id: &u64, before_id: &u64, limit: &u64) -> DBResult<Vec<ChatMessage>> {
+        let qr = sqlx::query_as!(
+            DBMessage,
+            "SELECT m.id, m.room_id, m.sender_id, IF(m.deleted_at IS NULL, m.body, '') AS 'body!',
+                m.time_sent, m.edited_at, m.deleted_at, m.reply_to, m.thread_root_id,
+                (SELECT COUNT(*) FROM Message r
+                    WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL) AS 'reply_count!'
+            FROM Message m
+            WHERE m.room_id = ?
+            AND m.thread_root_id IS NULL
+            AND m.id < ?
+            ORDER BY m.id DESC
+            LIMIT ?;",
+            room_id,
+            before_id,
+            limit)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(messages) => Ok(messages.into_iter().rev().map(Into::into).collect()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve up to `limit` of the earliest messages in the chat room
+    /// specified by `room_id` with ids greater than `after_id`. The messages
+    /// are ordered with the oldest/earliest message first.
+    /// 
+    /// Deleted messages are included with their body removed. Replies are not
+    /// included, see `chat_room_read_thread`.
+    pub async fn chat_room_read_messages_after(&self, room_id: &u64, after_id: &u64, limit: &u64) -> DBResult<Vec<ChatMessage>> {
+        let qr = sqlx::query_as!(
+            DBMessage,
+            "SELECT m.id, m.room_id, m.sender_id, IF(m.deleted_at IS NULL, m.body, '') AS 'body!',
+                m.time_sent, m.edited_at, m.deleted_at, m.reply_to, m.thread_root_id,
+                (SELECT COUNT(*) FROM Message r
+                    WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL) AS 'reply_count!'
+            FROM Message m
+            WHERE m.room_id = ?
+            AND m.thread_root_id IS NULL
+            AND m.id > ?
+            ORDER BY m.id
+            LIMIT ?;",
+            room_id,
+            after_id,
+            limit)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(messages) => Ok(messages.into_iter().map(Into::into).collect()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieve a single message specified by `message_id`. A deleted message
+    /// has its body removed.
+    pub async fn chat_room_get_message(&self, message_id: &u64) -> DBResult<ChatMessage> {
         let qr = sqlx::query_as!(
-            DBUser,
-            "SELECT *
-            FROM User
-            WHERE id = ?;",
-            user_id)
+            DBMessage,
+            "SELECT m.id, m.room_id, m.sender_id, IF(m.deleted_at IS NULL, m.body, '') AS 'body!',
+                m.time_sent, m.edited_at, m.deleted_at, m.reply_to, m.thread_root_id,
+                (SELECT COUNT(*) FROM Message r
+                    WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL) AS 'reply_count!'
+            FROM Message m
+            WHERE m.id = ?;",
+            message_id)
             .fetch_one(&self.conn_pool)
             .await;
 
-        Ok(qr?)
+        Ok(qr?.into())
     }
 
-    /// Create an entry in the UserToken table, mapping an auth `token` to a
-    /// `user_id` granting authorization.
-    pub async fn user_set_token(&self, user_id: &u64, token: &Uuid, user_agent: &str) -> DBResult<()> {
-        let qr = sqlx::query!(
-            "INSERT INTO UserToken (token, user_id, user_agent) VALUES (?, ?, ?)
-            ON DUPLICATE KEY UPDATE user_agent = ?;",
-            token.to_string(),
-            user_id,
-            user_agent,
-            user_agent)
-            .execute(&self.conn_pool)
+    /// Retrieve up to `limit` replies in the thread started by the message
+    /// specified by `root_id`, oldest first, with ids greater than `after_id`.
+    pub async fn chat_room_read_thread(&self, root_id: &u64, after_id: &u64, limit: &u64) -> DBResult<Vec<ChatMessage>> {
+        let qr = sqlx::query_as!(
+            DBMessage,
+            "SELECT m.id, m.room_id, m.sender_id, IF(m.deleted_at IS NULL, m.body, '') AS 'body!',
+                m.time_sent, m.edited_at, m.deleted_at, m.reply_to, m.thread_root_id,
+                (SELECT COUNT(*) FROM Message r
+                    WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL) AS 'reply_count!'
+            FROM Message m
+            WHERE m.thread_root_id = ?
+            AND m.id > ?
+            ORDER BY m.id
+            LIMIT ?;",
+            root_id,
+            after_id,
+            limit)
+            .fetch_all(&self.conn_pool)
             .await;
 
         match qr {
-            Ok(r) if r.rows_affected() > 0 => Ok(()),
-            Ok(_)  => Err(DatabaseServiceError::KeyAlreadyExists),
+            Ok(messages) => Ok(messages.into_iter().map(Into::into).collect()),
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Find the user_id associated with the provided `token` (if present).
-    pub async fn user_id_from_token(&self, token: &Uuid) -> DBResult<u64> {
-        let qr = sqlx::query!(
-            "SELECT user_id
-            FROM UserToken
-            WHERE token = ?",
-            token.to_string()
-        ).fetch_one(&self.conn_pool)
-        .await;
+    /// Search the bodies of messages in the chat rooms that `user_id` is a
+    /// member of, returning up to `limit` matches with the most relevant first.
+    /// 
+    /// Matches are optionally filtered by room, sender and when they were sent.
+    /// Deleted messages are never matched.
+    pub async fn chat_search_messages(&self, user_id: &u64, search: &MessageSearchParam, limit: &u64) -> DBResult<Vec<ChatMessage>> {
+        let qr = sqlx::query_as!(
+            DBMessage,
+            "SELECT m.id, m.room_id, m.sender_id, IF(m.deleted_at IS NULL, m.body, '') AS 'body!',
+                m.time_sent, m.edited_at, m.deleted_at, m.reply_to, m.thread_root_id,
+                (SELECT COUNT(*) FROM Message r
+                    WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL) AS 'reply_count!'
+            FROM Message m
+            WHERE MATCH(m.body) AGAINST(? IN NATURAL LANGUAGE MODE)
+            AND m.deleted_at IS NULL
+            AND m.room_id IN (SELECT room_id FROM RoomMember WHERE user_id = ?)
+            AND (? IS NULL OR m.room_id = ?)
+            AND (? IS NULL OR m.sender_id = ?)
+            AND (? IS NULL OR m.time_sent >= ?)
+            AND (? IS NULL OR m.time_sent < ?)
+            ORDER BY MATCH(m.body) AGAINST(? IN NATURAL LANGUAGE MODE) DESC, m.id DESC
+            LIMIT ?;",
+            search.q,
+            user_id,
+            search.room_id, search.room_id,
+            search.sender_id, search.sender_id,
+            search.from, search.from,
+            search.to, search.to,
+            search.q,
+            limit)
+            .fetch_all(&self.conn_pool)
+            .await;
 
         match qr {
-            Ok(r) => Ok(r.user_id),
+            Ok(messages) => Ok(messages.into_iter().map(Into::into).collect()),
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Remove the provided `token` from mapping to `user_id`, effectively
-    /// logging out the user from their current client.
-    pub async fn user_remove_token(&self, user_id: &u64, token: &Uuid) -> DBResult<()> {
-        let qr = sqlx::query!("DELETE FROM UserToken WHERE user_id = ? AND token = ?",
+    /// Record a new message for a particular chat room, returning the id of
+    /// the new message on success.
+    /// 
+    /// The Option fields of the `message` are ignored, other than `reply_to`.
+    /// A reply must also provide the `thread_root_id` of its thread.
+    /// 
+    /// `user_id` should be derived from the auth token, instead of the
+    /// `sender_id` of the ChatMessage struct.
+    /// 
+    /// The `attachments` of the message are linked to it by id. `NoResult` is
+    /// returned, and no message recorded, if any of them is not an upload by
+    /// `user_id` to the room that has yet to be sent.
+    /// 
+    /// `@username` mentions in the body of the message are recorded for the
+    /// room members they name, other than the sender.
+    pub async fn chat_room_send_message(&self, user_id: &u64, message: &ChatMessage, thread_root_id: &Option<u64>) -> DBResult<u64> {
+        if message.id.is_some() || message.time_sent.is_some() {
+            warn!("chat_room_send_message invoked with populated Option fields: {:?}", message);
+        }
+
+        let usernames = markdown::mentions(&message.body);
+        let mentioned_ids: Vec<u64> = match usernames.is_empty() {
+            true => Vec::new(),
+            false => match self.chat_room_get_users(&message.room_id).await {
+                Ok(members) => members.iter()
+                    .filter(|m| m.user_id != *user_id)
+                    .filter(|m| usernames.iter().any(|username| username.eq_ignore_ascii_case(&m.username)))
+                    .map(|m| m.user_id)
+                    .collect(),
+                Err(DatabaseServiceError::NoResult) => Vec::new(),
+                Err(e) => return Err(e),
+            },
+        };
+
+        let mut tx = self.conn_pool.begin().await?;
+
+        let qr = sqlx::query!(
+            "INSERT INTO Message (room_id, sender_id, body, reply_to, thread_root_id)
+            VALUES (?, ?, ?, ?, ?)",
+            message.room_id,
             user_id,
-            token.to_string())
-            .execute(&self.conn_pool)
+            message.body,
+            message.reply_to,
+            thread_root_id)
+            .execute(&mut *tx)
             .await;
 
-        match qr {
-            Ok(r) if r.rows_affected() > 0 => Ok(()),
-            Ok(_)  => Err(DatabaseServiceError::NoResult),
-            Err(e) => Err(e.into())
+        let message_id = match qr {
+            Ok(r) if r.rows_affected() > 0 => r.last_insert_id(),
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        };
+
+        for attachment in &message.attachments {
+            let qr = sqlx::query!(
+                "UPDATE Attachment
+                SET message_id = ?
+                WHERE id = ?
+                AND room_id = ?
+                AND uploader_id = ?
+                AND message_id IS NULL",
+                message_id,
+                attachment.id,
+                message.room_id,
+                user_id)
+                .execute(&mut *tx)
+                .await;
+
+            match qr {
+                Ok(r) if r.rows_affected() == 1 => {},
+                Ok(_)  => return Err(DatabaseServiceError::NoResult),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        for mentioned_id in &mentioned_ids {
+            sqlx::query!(
+                "INSERT INTO MessageMention (message_id, user_id)
+                VALUES (?, ?)",
+                message_id,
+                mentioned_id)
+                .execute(&mut *tx)
+                .await?;
         }
+
+        tx.commit().await?;
+        Ok(message_id)
     }
 
-    /// Retrieve information about the associated auth tokens for a provided `user_id`.
-    /// `token` is used to determine which of the returned tokens is a made by the
-    /// requesting user.
-    pub async fn user_get_associated_tokens(&self, user_id: &u64, token: &Uuid) -> DBResult<Vec<DBAuthInfo>> {
+    /// Retrieve up to `limit` messages mentioning `user_id` that they have not
+    /// read, across all of their chat rooms, newest first. Thread replies are
+    /// read through their thread rather than the room. Deleted messages and
+    /// messages in rooms the user has since left are excluded.
+    pub async fn chat_get_unread_mentions(&self, user_id: &u64, limit: &u64) -> DBResult<Vec<ChatMessage>> {
         let qr = sqlx::query_as!(
-            DBAuthInfo,
-            "SELECT user_agent, time_set, IF(token = ?, true, false) as 'is_requester: _'
-            FROM UserToken
-            WHERE user_id = ?
-            ORDER BY time_set",
-            token.to_string(),
-            user_id)
+            DBMessage,
+            "SELECT m.id, m.room_id, m.sender_id, IF(m.deleted_at IS NULL, m.body, '') AS 'body!',
+                m.time_sent, m.edited_at, m.deleted_at, m.reply_to, m.thread_root_id,
+                (SELECT COUNT(*) FROM Message r
+                    WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL) AS 'reply_count!'
+            FROM MessageMention mm
+            INNER JOIN Message m ON m.id = mm.message_id
+            INNER JOIN RoomMember rm ON rm.room_id = m.room_id AND rm.user_id = mm.user_id
+            LEFT JOIN ThreadRead tr ON tr.thread_root_id = m.thread_root_id AND tr.user_id = mm.user_id
+            WHERE mm.user_id = ?
+            AND m.deleted_at IS NULL
+            AND m.id > IF(m.thread_root_id IS NULL,
+                COALESCE(rm.last_read_message_id, 0),
+                COALESCE(tr.last_read_message_id, 0))
+            ORDER BY m.id DESC
+            LIMIT ?",
+            user_id,
+            limit)
             .fetch_all(&self.conn_pool)
             .await;
 
         match qr {
-            Ok(info) => Ok(info),
+            Ok(messages) => Ok(messages.into_iter().map(Into::into).collect()),
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Remove all tokens associated with the provided `user_id`.
-    pub async fn user_clear_tokens_by_id(&self, user_id: &u64) -> DBResult<()> {
-        let qr = sqlx::query!("DELETE FROM UserToken WHERE user_id = ?", user_id)
-            .execute(&self.conn_pool)
+    /// Replace the body of the message specified by `message_id`, keeping the
+    /// previous body as a revision of the message.
+    /// 
+    /// The mentions of the message are replaced with those in the new body,
+    /// for the room members they name, other than the sender.
+    pub async fn chat_room_edit_message(&self, message_id: &u64, body: &str) -> DBResult<()> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        let qr = sqlx::query!(
+            "INSERT INTO MessageRevision (message_id, body, time_set)
+            SELECT id, body, COALESCE(edited_at, time_sent)
+            FROM Message
+            WHERE id = ?
+            AND deleted_at IS NULL",
+            message_id)
+            .execute(&mut *tx)
             .await;
 
-        println!("user_clear_tokens_by_id: {:?}", qr);
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => {},
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        }
+
+        let qr = sqlx::query!(
+            "UPDATE Message
+            SET body = ?, edited_at = NOW()
+            WHERE id = ?",
+            body,
+            message_id)
+            .execute(&mut *tx)
+            .await;
 
         match qr {
-            Ok(_)  => Ok(()),
-            Err(e) => Err(e.into()),
+            Ok(r) if r.rows_affected() == 1 => {},
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        }
+
+        sqlx::query!("DELETE FROM MessageMention WHERE message_id = ?", message_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let usernames = markdown::mentions(body);
+        if !usernames.is_empty() {
+            let members = sqlx::query!(
+                "SELECT u.id, u.username
+                FROM Message m
+                INNER JOIN RoomMember rm ON rm.room_id = m.room_id
+                INNER JOIN User u ON u.id = rm.user_id
+                WHERE m.id = ?
+                AND u.id != m.sender_id",
+                message_id)
+                .fetch_all(&mut *tx)
+                .await?;
+
+            let mentioned_ids = members.iter()
+                .filter(|m| usernames.iter().any(|username| username.eq_ignore_ascii_case(&m.username)))
+                .map(|m| m.id);
+            for mentioned_id in mentioned_ids {
+                sqlx::query!(
+                    "INSERT INTO MessageMention (message_id, user_id)
+                    VALUES (?, ?)",
+                    message_id,
+                    mentioned_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
         }
+
+        tx.commit().await?;
+        Ok(())
     }
 
-    pub async fn user_update_password_hash(&self, user_id: &u64, password_hash: String) -> DBResult<()> {
+    /// Mark the message specified by `message_id` as deleted by `user_id`.
+    /// 
+    /// The message is kept so that message windows are unaffected, but its
+    /// body is no longer returned.
+    pub async fn chat_room_delete_message(&self, message_id: &u64, user_id: &u64) -> DBResult<()> {
         let qr = sqlx::query!(
-            "UPDATE User
-            SET password_hash = ?
-            WHERE id = ?",
-            password_hash,
-            user_id)
+            "UPDATE Message
+            SET deleted_at = NOW(), deleted_by = ?
+            WHERE id = ?
+            AND deleted_at IS NULL",
+            user_id,
+            message_id)
             .execute(&self.conn_pool)
             .await;
 
@@ -242,176 +1902,284 @@ impl DatabaseService {
         }
     }
 
-    /*  Chat room management  */
-
-    /// Get a list of chat rooms that the user specified by `user_id` are
-    /// members of.
-    pub async fn chat_room_list_for_user(&self, user_id: &u64) -> DBResult<Vec<ChatRoom>> {
+    /// Retrieve the previous bodies of the message specified by `message_id`,
+    /// oldest first.
+    pub async fn chat_room_get_message_revisions(&self, message_id: &u64) -> DBResult<Vec<ChatMessageRevision>> {
         let qr = sqlx::query_as!(
-            ChatRoom,
-            "SELECT *
-            FROM Room
-            WHERE id IN (
-                SELECT room_id
-                FROM RoomMember
-                WHERE user_id = ?
-            );",
-            user_id)
+            ChatMessageRevision,
+            "SELECT body, time_set
+            FROM MessageRevision
+            WHERE message_id = ?
+            ORDER BY id",
+            message_id)
             .fetch_all(&self.conn_pool)
             .await;
 
-        Ok(qr?)
+        match qr {
+            Ok(revisions) => Ok(revisions),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    /// Create a new chat room with the specified `room_name` returning the
-    /// rooms `id` on success.
-    pub async fn chat_room_create(&self, room_name: &str) -> DBResult<u64> {
+    /// Record a reaction of `emoji` by `user_id` to the message specified by
+    /// `message_id`. `NoResult` is returned if the reaction already exists.
+    pub async fn chat_room_add_reaction(&self, message_id: &u64, user_id: &u64, emoji: &str) -> DBResult<()> {
         let qr = sqlx::query!(
-            "INSERT INTO Room (Name) VALUES (?);",
-            room_name)
+            "INSERT IGNORE INTO MessageReaction (message_id, user_id, emoji)
+            VALUES (?, ?, ?)",
+            message_id,
+            user_id,
+            emoji)
             .execute(&self.conn_pool)
             .await;
 
         match qr {
-            Ok(r) if r.rows_affected() == 1 => Ok(r.last_insert_id()),
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
             Ok(_)  => Err(DatabaseServiceError::NoResult),
             Err(e) => Err(e.into()),
         }
     }
 
-    pub async fn chat_room_change_name(&self, room_id: &u64, name: &str) -> DBResult<()> {
+    /// Remove the reaction of `emoji` by `user_id` to the message specified by
+    /// `message_id`. `NoResult` is returned if there was no such reaction.
+    pub async fn chat_room_remove_reaction(&self, message_id: &u64, user_id: &u64, emoji: &str) -> DBResult<()> {
         let qr = sqlx::query!(
-            "UPDATE Room
-            SET name = ?
-            WHERE id = ?",
-            name,
-            room_id)
+            "DELETE FROM MessageReaction
+            WHERE message_id = ?
+            AND user_id = ?
+            AND emoji = ?",
+            message_id,
+            user_id,
+            emoji)
             .execute(&self.conn_pool)
             .await;
 
         match qr {
-            Ok(r) if r.rows_affected() > 0 => Ok(()),
+            Ok(r) if r.rows_affected() == 1 => Ok(()),
             Ok(_)  => Err(DatabaseServiceError::NoResult),
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Add the user specified by `user_id` to the chat room specified by
-    /// `room_id`.
-    pub async fn chat_room_add_user(&self, room_id: &u64, user_id: &u64) -> DBResult<()> {
-        let qr = sqlx::query!(
-            "INSERT INTO RoomMember VALUES (?, ?);",
+    /// Retrieve the reaction counts of the messages in the room specified by
+    /// `room_id` with ids from `first_id` to `last_id` inclusive. Counts for a
+    /// message are ordered by when the emoji was first used.
+    /// 
+    /// `reacted` is set on counts that include a reaction by `user_id`.
+    pub async fn chat_room_get_reactions(&self, room_id: &u64, first_id: &u64, last_id: &u64, user_id: &u64) -> DBResult<Vec<DBReactionCount>> {
+        let qr = sqlx::query_as!(
+            DBReactionCount,
+            "SELECT mr.message_id, mr.emoji, COUNT(*) AS 'count!',
+                IF(MAX(mr.user_id = ?) = 1, true, false) AS 'reacted: _'
+            FROM MessageReaction mr
+            INNER JOIN Message m ON m.id = mr.message_id
+            WHERE m.room_id = ?
+            AND mr.message_id BETWEEN ? AND ?
+            GROUP BY mr.message_id, mr.emoji
+            ORDER BY mr.message_id, MIN(mr.time_set)",
+            user_id,
             room_id,
-            user_id)
-            .execute(&self.conn_pool)
+            first_id,
+            last_id)
+            .fetch_all(&self.conn_pool)
             .await;
 
         match qr {
-            Ok(r) if r.rows_affected() == 1 => Ok(()),
-            Ok(e)  => {
-                // debug!("{:?}", e);
-                Err(DatabaseServiceError::NoResult)
-            },
+            Ok(counts) => Ok(counts),
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Remove the user specified by `user_id` from the chat room specified
-    /// by `room_id`
-    pub async fn chat_room_remove_user(&self, room_id: &u64, user_id: &u64) -> DBResult<()> {
+    /// Pin the message specified by `message_id` in the chat room specified by
+    /// `room_id` on behalf of `user_id`, returning the pin. `NoResult` is
+    /// returned if the message is already pinned.
+    pub async fn chat_room_pin_message(&self, room_id: &u64, message_id: &u64, user_id: &u64) -> DBResult<ChatPinnedMessage> {
         let qr = sqlx::query!(
-            "DELETE FROM RoomMember
-            WHERE room_id = ?
-            AND user_id = ?",
+            "INSERT IGNORE INTO MessagePin (message_id, room_id, pinned_by)
+            VALUES (?, ?, ?)",
+            message_id,
             room_id,
             user_id)
             .execute(&self.conn_pool)
             .await;
 
+        match qr {
+            Ok(r) if r.rows_affected() == 1 => {},
+            Ok(_)  => return Err(DatabaseServiceError::NoResult),
+            Err(e) => return Err(e.into()),
+        }
+
+        let qr = sqlx::query_as!(
+            DBPinnedMessage,
+            "SELECT m.id, m.room_id, m.sender_id, IF(m.deleted_at IS NULL, m.body, '') AS 'body!',
+                m.time_sent, m.edited_at, m.deleted_at, m.reply_to, m.thread_root_id,
+                (SELECT COUNT(*) FROM Message r
+                    WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL) AS 'reply_count!',
+                p.pinned_by, p.time_pinned
+            FROM MessagePin p
+            INNER JOIN Message m ON m.id = p.message_id
+            WHERE p.message_id = ?",
+            message_id)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        Ok(qr?.into())
+    }
+
+    /// Unpin the message specified by `message_id` in the chat room specified
+    /// by `room_id`. `NoResult` is returned if the message was not pinned.
+    pub async fn chat_room_unpin_message(&self, room_id: &u64, message_id: &u64) -> DBResult<()> {
+        let qr = sqlx::query!(
+            "DELETE FROM MessagePin
+            WHERE message_id = ?
+            AND room_id = ?",
+            message_id,
+            room_id)
+            .execute(&self.conn_pool)
+            .await;
+
         match qr {
             Ok(r) if r.rows_affected() == 1 => Ok(()),
             Ok(_)  => Err(DatabaseServiceError::NoResult),
-            Err(e) => Err(e.into())
+            Err(e) => Err(e.into()),
         }
     }
 
-    /// Retrieve a list of users that are members of a room specified by
-    /// `room_id`. User info includes user IDs and usernames.
-    pub async fn chat_room_get_users(&self, room_id: &u64) -> DBResult<Vec<DBRoomMember>> {
+    /// Retrieve the pinned messages of the chat room specified by `room_id`,
+    /// most recently pinned first. Deleted messages are excluded.
+    pub async fn chat_room_get_pinned_messages(&self, room_id: &u64) -> DBResult<Vec<ChatPinnedMessage>> {
         let qr = sqlx::query_as!(
-            DBRoomMember,
-            "SELECT u.id AS 'user_id', u.username AS 'username'
-            FROM User u
-            INNER JOIN RoomMember rm ON u.id = rm.user_id
-            WHERE rm.room_id = ?",
+            DBPinnedMessage,
+            "SELECT m.id, m.room_id, m.sender_id, IF(m.deleted_at IS NULL, m.body, '') AS 'body!',
+                m.time_sent, m.edited_at, m.deleted_at, m.reply_to, m.thread_root_id,
+                (SELECT COUNT(*) FROM Message r
+                    WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL) AS 'reply_count!',
+                p.pinned_by, p.time_pinned
+            FROM MessagePin p
+            INNER JOIN Message m ON m.id = p.message_id
+            WHERE p.room_id = ?
+            AND m.deleted_at IS NULL
+            ORDER BY p.time_pinned DESC, m.id DESC",
             room_id)
             .fetch_all(&self.conn_pool)
             .await;
 
         match qr {
-            Ok(members) if !members.is_empty() => Ok(members),
-            Ok(_)  => Err(DatabaseServiceError::NoResult),
+            Ok(pins) => Ok(pins.into_iter().map(Into::into).collect()),
             Err(e) => Err(e.into()),
         }
     }
 
-
-    /*  Chat interaction */
-
-    /// Retrieve a window of messages from a particular chat room specified by
-    /// `room_id`. The messages within this window are ordered with having the
-    /// oldest/earliest message first, and newest/latest message last.
-    /// 
-    /// `offset` controls how far away from the newest message the window starts.
-    /// `limit` then controls the window size (I.O.W the quantity of messages).
-    pub async fn chat_room_read_messages(&self, room_id: &u64, offset: &u64, limit: &u64) -> DBResult<Vec<ChatMessage>> {
-        let qr = sqlx::query_as!(
-            ChatMessage,
-            "SELECT *
-            FROM Message
-            WHERE room_id = ?
-            ORDER BY time_sent DESC
-            LIMIT ?
-            OFFSET ?;",
+    /// Record a file uploaded by `uploader_id` to the chat room specified by
+    /// `room_id`, returning the id of the attachment. The contents are kept in
+    /// attachment storage under `storage_key`.
+    pub async fn chat_room_create_attachment(
+        &self,
+        room_id: &u64,
+        uploader_id: &u64,
+        file_name: &str,
+        content_type: &str,
+        size: &u64,
+        storage_key: &str,
+        has_thumbnail: bool
+    ) -> DBResult<u64> {
+        let qr = sqlx::query!(
+            "INSERT INTO Attachment (room_id, uploader_id, file_name, content_type, size, storage_key, has_thumbnail)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
             room_id,
-            limit,
-            offset)
-            .fetch_all(&self.conn_pool)
+            uploader_id,
+            file_name,
+            content_type,
+            size,
+            storage_key,
+            has_thumbnail)
+            .execute(&self.conn_pool)
             .await;
-        
+
         match qr {
-            Ok(messages) => Ok(messages),
+            Ok(r) if r.rows_affected() == 1 => Ok(r.last_insert_id()),
+            Ok(_)  => Err(DatabaseServiceError::NoResult),
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Record a new message for a particular chat room.
-    /// 
-    /// The Option fields of the `message` are ignored.
-    /// 
-    /// `user_id` should be derived from the auth token, instead of the
-    /// `sender_id` of the ChatMessage struct.
-    pub async fn chat_room_send_message(&self, user_id: &u64, message: &ChatMessage) -> DBResult<()> {
-        if message.id.is_some() || message.time_sent.is_some() {
-            warn!("chat_room_send_message invoked with populated Option fields: {:?}", message);
-        }
+    /// Retrieve the attachment specified by `attachment_id`, whether or not
+    /// it has been sent with a message.
+    pub async fn chat_room_get_attachment(&self, attachment_id: &u64) -> DBResult<DBAttachment> {
+        let qr = sqlx::query_as!(
+            DBAttachment,
+            "SELECT a.id, a.room_id, a.uploader_id, a.message_id, a.file_name, a.content_type,
+                a.size, a.storage_key, a.has_thumbnail AS 'has_thumbnail: _',
+                IF(m.deleted_at IS NULL, false, true) AS 'message_deleted: _'
+            FROM Attachment a
+            LEFT JOIN Message m ON m.id = a.message_id
+            WHERE a.id = ?",
+            attachment_id)
+            .fetch_one(&self.conn_pool)
+            .await;
 
-        let qr = sqlx::query!(
-            "INSERT INTO Message (room_id, sender_id, body)
-            VALUES (?, ?, ?)",
-            message.room_id,
-            user_id,
-            message.body)
-            .execute(&self.conn_pool)
+        Ok(qr?)
+    }
+
+    /// Retrieve the attachments of the messages in the room specified by
+    /// `room_id` with ids from `first_id` to `last_id` inclusive, in the order
+    /// they were uploaded. Attachments of deleted messages are excluded.
+    pub async fn chat_room_get_attachments(&self, room_id: &u64, first_id: &u64, last_id: &u64) -> DBResult<Vec<DBAttachment>> {
+        let qr = sqlx::query_as!(
+            DBAttachment,
+            "SELECT a.id, a.room_id, a.uploader_id, a.message_id, a.file_name, a.content_type,
+                a.size, a.storage_key, a.has_thumbnail AS 'has_thumbnail: _',
+                IF(m.deleted_at IS NULL, false, true) AS 'message_deleted: _'
+            FROM Attachment a
+            INNER JOIN Message m ON m.id = a.message_id
+            WHERE a.room_id = ?
+            AND a.message_id BETWEEN ? AND ?
+            AND m.deleted_at IS NULL
+            ORDER BY a.id",
+            room_id,
+            first_id,
+            last_id)
+            .fetch_all(&self.conn_pool)
             .await;
 
         match qr {
-            Ok(r) if r.rows_affected() > 0 => Ok(()),
-            Ok(_)  => Ok(()),
+            Ok(attachments) => Ok(attachments),
             Err(e) => Err(e.into()),
         }
     }
 
+    /// Remove uploads that were not sent with a message within `max_age_secs`
+    /// of being uploaded. Returns their storage keys, whose contents are left
+    /// for the caller to remove.
+    pub async fn chat_purge_unsent_attachments(&self, max_age_secs: &u64) -> DBResult<Vec<String>> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        // Locked, so that they cannot be sent while being removed
+        let storage_keys = sqlx::query!(
+            "SELECT storage_key
+            FROM Attachment
+            WHERE message_id IS NULL
+            AND time_uploaded <= NOW() - INTERVAL ? SECOND
+            FOR UPDATE",
+            max_age_secs)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|r| r.storage_key)
+            .collect();
+
+        sqlx::query!(
+            "DELETE FROM Attachment
+            WHERE message_id IS NULL
+            AND time_uploaded <= NOW() - INTERVAL ? SECOND",
+            max_age_secs)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(storage_keys)
+    }
+
     /// Retrieve a list of users with `search_term` in their username.
     /// 
     /// Users that have blocked the user with the provided `user_id` are
@@ -523,6 +2291,31 @@ impl DatabaseService {
         }
     }
 
+    /// Get when each friend of the user specified by `user_id` was last seen.
+    /// Friends may always see each other's last seen time.
+    pub async fn user_association_get_friends_last_seen(&self, user_id: &u64) -> DBResult<Vec<DBLastSeen>> {
+        let qr = sqlx::query_as!(
+            DBLastSeen,
+            "SELECT id AS 'user_id', last_seen_at, true AS 'last_seen_visible: _'
+            FROM User
+            WHERE id IN (
+                SELECT requester.other_user_id
+                FROM UserAssociation requester
+                INNER JOIN UserAssociation requestee
+                ON requester.other_user_id = requestee.user_id
+                WHERE requester.association = 'FRIEND'
+                AND requester.user_id = ?
+            )",
+            user_id)
+            .fetch_all(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(last_seen) => Ok(last_seen),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Get a list of users that have sent `user_id` a (currently unaccepted)
     /// friend request.
     pub async fn user_association_get_friend_requesters(&self, user_id: &u64) -> DBResult<Vec<UserInfo>> {
@@ -575,6 +2368,45 @@ impl DatabaseService {
         }
     }
 
+    /// Whether `user_id` has blocked `other_id`.
+    pub async fn user_association_is_blocked(&self, user_id: &u64, other_id: &u64) -> DBResult<bool> {
+        let qr = sqlx::query!(
+            "SELECT COUNT(*) as count
+            FROM UserAssociation
+            WHERE user_id = ?
+            AND other_user_id = ?
+            AND association = 'BLOCK'",
+            user_id,
+            other_id)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) => Ok(r.count > 0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether either of `user_id` and `other_id` has blocked the other.
+    pub async fn user_association_is_blocked_either(&self, user_id: &u64, other_id: &u64) -> DBResult<bool> {
+        let qr = sqlx::query!(
+            "SELECT COUNT(*) as count
+            FROM UserAssociation
+            WHERE association = 'BLOCK'
+            AND ((user_id = ? AND other_user_id = ?) OR (user_id = ? AND other_user_id = ?))",
+            user_id,
+            other_id,
+            other_id,
+            user_id)
+            .fetch_one(&self.conn_pool)
+            .await;
+
+        match qr {
+            Ok(r) => Ok(r.count > 0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Get a list of users that have been blocked by `user_id`.
     pub async fn user_association_get_blocked(&self, user_id: &u64) -> DBResult<Vec<UserInfo>> {
         let qr = sqlx::query_as!(
@@ -1,28 +1,37 @@
-use std::str::FromStr;
+use std::{str::FromStr, time::Duration};
 
 use const_format::formatcp;
 use serde_json::json;
 
 use common::{
-    AccountPasswordChange, AccountRequest, ChatMessage, ChatRoomManageUser, ChatRoomName, LoginResponse, LoginTokenInfo, UserAssociationUpdate, UserAssociations, UserInfo
+    markdown,
+    AccountPasswordChange, AccountRequest, AuthToken, ChatAttachment, ChatDirectMessage, ChatMessage, ChatMessageEdit, ChatMessagePage, ChatMessageRevision, ChatReadMarker, ChatRoom, ChatRoomDirectoryPage, ChatRoomInvitation, ChatRoomInviteCode, ChatRoomInviteCodeRequest, ChatRoomListing, ChatRoomManageUser, ChatRoomMember, ChatRoomName, ChatRoomRoleUpdate, ChatRoomVisibility, ConnectionTicket, LoginChallenge, LoginResponse, LoginTokenInfo, LoginTotpRequest, PrivacySettings, ReactionChange, RoomEventKind, RoomKind, TotpCode, TotpEnrolment, TotpRecoveryCodes, TotpStatus, UserAssociationUpdate, UserAssociations, UserInfo, UserPresenceInfo,
+    AUTH_TOKEN_BYTES, SOCKET_RESYNC_MESSAGE
 };
 
+use actix_multipart::Multipart;
 use actix_web::{
+    delete,
     get,
     post,
     put,
     web::{
-        Data, Json, Path, Query, ServiceConfig
+        Data, Json, Path, Payload, Query, ServiceConfig
+    },
+    http::header::{
+        self, ContentDisposition, DispositionParam, DispositionType
     },
-    http::header,
     HttpRequest,
     HttpResponse,
 };
 use actix_web_httpauth::extractors::bearer::BearerAuth;
+use actix_ws::{CloseCode, CloseReason};
+use futures_util::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
 
 use argon2::{
     password_hash::{
-        rand_core::OsRng,
+        rand_core::{OsRng, RngCore},
         PasswordHash,
         PasswordHasher,
         PasswordVerifier,
@@ -33,22 +42,80 @@ use argon2::{
 use uuid::Uuid;
 
 use crate::{
+    attachment::AttachmentService,
     database::{
         DatabaseService,
         DatabaseServiceError,
     },
-    models::UserSearchParam,
+    models::{
+        DBAttachment,
+        DBRoomLeave,
+        DBRoomMember,
+        DBRoomRole,
+        DBSession,
+        EmptyRoomPolicy,
+        RoomAction,
+        DirectoryParam,
+        MessagePageParam,
+        MentionParam,
+        MessageSearchParam,
+        EventStreamParam,
+        ThreadParam,
+        TicketParam,
+        UserSearchParam
+    },
+    presence::PresenceService,
+    realtime::RealtimeService,
+    ticket::{TicketService, TICKET_LIFETIME_SECS},
+    totp,
+    typing::TypingService,
 };
 
 const MIN_USERNAME_LEN: usize = 4;
 const MAX_USERNAME_LEN: usize = 64;
 const MIN_PASSWORD_LEN: usize = 8;
 const MAX_PASSWORD_LEN: usize = 64;
+const MAX_MESSAGE_LEN: usize = 1000;
+const MAX_REACTION_LEN: usize = 32;
+const MAX_SEARCH_LEN: usize = 256;
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 100;
+const MAX_MESSAGE_ATTACHMENTS: usize = 10;
+const MAX_FILE_NAME_LEN: usize = 255;
+/// Long enough for a TOTP or recovery code, with some surrounding whitespace
+const MAX_TOTP_CODE_LEN: usize = 32;
+/// Seconds that a login challenge can be answered within
+const LOGIN_CHALLENGE_LIFETIME_SECS: u64 = 5 * 60;
+/// Wrong codes given for a login challenge before it is no longer accepted
+const MAX_LOGIN_CHALLENGE_FAILURES: u32 = 5;
+/// The multipart form field that uploads are expected in
+const ATTACHMENT_FIELD_NAME: &str = "file";
 
 const BAD_USERNAME_REASON: &str = formatcp!("Username must be between {MIN_USERNAME_LEN} and {MAX_USERNAME_LEN} in length");
 const BAD_PASSWORD_REASON: &str = formatcp!("Password must be between {MIN_PASSWORD_LEN} and {MAX_PASSWORD_LEN} in length");
 const NON_ALLOWED_CHARACTER_REASON: &str = "A field contains dis-allowed characters. Alphanumeric only";
 const BAD_TOKEN_FORMAT_REASON: &str = "Invalid bearer token format";
+const INVALID_TOKEN_REASON: &str = "Session has expired or been logged out";
+const BAD_TICKET_FORMAT_REASON: &str = "Invalid ticket format";
+const INVALID_TICKET_REASON: &str = "Ticket has expired or already been used";
+const BAD_PAGE_SIZE_REASON: &str = formatcp!("limit must be between 1 and {MAX_PAGE_SIZE}");
+const DIRECT_ROOM_REASON: &str = "Direct rooms cannot be renamed or have their members changed";
+const DIRECT_BLOCKED_REASON: &str = "Direct messages cannot be sent while either user has blocked the other";
+const BAD_REACTION_REASON: &str = formatcp!("Reaction must be a single emoji of at most {MAX_REACTION_LEN} bytes");
+const BAD_ATTACHMENTS_REASON: &str = formatcp!("A message can have at most {MAX_MESSAGE_ATTACHMENTS} attachments, which must be unsent uploads to the room by the sender");
+const UNSAFE_LINK_REASON: &str = "Links must use http, https or mailto";
+const BAD_FILE_NAME_REASON: &str = formatcp!("File name must be between 1 and {MAX_FILE_NAME_LEN} in length");
+const BAD_TOTP_CODE_REASON: &str = "Incorrect code";
+const BAD_CHALLENGE_REASON: &str = "Login challenge is invalid or has expired";
+const TOTP_NOT_ENABLED_REASON: &str = "Two-factor authentication is not enabled";
+
+const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+/// How often the session of an open chat socket or event stream is checked,
+/// so that the connection closes once the session expires or is logged out
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Marks a 401 as caused by the bearer token itself, rather than a lack of
+/// permission, so clients know to log in again
+const INVALID_TOKEN_CHALLENGE: &str = "Bearer error=\"invalid_token\"";
 
 pub fn config(config: &mut ServiceConfig) -> () {
     config.service(actix_web::web::scope("")
@@ -56,19 +123,62 @@ pub fn config(config: &mut ServiceConfig) -> () {
         // Account management
         .service(register)
         .service(login)
+        .service(login_totp)
         .service(change_password)
         .service(clear_token)
         .service(get_all_tokens)
+        .service(revoke_token)
         .service(clear_all_tokens)
+        .service(issue_ticket)
+        .service(get_totp_status)
+        .service(start_totp_enrolment)
+        .service(confirm_totp_enrolment)
+        .service(disable_totp)
+        .service(get_privacy_settings)
+        .service(set_privacy_settings)
         // Chat room management
         .service(get_room_list)
         .service(create_chat_room)
         .service(change_room_name)
         .service(get_room_member_names)
         .service(manage_room_members)
+        .service(change_member_role)
+        .service(get_room_invitations)
+        .service(accept_room_invitation)
+        .service(decline_room_invitation)
+        .service(create_invite_code)
+        .service(get_invite_codes)
+        .service(revoke_invite_code)
+        .service(join_by_invite_code)
+        .service(change_room_visibility)
+        .service(get_room_directory)
+        .service(join_public_room)
+        .service(leave_room)
+        .service(get_direct_room)
+        .service(send_direct_message)
         // Chat interaction
         .service(chat_get_messages)
+        .service(chat_get_message_window)
+        .service(chat_search_messages)
+        .service(chat_get_unread_mentions)
         .service(chat_send_message)
+        .service(chat_edit_message)
+        .service(chat_delete_message)
+        .service(chat_get_message_history)
+        .service(chat_mark_read)
+        .service(chat_get_read_receipts)
+        .service(chat_get_thread)
+        .service(chat_add_reaction)
+        .service(chat_remove_reaction)
+        .service(chat_pin_message)
+        .service(chat_unpin_message)
+        .service(chat_get_pinned_messages)
+        .service(chat_upload_attachment)
+        .service(chat_get_attachment)
+        .service(chat_get_attachment_thumbnail)
+        .service(chat_typing)
+        .service(chat_socket)
+        .service(room_event_stream)
         // User interaction
         .service(user_search_global)
         .service(user_association)
@@ -171,30 +281,66 @@ async fn login(
     };
 
     std::mem::drop(stored_hash);
-    
-    // Get user-agent header
-    let headers = req.headers();
-    let user_agent = match headers.get(header::USER_AGENT) {
-        Some(data) => data.to_str().unwrap_or("Unknown client"),
-        None => "Unknown client"
+
+    // Accounts with two-factor authentication are only issued a token once a
+    // code is given for the challenge
+    match db_service.user_get_totp(&db_user_data.id).await {
+        Ok(totp) if totp.confirmed_at.is_some() => {
+            let challenge = generate_token();
+            return match db_service.user_create_login_challenge(&db_user_data.id, &challenge, &LOGIN_CHALLENGE_LIFETIME_SECS).await {
+                Ok(expires_at) => HttpResponse::Accepted().json(LoginChallenge {
+                    challenge: challenge.to_string(),
+                    expires_at
+                }),
+                Err(_) => HttpResponse::InternalServerError().reason("4").finish(),
+            }
+        },
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {},
+        Err(_) => return HttpResponse::InternalServerError().reason("4").finish(),
+    }
+
+    issue_token(&db_service, &db_user_data.id, &req).await
+}
+
+#[post("/account/login/totp")]
+async fn login_totp(
+    db_service: Data<DatabaseService>,
+    argon2: Data<Argon2<'_>>,
+    req: HttpRequest,
+    body: Json<LoginTotpRequest>
+) -> HttpResponse {
+    // Input validation
+    let challenge = match AuthToken::from_str(&body.challenge) {
+        Ok(challenge) => challenge,
+        Err(_) => return HttpResponse::BadRequest().reason(BAD_CHALLENGE_REASON).finish(),
     };
+    if body.code.len() > MAX_TOTP_CODE_LEN {
+        return HttpResponse::BadRequest().reason(BAD_TOTP_CODE_REASON).finish()
+    }
 
-    // Generate and store token before sending back to client
-    // In the very unlikely chance a UUID V4 clash occurs, re-try
-    let mut token = Uuid::new_v4();
-    let mut token_set_result = Err(DatabaseServiceError::KeyAlreadyExists);
-    while let Err(DatabaseServiceError::KeyAlreadyExists) = token_set_result {
-        token = Uuid::new_v4();
-        token_set_result = db_service.user_set_token(&db_user_data.id, &token, user_agent).await;
+    let user_id = match db_service.user_get_login_challenge(&challenge, &MAX_LOGIN_CHALLENGE_FAILURES).await {
+        Ok(user_id) => user_id,
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Unauthorized().reason(BAD_CHALLENGE_REASON).finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    match verify_second_factor(&db_service, &argon2, &user_id, &body.code).await {
+        Ok(true) => {},
+        Ok(false) => {
+            let _ = db_service.user_fail_login_challenge(&challenge).await;
+            return HttpResponse::BadRequest().reason(BAD_TOTP_CODE_REASON).finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
     }
 
-    match token_set_result {
-        Ok(()) => HttpResponse::Ok().json(LoginResponse {
-            user_id: db_user_data.id,
-            token: token.to_string()
-        }),
-        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    // A challenge is only answered once
+    match db_service.user_remove_login_challenge(&challenge).await {
+        Ok(()) => {},
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Unauthorized().reason(BAD_CHALLENGE_REASON).finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
     }
+
+    issue_token(&db_service, &user_id, &req).await
 }
 
 #[post("/account/change-password")]
@@ -268,7 +414,7 @@ pub async fn clear_token(
     };
 
     // Checked unwrap as function called above ensures the token is valid
-    let token = Uuid::from_str(bearer.token()).unwrap();
+    let token = AuthToken::from_str(bearer.token()).unwrap();
 
     match db_service.user_remove_token(&user_id, &token).await {
         Ok(()) => HttpResponse::Ok().finish(),
@@ -289,7 +435,7 @@ pub async fn get_all_tokens(
     };
 
     // Checked unwrap, valid per above match
-    let token = Uuid::from_str(bearer.token()).unwrap();
+    let token = AuthToken::from_str(bearer.token()).unwrap();
 
     // Get user-agent header
     let headers = req.headers();
@@ -297,9 +443,10 @@ pub async fn get_all_tokens(
         Some(data) => data.to_str().unwrap_or("Unknown client"),
         None => "Unknown client"
     };
+    let ip_address = req.peer_addr().map(|addr| addr.ip().to_string());
 
-    // Update user-agent for current token
-    if let Err(_) = db_service.user_set_token(&user_id, &token, user_agent).await {
+    // Update user-agent and address for current token
+    if let Err(_) = db_service.user_set_token(&user_id, &token, user_agent, ip_address.as_deref()).await {
         return HttpResponse::InternalServerError().reason("1").finish()
     }
 
@@ -312,6 +459,27 @@ pub async fn get_all_tokens(
     }
 }
 
+#[delete("/account/tokens/{session_id}")]
+pub async fn revoke_token(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<String>
+) -> HttpResponse {
+    let session_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match db_service.user_revoke_session(&user_id, &session_id).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(DatabaseServiceError::NoResult) => HttpResponse::NotFound().reason("No such session for the user").finish(),
+        Err(_) => HttpResponse::InternalServerError().reason("1").finish(),
+    }
+}
+
 #[post("/account/clear-tokens")]
 pub async fn clear_all_tokens(
     db_service: Data<DatabaseService>,
@@ -330,6 +498,206 @@ pub async fn clear_all_tokens(
     }
 }
 
+#[post("/account/ticket")]
+pub async fn issue_ticket(
+    db_service: Data<DatabaseService>,
+    tickets: Data<TicketService>,
+    bearer: BearerAuth
+) -> HttpResponse {
+    // Identify requesting user
+    if let Err(response) = token_to_user_id(&db_service, bearer.token()).await {
+        return response
+    }
+
+    // Checked unwrap as function called above ensures the token is valid
+    let token = AuthToken::from_str(bearer.token()).unwrap();
+
+    let ticket = tickets.issue(token);
+    HttpResponse::Ok().json(ConnectionTicket {
+        ticket: ticket.to_string(),
+        expires_at: chrono::Utc::now() + chrono::Duration::seconds(TICKET_LIFETIME_SECS as i64)
+    })
+}
+
+#[get("/account/totp")]
+pub async fn get_totp_status(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth
+) -> HttpResponse {
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let enabled = match db_service.user_get_totp(&user_id).await {
+        Ok(user_totp) => user_totp.confirmed_at.is_some(),
+        Err(DatabaseServiceError::NoResult) => false,
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+    let recovery_codes_remaining = match db_service.user_get_recovery_codes(&user_id).await {
+        Ok(codes) => codes.len() as u64,
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    HttpResponse::Ok().json(TotpStatus { enabled, recovery_codes_remaining })
+}
+
+#[post("/account/totp")]
+pub async fn start_totp_enrolment(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth
+) -> HttpResponse {
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match db_service.user_get_totp(&user_id).await {
+        Ok(user_totp) if user_totp.confirmed_at.is_some() => {
+            return HttpResponse::BadRequest().reason("Two-factor authentication is already enabled").finish()
+        },
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {},
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    }
+    let username = match db_service.user_get_by_id(&user_id).await {
+        Ok(user) => user.username,
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    // Any earlier unconfirmed secret is replaced
+    let secret = totp::generate_secret();
+    match db_service.user_set_totp_secret(&user_id, &secret).await {
+        Ok(()) => HttpResponse::Ok().json(TotpEnrolment {
+            secret: totp::encode_secret(&secret),
+            otpauth_uri: totp::otpauth_uri(&secret, &username)
+        }),
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+#[post("/account/totp/confirm")]
+pub async fn confirm_totp_enrolment(
+    db_service: Data<DatabaseService>,
+    argon2: Data<Argon2<'_>>,
+    bearer: BearerAuth,
+    body: Json<TotpCode>
+) -> HttpResponse {
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let user_totp = match db_service.user_get_totp(&user_id).await {
+        Ok(user_totp) if user_totp.confirmed_at.is_none() => user_totp,
+        Ok(_) => return HttpResponse::BadRequest().reason("Two-factor authentication is already enabled").finish(),
+        Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::BadRequest().reason("Two-factor authentication setup has not been started").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    // Confirming with a code shows the secret was added to an authenticator
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let Some(step) = totp::matching_step(&user_totp.secret, body.code.trim(), now) else {
+        return HttpResponse::BadRequest().reason(BAD_TOTP_CODE_REASON).finish()
+    };
+
+    // Recovery codes are hashed like passwords, and only shown this once
+    let codes: Vec<String> = (0..totp::RECOVERY_CODE_COUNT).map(|_| totp::generate_recovery_code()).collect();
+    let mut code_hashes = Vec::with_capacity(codes.len());
+    for code in &codes {
+        let salt = SaltString::generate(&mut OsRng);
+        match argon2.hash_password(code.as_bytes(), &salt) {
+            Ok(hash) => code_hashes.push(hash.to_string()),
+            Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+        }
+    }
+
+    match db_service.user_confirm_totp(&user_id, &step, &code_hashes).await {
+        Ok(()) => HttpResponse::Ok().json(TotpRecoveryCodes { codes }),
+        Err(DatabaseServiceError::NoResult) => {
+            HttpResponse::BadRequest().reason("Two-factor authentication is already enabled").finish()
+        },
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+#[post("/account/totp/disable")]
+pub async fn disable_totp(
+    db_service: Data<DatabaseService>,
+    argon2: Data<Argon2<'_>>,
+    bearer: BearerAuth,
+    body: Json<TotpCode>
+) -> HttpResponse {
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    if body.code.len() > MAX_TOTP_CODE_LEN {
+        return HttpResponse::BadRequest().reason(BAD_TOTP_CODE_REASON).finish()
+    }
+    match db_service.user_get_totp(&user_id).await {
+        Ok(user_totp) if user_totp.confirmed_at.is_some() => {},
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::BadRequest().reason(TOTP_NOT_ENABLED_REASON).finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    }
+
+    // A stolen token alone is not enough to turn off the second factor
+    match verify_second_factor(&db_service, &argon2, &user_id, &body.code).await {
+        Ok(true) => {},
+        Ok(false) => return HttpResponse::BadRequest().reason(BAD_TOTP_CODE_REASON).finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    }
+
+    match db_service.user_disable_totp(&user_id).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(DatabaseServiceError::NoResult) => HttpResponse::BadRequest().reason(TOTP_NOT_ENABLED_REASON).finish(),
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+#[get("/account/privacy")]
+pub async fn get_privacy_settings(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth
+) -> HttpResponse {
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match db_service.user_get_last_seen_visibility(&user_id).await {
+        Ok(visibility) => HttpResponse::Ok().json(PrivacySettings { last_seen_visibility: visibility.into() }),
+        Err(_) => HttpResponse::InternalServerError().reason("1").finish(),
+    }
+}
+
+#[put("/account/privacy")]
+pub async fn set_privacy_settings(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    body: Json<PrivacySettings>
+) -> HttpResponse {
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match db_service.user_set_last_seen_visibility(&user_id, body.last_seen_visibility.into()).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().reason("1").finish(),
+    }
+}
+
 // Chat room management
 
 #[get("/chat/rooms")]
@@ -379,8 +747,8 @@ async fn create_chat_room(
         Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
     };
 
-    // Add requesting user to the chat room
-    match db_service.chat_room_add_user(&room_id, &user_id).await {
+    // Add requesting user to the chat room as its owner
+    match db_service.chat_room_add_user(&room_id, &user_id, DBRoomRole::Owner).await {
         Ok(()) => HttpResponse::Ok().finish(),
         Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
     }
@@ -389,6 +757,7 @@ async fn create_chat_room(
 #[put("/chat/{room_id}/change-name")]
 async fn change_room_name(
     db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
     bearer: BearerAuth,
     path: Path<u64>,
     body: Json<ChatRoomName>
@@ -419,19 +788,33 @@ async fn change_room_name(
         Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
     };
 
-    if let None = room_users.iter().find(|m| m.user_id == user_id) {
-        return HttpResponse::Unauthorized().reason("Not part of the room").finish()
+    let requester = match room_users.iter().find(|m| m.user_id == user_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("Not part of the room").finish(),
+    };
+
+    if let Err(response) = require_group_room(&db_service, &room_id).await {
+        return response
     }
 
-    match db_service.chat_room_change_name(&room_id, &body.room_name).await {
-        Ok(()) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    if !requester.role.permits(RoomAction::Rename) {
+        return HttpResponse::Forbidden().reason(RoomAction::Rename.forbidden_reason()).finish()
+    }
+
+    if let Err(_) = db_service.chat_room_change_name(&room_id, &body.room_name).await {
+        return HttpResponse::InternalServerError().reason("3").finish()
     }
+
+    let recipients = room_users.iter().map(|m| m.user_id).collect();
+    realtime.publish(room_id, recipients, RoomEventKind::RoomRenamed(body.into_inner()));
+
+    HttpResponse::Ok().finish()
 }
 
 #[get("/chat/{room_id}/members")]
 async fn get_room_member_names(
     db_service: Data<DatabaseService>,
+    presence: Data<PresenceService>,
     bearer: BearerAuth,
     path: Path<u64>
 ) -> HttpResponse {
@@ -454,9 +837,20 @@ async fn get_room_member_names(
         return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
     }
 
+    let last_seen = match db_service.chat_room_get_last_seen(&room_id, &user_id).await {
+        Ok(last_seen) => last_seen,
+        Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+    };
+
     let members_list = members.iter()
-        .map(|m| UserInfo { id: m.user_id, username: m.username.clone() })
-        .collect::<Vec<UserInfo>>();
+        .map(|m| {
+            let mut member: ChatRoomMember = m.into();
+            member.presence = last_seen.iter()
+                .find(|seen| seen.user_id == m.user_id)
+                .map(|seen| presence.presence(seen.user_id, seen.last_seen_at, seen.last_seen_visible.0));
+            member
+        })
+        .collect::<Vec<ChatRoomMember>>();
 
     HttpResponse::Ok().json(members_list)
 }
@@ -464,6 +858,7 @@ async fn get_room_member_names(
 #[post("/chat/{room_id}/manage-user")]
 async fn manage_room_members(
     db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
     bearer: BearerAuth,
     path: Path<u64>,
     body: Json<ChatRoomManageUser>
@@ -484,29 +879,70 @@ async fn manage_room_members(
     };
 
     // Ensure requester is in room
-    if let None = room_members.iter().find(|m| m.user_id == requester_id) {
-        return HttpResponse::Unauthorized().reason("Must be part of the room being managed").finish()
+    let requester = match room_members.iter().find(|m| m.user_id == requester_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("Must be part of the room being managed").finish(),
+    };
+
+    let target = room_members.iter().find(|m| m.user_id == body.user_id);
+    let user_present = target.is_some();
+
+    if let Err(response) = require_group_room(&db_service, &room_id).await {
+        return response
     }
 
-    let user_present = room_members.iter()
-        .find(|m| m.user_id == body.user_id)
-        .is_some();
+    // Check the requester's role permits the action. Members other than the
+    // owner may always remove themselves.
+    let action = match (&body.action, target) {
+        (common::ChatRoomManageUserAction::AddUser, _) => Some(RoomAction::AddMember),
+        (common::ChatRoomManageUserAction::RemoveUser, Some(target)) if target.user_id == requester_id => {
+            (target.role == DBRoomRole::Owner).then_some(RoomAction::RemoveMember(DBRoomRole::Owner))
+        },
+        (common::ChatRoomManageUserAction::RemoveUser, Some(target)) => Some(RoomAction::RemoveMember(target.role)),
+        (common::ChatRoomManageUserAction::RemoveUser, None) => None,
+    };
+    if let Some(action) = action {
+        if !requester.role.permits(action) {
+            return HttpResponse::Forbidden().reason(action.forbidden_reason()).finish()
+        }
+    }
 
     match body.action {
         common::ChatRoomManageUserAction::AddUser if user_present => {
             HttpResponse::Ok().finish()
         },
         common::ChatRoomManageUserAction::AddUser => {
-            match db_service.chat_room_add_user(&room_id, &body.user_id).await {
+            match db_service.user_get_by_id(&body.user_id).await {
+                Ok(_) => {},
+                Err(DatabaseServiceError::NoResult) => return HttpResponse::BadRequest().reason("User being invited does not exist").finish(),
+                Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+            }
+
+            // Users join by accepting an invitation, which those that have
+            // blocked the requester cannot be sent
+            match db_service.user_association_is_blocked(&body.user_id, &requester_id).await {
+                Ok(false) => {},
+                Ok(true) => return HttpResponse::Forbidden().reason("User being invited has blocked the requester").finish(),
+                Err(_) => return HttpResponse::InternalServerError().reason("5").finish(),
+            }
+
+            match db_service.chat_room_invite_user(&room_id, &body.user_id, &requester_id).await {
                 Ok(()) => HttpResponse::Ok().finish(),
-                Err(_) => HttpResponse::InternalServerError().reason("3").finish()
+                Err(_) => HttpResponse::InternalServerError().reason("6").finish(),
             }
         }
         common::ChatRoomManageUserAction::RemoveUser if user_present => {
-            match db_service.chat_room_remove_user(&room_id, &body.user_id).await {
-                Ok(()) => HttpResponse::Ok().finish(),
-                Err(_) => HttpResponse::InternalServerError().reason("4").finish(),
+            if let Err(_) = db_service.chat_room_remove_user(&room_id, &body.user_id).await {
+                return HttpResponse::InternalServerError().reason("4").finish()
+            }
+
+            // Notify remaining members and the removed user
+            let recipients = room_members.iter().map(|m| m.user_id).collect();
+            if let Some(removed) = room_members.iter().find(|m| m.user_id == body.user_id) {
+                let removed = UserInfo { id: removed.user_id, username: removed.username.clone() };
+                realtime.publish(room_id, recipients, RoomEventKind::MemberRemoved(removed));
             }
+            HttpResponse::Ok().finish()
         },
         common::ChatRoomManageUserAction::RemoveUser => {
             HttpResponse::BadRequest().reason("User being removed is not part of the room").finish()
@@ -514,92 +950,1805 @@ async fn manage_room_members(
     }
 }
 
-
-// Chat interaction
-#[get("/chat/{room_id}/{offset}/{limit}")]
-async fn chat_get_messages(
+#[put("/chat/{room_id}/members/{user_id}/role")]
+async fn change_member_role(
     db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
     bearer: BearerAuth,
-    path: Path<(u64, u64, u64)>
+    path: Path<(u64, u64)>,
+    body: Json<ChatRoomRoleUpdate>
 ) -> HttpResponse {
-    let (room_id, offset, limit) = path.into_inner();
+    let (room_id, user_id) = path.into_inner();
 
-    if limit == 0 {
-        return HttpResponse::BadRequest().reason("limit parameter was 0").finish()
+    // Ownership can only be transferred by the owner leaving the room
+    if body.role == common::RoomRole::Owner {
+        return HttpResponse::BadRequest().reason("role cannot be Owner").finish()
     }
 
     // Identify requesting user
-    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+    let requester_id = match token_to_user_id(&db_service, bearer.token()).await {
         Ok(id) => id,
         Err(response) => return response,
     };
 
-    // Check if requestng user is in the room
-    let members = match db_service.chat_room_get_users(&room_id).await {
+    let room_members = match db_service.chat_room_get_users(&room_id).await {
         Ok(members) => members,
         Err(DatabaseServiceError::NoResult) => Vec::new(),
         Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
     };
 
-    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
-        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    let requester = match room_members.iter().find(|m| m.user_id == requester_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+
+    if let Err(response) = require_group_room(&db_service, &room_id).await {
+        return response
     }
 
-    // Retrieve messages to be returned
-    match db_service.chat_room_read_messages(&room_id, &offset, &limit).await {
-        Ok(msg_window) => HttpResponse::Ok().json(msg_window),
-        Err(_) => HttpResponse::InternalServerError().reason("2").finish(),
+    if !requester.role.permits(RoomAction::ChangeRole) {
+        return HttpResponse::Forbidden().reason(RoomAction::ChangeRole.forbidden_reason()).finish()
+    }
+
+    let target = match room_members.iter().find(|m| m.user_id == user_id) {
+        Some(target) => target,
+        None => return HttpResponse::BadRequest().reason("User is not part of the room").finish(),
+    };
+
+    if target.role == DBRoomRole::Owner {
+        return HttpResponse::BadRequest().reason("The owner's role cannot be changed").finish()
+    }
+
+    let role = DBRoomRole::from(body.role);
+    match db_service.chat_room_set_role(&room_id, &user_id, role).await {
+        Ok(()) => {},
+        // Already has the role
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Ok().finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
     }
+
+    let recipients = room_members.iter().map(|m| m.user_id).collect();
+    let member = ChatRoomMember { id: target.user_id, username: target.username.clone(), role: body.role, presence: None };
+    realtime.publish(room_id, recipients, RoomEventKind::MemberRoleChanged(member));
+
+    HttpResponse::Ok().finish()
 }
 
-#[post("/chat")]
-async fn chat_send_message(
+#[get("/chat/invitations")]
+async fn get_room_invitations(
     db_service: Data<DatabaseService>,
-    bearer: BearerAuth,
-    body: Json<ChatMessage>
+    bearer: BearerAuth
 ) -> HttpResponse {
-    // Disallow optional fields being populated
-    if body.id.is_some() || body.sender_id.is_some() || body.time_sent.is_some() {
-        return HttpResponse::BadRequest().reason("id, sender_id, or time_sent fields have values").finish()
-    }
-
     // Identify requesting user
     let user_id = match token_to_user_id(&db_service, bearer.token()).await {
         Ok(id) => id,
         Err(response) => return response,
     };
 
-    // Check if requestng user is in the room
-    let members = match db_service.chat_room_get_users(&body.room_id).await {
-        Ok(members) => members,
-        Err(DatabaseServiceError::NoResult) => Vec::new(),
-        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
-    };
-
-    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
-        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
-    }
-
-    // Record new message
-    match db_service.chat_room_send_message(&user_id, &body).await {
-        Ok(()) => HttpResponse::Ok().finish(),
+    match db_service.chat_room_list_invitations(&user_id).await {
+        Ok(invitations) => {
+            let invitations: Vec<ChatRoomInvitation> = invitations.into_iter()
+                .map(Into::into)
+                .collect();
+            HttpResponse::Ok().json(invitations)
+        },
         Err(_) => HttpResponse::InternalServerError().reason("2").finish(),
     }
 }
 
-// User interaction
-
-#[get("/users")]
-async fn user_search_global(
+#[post("/chat/invitations/{room_id}/accept")]
+async fn accept_room_invitation(
     db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
     bearer: BearerAuth,
-    query: Query<UserSearchParam>
+    path: Path<u64>
 ) -> HttpResponse {
-    // Search must contain some text
-    if query.username.is_empty() {
-        return HttpResponse::BadRequest().reason("username query cannot be empty").finish()
-    }
-    
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match db_service.chat_room_accept_invitation(&room_id, &user_id).await {
+        Ok(()) => {},
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::BadRequest().reason("No pending invitation to the room").finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    }
+
+    // Notify the room members, including the user that joined
+    match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => {
+            let recipients = members.iter().map(|m| m.user_id).collect();
+            if let Some(joined) = members.iter().find(|m| m.user_id == user_id) {
+                realtime.publish(room_id, recipients, RoomEventKind::MemberAdded(joined.into()));
+            }
+        },
+        Err(_) => log::warn!("Failed to retrieve members of room {} for delivery", room_id),
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[post("/chat/invitations/{room_id}/decline")]
+async fn decline_room_invitation(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<u64>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match db_service.chat_room_decline_invitation(&room_id, &user_id).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(DatabaseServiceError::NoResult) => HttpResponse::BadRequest().reason("No pending invitation to the room").finish(),
+        Err(_) => HttpResponse::InternalServerError().reason("2").finish(),
+    }
+}
+
+#[put("/chat/{room_id}/visibility")]
+async fn change_room_visibility(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<u64>,
+    body: Json<ChatRoomVisibility>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let room_members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let requester = match room_members.iter().find(|m| m.user_id == user_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+
+    if let Err(response) = require_group_room(&db_service, &room_id).await {
+        return response
+    }
+
+    if !requester.role.permits(RoomAction::ChangeVisibility) {
+        return HttpResponse::Forbidden().reason(RoomAction::ChangeVisibility.forbidden_reason()).finish()
+    }
+
+    match db_service.chat_room_set_visibility(&room_id, body.visibility.into()).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().reason("2").finish(),
+    }
+}
+
+#[get("/chat/directory")]
+async fn get_room_directory(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    query: Query<DirectoryParam>
+) -> HttpResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return HttpResponse::BadRequest().reason(BAD_PAGE_SIZE_REASON).finish()
+    }
+    let search_term = query.q.as_deref().unwrap_or("").trim();
+    if search_term.len() > MAX_SEARCH_LEN {
+        return HttpResponse::BadRequest().reason("q value too long").finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let after_id = query.after_id.unwrap_or(0);
+    let rooms: Vec<ChatRoomListing> = match db_service.chat_room_directory(&user_id, search_term, &after_id, &limit).await {
+        Ok(rooms) => rooms.into_iter().map(Into::into).collect(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    // A page short of the limit has reached the end
+    let next_cursor = rooms.last()
+        .map(|listing| listing.room.id)
+        .filter(|_| rooms.len() as u64 == limit);
+    HttpResponse::Ok().json(ChatRoomDirectoryPage { rooms, next_cursor })
+}
+
+#[post("/chat/{room_id}/join")]
+async fn join_public_room(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<u64>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let room_members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !room_members.iter().any(|m| m.user_id == user_id) {
+        match db_service.chat_room_join_public(&room_id, &user_id).await {
+            Ok(()) => {},
+            Err(DatabaseServiceError::NoResult) => return HttpResponse::NotFound().reason("Room does not exist or is not public").finish(),
+            Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+        }
+
+        // Notify existing members and the user that joined
+        match db_service.user_get_by_id(&user_id).await {
+            Ok(user) => {
+                let recipients = room_members.iter()
+                    .map(|m| m.user_id)
+                    .chain(std::iter::once(user.id))
+                    .collect();
+                let joined = ChatRoomMember { id: user.id, username: user.username, role: common::RoomRole::Member, presence: None };
+                realtime.publish(room_id, recipients, RoomEventKind::MemberAdded(joined));
+            },
+            Err(_) => log::warn!("Failed to retrieve joined user {} for delivery", user_id),
+        }
+    }
+
+    match db_service.chat_room_get(&room_id).await {
+        Ok(room) => HttpResponse::Ok().json(room),
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+#[post("/chat/{room_id}/leave")]
+async fn leave_room(
+    db_service: Data<DatabaseService>,
+    attachments: Data<AttachmentService>,
+    realtime: Data<RealtimeService>,
+    empty_room_policy: Data<EmptyRoomPolicy>,
+    bearer: BearerAuth,
+    path: Path<u64>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let room_members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let leaving = match room_members.iter().find(|m| m.user_id == user_id) {
+        Some(leaving) => leaving,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+
+    if let Err(response) = require_group_room(&db_service, &room_id).await {
+        return response
+    }
+
+    let outcome = match db_service.chat_room_leave(&room_id, &user_id, **empty_room_policy).await {
+        Ok(outcome) => outcome,
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    // Notify remaining members and the user that left
+    let recipients = room_members.iter().map(|m| m.user_id).collect();
+    let left = UserInfo { id: leaving.user_id, username: leaving.username.clone() };
+    realtime.publish(room_id, recipients, RoomEventKind::MemberRemoved(left));
+
+    if let DBRoomLeave::Emptied(storage_keys) = &outcome {
+        remove_stored_attachments(&attachments, storage_keys.clone()).await;
+    }
+
+    if let DBRoomLeave::OwnershipTransferred(owner_id) = outcome {
+        if let Some(owner) = room_members.iter().find(|m| m.user_id == owner_id) {
+            let recipients = room_members.iter()
+                .map(|m| m.user_id)
+                .filter(|id| *id != user_id)
+                .collect();
+            let promoted = ChatRoomMember { id: owner.user_id, username: owner.username.clone(), role: common::RoomRole::Owner, presence: None };
+            realtime.publish(room_id, recipients, RoomEventKind::MemberRoleChanged(promoted));
+        }
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[get("/chat/direct/{user_id}")]
+async fn get_direct_room(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<u64>
+) -> HttpResponse {
+    let other_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let room_id = match db_service.chat_room_get_direct(&user_id, &other_id).await {
+        Ok(room_id) => room_id,
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::NotFound().reason("No direct room with the user").finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    match direct_room(&db_service, &room_id, &other_id).await {
+        Ok(room) => HttpResponse::Ok().json(room),
+        Err(response) => response,
+    }
+}
+
+#[post("/chat/direct/{user_id}")]
+async fn send_direct_message(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<u64>,
+    body: Json<ChatDirectMessage>
+) -> HttpResponse {
+    let other_id = path.into_inner();
+
+    // Input validation
+    if body.body.is_empty() {
+        return HttpResponse::BadRequest().reason("Empty body value").finish()
+    }
+    if body.body.chars().count() > MAX_MESSAGE_LEN {
+        return HttpResponse::BadRequest().reason("body value longer than 1000 chars").finish()
+    }
+    if markdown::validate(&body.body).is_err() {
+        return HttpResponse::BadRequest().reason(UNSAFE_LINK_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    if user_id == other_id {
+        return HttpResponse::BadRequest().reason("Cannot send a direct message to yourself").finish()
+    }
+
+    // Direct rooms are only between friends that have not blocked each other
+    match db_service.user_association_get_friends(&user_id).await {
+        Ok(friends) if friends.iter().any(|friend| friend.id == other_id) => {},
+        Ok(_) => return HttpResponse::Forbidden().reason("Direct messages can only be sent to friends").finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    }
+    match db_service.user_association_is_blocked_either(&user_id, &other_id).await {
+        Ok(false) => {},
+        Ok(true) => return HttpResponse::Forbidden().reason(DIRECT_BLOCKED_REASON).finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    }
+
+    // The direct room is created by the first message between the users
+    let room_id = match db_service.chat_room_get_direct(&user_id, &other_id).await {
+        Ok(room_id) => room_id,
+        Err(DatabaseServiceError::NoResult) => match db_service.chat_room_create_direct(&user_id, &other_id).await {
+            Ok(room_id) => room_id,
+            // Created by the other user in the meantime
+            Err(DatabaseServiceError::KeyAlreadyExists) => match db_service.chat_room_get_direct(&user_id, &other_id).await {
+                Ok(room_id) => room_id,
+                Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+            },
+            Err(_) => return HttpResponse::InternalServerError().reason("4").finish(),
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("5").finish(),
+    };
+
+    let message = ChatMessage {
+        id: None,
+        room_id,
+        sender_id: None,
+        body: body.into_inner().body,
+        time_sent: None,
+        edited_at: None,
+        deleted_at: None,
+        reactions: Vec::new(),
+        reply_to: None,
+        thread_root_id: None,
+        reply_count: 0,
+        attachments: Vec::new()
+    };
+    let message_id = match db_service.chat_room_send_message(&user_id, &message, &None).await {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().reason("6").finish(),
+    };
+
+    // Push the stored message to both users
+    match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) => realtime.publish(room_id, vec![user_id, other_id], RoomEventKind::MessageSent(message)),
+        Err(_) => log::warn!("Failed to retrieve message {} for delivery", message_id),
+    }
+
+    match direct_room(&db_service, &room_id, &other_id).await {
+        Ok(room) => HttpResponse::Ok().json(room),
+        Err(response) => response,
+    }
+}
+
+#[post("/chat/{room_id}/invite-codes")]
+async fn create_invite_code(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<u64>,
+    body: Json<ChatRoomInviteCodeRequest>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Input validation
+    if body.max_uses == Some(0) {
+        return HttpResponse::BadRequest().reason("max_uses must be at least 1").finish()
+    }
+    if body.expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now()) {
+        return HttpResponse::BadRequest().reason("expires_at must be in the future").finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let room_members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let requester = match room_members.iter().find(|m| m.user_id == user_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+    if let Err(response) = require_group_room(&db_service, &room_id).await {
+        return response
+    }
+
+    if !requester.role.permits(RoomAction::ManageInviteCodes) {
+        return HttpResponse::Forbidden().reason(RoomAction::ManageInviteCodes.forbidden_reason()).finish()
+    }
+
+    let code = Uuid::new_v4().simple().to_string();
+    if let Err(_) = db_service.chat_room_create_invite_code(&code, &room_id, &user_id, &body.expires_at, &body.max_uses).await {
+        return HttpResponse::InternalServerError().reason("2").finish()
+    }
+
+    match db_service.chat_room_get_invite_code(&code).await {
+        Ok(invite_code) => HttpResponse::Ok().json(Into::<ChatRoomInviteCode>::into(invite_code)),
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+#[get("/chat/{room_id}/invite-codes")]
+async fn get_invite_codes(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<u64>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let room_members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let requester = match room_members.iter().find(|m| m.user_id == user_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+    if !requester.role.permits(RoomAction::ManageInviteCodes) {
+        return HttpResponse::Forbidden().reason(RoomAction::ManageInviteCodes.forbidden_reason()).finish()
+    }
+
+    match db_service.chat_room_list_invite_codes(&room_id).await {
+        Ok(codes) => {
+            let codes: Vec<ChatRoomInviteCode> = codes.into_iter()
+                .map(Into::into)
+                .collect();
+            HttpResponse::Ok().json(codes)
+        },
+        Err(_) => HttpResponse::InternalServerError().reason("2").finish(),
+    }
+}
+
+#[delete("/chat/{room_id}/invite-codes/{code}")]
+async fn revoke_invite_code(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<(u64, String)>
+) -> HttpResponse {
+    let (room_id, code) = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let room_members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let requester = match room_members.iter().find(|m| m.user_id == user_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+    if !requester.role.permits(RoomAction::ManageInviteCodes) {
+        return HttpResponse::Forbidden().reason(RoomAction::ManageInviteCodes.forbidden_reason()).finish()
+    }
+
+    match db_service.chat_room_revoke_invite_code(&room_id, &code).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(DatabaseServiceError::NoResult) => HttpResponse::BadRequest().reason("Invite code does not exist").finish(),
+        Err(_) => HttpResponse::InternalServerError().reason("2").finish(),
+    }
+}
+
+#[post("/chat/join/{code}")]
+async fn join_by_invite_code(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<String>
+) -> HttpResponse {
+    let code = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let room_id = match db_service.chat_room_get_invite_code(&code).await {
+        Ok(invite_code) => invite_code.room_id,
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::BadRequest().reason("Invite code does not exist").finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let room = match db_service.chat_room_get(&room_id).await {
+        Ok(room) => room,
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    let room_members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+    };
+
+    // Existing members do not use up the code
+    if room_members.iter().any(|m| m.user_id == user_id) {
+        return HttpResponse::Ok().json(room)
+    }
+
+    match db_service.chat_room_redeem_invite_code(&code, &room_id, &user_id).await {
+        Ok(()) => {},
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::BadRequest().reason("Invite code has expired or has no uses left").finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("4").finish(),
+    }
+
+    // Notify existing members and the user that joined
+    match db_service.user_get_by_id(&user_id).await {
+        Ok(user) => {
+            let recipients = room_members.iter()
+                .map(|m| m.user_id)
+                .chain(std::iter::once(user.id))
+                .collect();
+            let joined = ChatRoomMember { id: user.id, username: user.username, role: common::RoomRole::Member, presence: None };
+            realtime.publish(room_id, recipients, RoomEventKind::MemberAdded(joined));
+        },
+        Err(_) => log::warn!("Failed to retrieve joined user {} for delivery", user_id),
+    }
+
+    HttpResponse::Ok().json(room)
+}
+
+
+// Chat interaction
+#[get("/chat/{room_id}/messages")]
+async fn chat_get_messages(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<u64>,
+    query: Query<MessagePageParam>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return HttpResponse::BadRequest().reason(BAD_PAGE_SIZE_REASON).finish()
+    }
+    if query.before_id.is_some() && query.after_id.is_some() {
+        return HttpResponse::BadRequest().reason("Only one of before_id and after_id may be provided").finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    // Retrieve messages to be returned. Without a cursor, the latest messages
+    // are returned and paged backwards from.
+    let (messages, next_cursor) = match query.after_id {
+        Some(after_id) => {
+            let messages = db_service.chat_room_read_messages_after(&room_id, &after_id, &limit).await;
+            let next_cursor = messages.as_ref().ok().and_then(|m| m.last()).and_then(|m| m.id);
+            (messages, next_cursor)
+        },
+        None => {
+            let before_id = query.before_id.unwrap_or(u64::MAX);
+            let messages = db_service.chat_room_read_messages_before(&room_id, &before_id, &limit).await;
+            let next_cursor = messages.as_ref().ok().and_then(|m| m.first()).and_then(|m| m.id);
+            (messages, next_cursor)
+        },
+    };
+
+    let mut messages = match messages {
+        Ok(messages) => messages,
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    if let Err(_) = attach_reactions(&db_service, &room_id, &user_id, &mut messages).await {
+        return HttpResponse::InternalServerError().reason("3").finish()
+    }
+    if let Err(_) = attach_files(&db_service, &room_id, &mut messages).await {
+        return HttpResponse::InternalServerError().reason("4").finish()
+    }
+
+    // A page short of the limit has reached the end
+    let next_cursor = next_cursor.filter(|_| messages.len() as u64 == limit);
+    HttpResponse::Ok().json(ChatMessagePage { messages, next_cursor })
+}
+
+/// Deprecated in favour of `chat_get_messages`. Kept for older clients, which
+/// page with offsets from the latest message.
+#[get("/chat/{room_id}/{offset:\\d+}/{limit:\\d+}")]
+async fn chat_get_message_window(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64, u64)>
+) -> HttpResponse {
+    let (room_id, offset, limit) = path.into_inner();
+
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return HttpResponse::BadRequest().reason(BAD_PAGE_SIZE_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    // The window is the oldest `limit` of the latest `offset + limit` messages
+    let mut messages = match db_service.chat_room_read_messages_before(&room_id, &u64::MAX, &offset.saturating_add(limit)).await {
+        Ok(messages) => messages,
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+    messages.truncate(messages.len().saturating_sub(offset as usize));
+
+    if let Err(_) = attach_reactions(&db_service, &room_id, &user_id, &mut messages).await {
+        return HttpResponse::InternalServerError().reason("3").finish()
+    }
+    if let Err(_) = attach_files(&db_service, &room_id, &mut messages).await {
+        return HttpResponse::InternalServerError().reason("4").finish()
+    }
+
+    HttpResponse::Ok().json(messages)
+}
+
+#[get("/chat/search")]
+async fn chat_search_messages(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    query: Query<MessageSearchParam>
+) -> HttpResponse {
+    // Input validation
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().reason("q query cannot be empty").finish()
+    }
+    if query.q.chars().count() > MAX_SEARCH_LEN {
+        return HttpResponse::BadRequest().reason("q query longer than 256 chars").finish()
+    }
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return HttpResponse::BadRequest().reason("from must be before to").finish()
+        }
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return HttpResponse::BadRequest().reason(BAD_PAGE_SIZE_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the filtered room. Otherwise, the search
+    // only includes rooms the user is a member of.
+    if let Some(room_id) = query.room_id {
+        let members = match db_service.chat_room_get_users(&room_id).await {
+            Ok(members) => members,
+            Err(DatabaseServiceError::NoResult) => Vec::new(),
+            Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+        };
+
+        if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+            return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+        }
+    }
+
+    match db_service.chat_search_messages(&user_id, &query, &limit).await {
+        Ok(messages) => HttpResponse::Ok().json(messages),
+        Err(_) => HttpResponse::InternalServerError().reason("2").finish(),
+    }
+}
+
+#[get("/chat/mentions")]
+async fn chat_get_unread_mentions(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    query: Query<MentionParam>
+) -> HttpResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return HttpResponse::BadRequest().reason(BAD_PAGE_SIZE_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match db_service.chat_get_unread_mentions(&user_id, &limit).await {
+        Ok(messages) => HttpResponse::Ok().json(messages),
+        Err(_) => HttpResponse::InternalServerError().reason("1").finish(),
+    }
+}
+
+#[post("/chat")]
+async fn chat_send_message(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    typing: Data<TypingService>,
+    bearer: BearerAuth,
+    body: Json<ChatMessage>
+) -> HttpResponse {
+    // Disallow optional fields being populated
+    let optional_fields_set = body.id.is_some()
+        || body.sender_id.is_some()
+        || body.time_sent.is_some()
+        || body.edited_at.is_some()
+        || body.deleted_at.is_some()
+        || !body.reactions.is_empty()
+        || body.thread_root_id.is_some()
+        || body.reply_count != 0;
+    if optional_fields_set {
+        return HttpResponse::BadRequest().reason("id, sender_id, time_sent, edited_at, deleted_at, reactions, thread_root_id, or reply_count fields have values").finish()
+    }
+    if body.attachments.len() > MAX_MESSAGE_ATTACHMENTS {
+        return HttpResponse::BadRequest().reason(BAD_ATTACHMENTS_REASON).finish()
+    }
+    // A message can be just attachments, without any text
+    if body.body.is_empty() && body.attachments.is_empty() {
+        return HttpResponse::BadRequest().reason("Empty body value").finish()
+    }
+    if body.body.chars().count() > MAX_MESSAGE_LEN {
+        return HttpResponse::BadRequest().reason("body value longer than 1000 chars").finish()
+    }
+    if markdown::validate(&body.body).is_err() {
+        return HttpResponse::BadRequest().reason(UNSAFE_LINK_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&body.room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    // Direct messages are refused while either member has blocked the other
+    let room = match db_service.chat_room_get(&body.room_id).await {
+        Ok(room) => room,
+        Err(_) => return HttpResponse::InternalServerError().reason("4").finish(),
+    };
+    if room.kind == RoomKind::Direct {
+        if let Some(other) = members.iter().find(|m| m.user_id != user_id) {
+            match db_service.user_association_is_blocked_either(&user_id, &other.user_id).await {
+                Ok(false) => {},
+                Ok(true) => return HttpResponse::Forbidden().reason(DIRECT_BLOCKED_REASON).finish(),
+                Err(_) => return HttpResponse::InternalServerError().reason("5").finish(),
+            }
+        }
+    }
+
+    // Replies join the thread of the message being replied to
+    let thread_root_id = match body.reply_to {
+        Some(reply_to) => match db_service.chat_room_get_message(&reply_to).await {
+            Ok(parent) if parent.room_id == body.room_id && parent.deleted_at.is_none() => {
+                parent.thread_root_id.or(parent.id)
+            },
+            Ok(_) | Err(DatabaseServiceError::NoResult) => {
+                return HttpResponse::BadRequest().reason("reply_to message does not exist in the room").finish()
+            },
+            Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+        },
+        None => None,
+    };
+
+    // Record new message, along with its attachments
+    let message_id = match db_service.chat_room_send_message(&user_id, &body, &thread_root_id).await {
+        Ok(id) => id,
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::BadRequest().reason(BAD_ATTACHMENTS_REASON).finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    // Members stop showing the sender as typing once the message arrives
+    typing.stop_typing(body.room_id, user_id);
+
+    // Push the stored message to room members
+    match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) => {
+            let mut sent = vec![message];
+            if let Err(_) = attach_files(&db_service, &body.room_id, &mut sent).await {
+                log::warn!("Failed to retrieve attachments of message {} for delivery", message_id);
+            }
+            let recipients = members.iter().map(|m| m.user_id).collect();
+            realtime.publish(body.room_id, recipients, RoomEventKind::MessageSent(sent.remove(0)));
+        },
+        Err(_) => log::warn!("Failed to retrieve message {} for delivery", message_id),
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[put("/chat/{room_id}/messages/{message_id}")]
+async fn chat_edit_message(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64)>,
+    body: Json<ChatMessageEdit>
+) -> HttpResponse {
+    let (room_id, message_id) = path.into_inner();
+
+    // Input validation
+    if body.body.is_empty() {
+        return HttpResponse::BadRequest().reason("Empty body value").finish()
+    }
+    if body.body.chars().count() > MAX_MESSAGE_LEN {
+        return HttpResponse::BadRequest().reason("body value longer than 1000 chars").finish()
+    }
+    if markdown::validate(&body.body).is_err() {
+        return HttpResponse::BadRequest().reason(UNSAFE_LINK_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    // Only the sender may edit their message
+    let message = match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) if message.room_id == room_id => message,
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    if message.sender_id != Some(user_id) {
+        return HttpResponse::Forbidden().reason("Only the sender can edit a message").finish()
+    }
+
+    if message.deleted_at.is_some() {
+        return HttpResponse::BadRequest().reason("Message has been deleted").finish()
+    }
+
+    if let Err(_) = db_service.chat_room_edit_message(&message_id, &body.body).await {
+        return HttpResponse::InternalServerError().reason("3").finish()
+    }
+
+    // Push the edited message to room members
+    match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) => {
+            let recipients = members.iter().map(|m| m.user_id).collect();
+            realtime.publish(room_id, recipients, RoomEventKind::MessageEdited(message));
+        },
+        Err(_) => log::warn!("Failed to retrieve message {} for delivery", message_id),
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[delete("/chat/{room_id}/messages/{message_id}")]
+async fn chat_delete_message(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64)>
+) -> HttpResponse {
+    let (room_id, message_id) = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let requester = match members.iter().find(|m| m.user_id == user_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+
+    let message = match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) if message.room_id == room_id => message,
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    // Senders may retract their own messages, moderators may remove any
+    if message.sender_id != Some(user_id) && !requester.role.permits(RoomAction::DeleteMessage) {
+        return HttpResponse::Forbidden().reason(RoomAction::DeleteMessage.forbidden_reason()).finish()
+    }
+
+    match db_service.chat_room_delete_message(&message_id, &user_id).await {
+        Ok(()) => {},
+        // Already deleted
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Ok().finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+    }
+
+    // Push the tombstone to room members
+    match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) => {
+            let recipients = members.iter().map(|m| m.user_id).collect();
+            realtime.publish(room_id, recipients, RoomEventKind::MessageDeleted(message));
+        },
+        Err(_) => log::warn!("Failed to retrieve message {} for delivery", message_id),
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[get("/chat/{room_id}/messages/{message_id}/history")]
+async fn chat_get_message_history(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64)>
+) -> HttpResponse {
+    let (room_id, message_id) = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    let message = match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) if message.room_id == room_id => message,
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    // Revisions are removed along with the message body
+    if message.deleted_at.is_some() {
+        return HttpResponse::Ok().json(Vec::<ChatMessageRevision>::new())
+    }
+
+    match db_service.chat_room_get_message_revisions(&message_id).await {
+        Ok(revisions) => HttpResponse::Ok().json(revisions),
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+#[put("/chat/{room_id}/read")]
+async fn chat_mark_read(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<u64>,
+    body: Json<ChatReadMarker>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    let message = match db_service.chat_room_get_message(&body.message_id).await {
+        Ok(message) if message.room_id == room_id => message,
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    // Replies are read through their own thread, so reading one thread does
+    // not mark the replies of other threads as read
+    let marked = match message.thread_root_id {
+        Some(thread_root_id) => db_service.chat_thread_mark_read(&thread_root_id, &user_id, &body.message_id).await,
+        None => db_service.chat_room_mark_read(&room_id, &user_id, &body.message_id).await,
+    };
+    match marked {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+#[get("/chat/{room_id}/messages/{message_id}/receipts")]
+async fn chat_get_read_receipts(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64)>
+) -> HttpResponse {
+    let (room_id, message_id) = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    let message = match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) if message.room_id == room_id => message,
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    let readers = match message.thread_root_id {
+        Some(thread_root_id) => db_service.chat_thread_get_readers(&room_id, &thread_root_id, &message_id).await,
+        None => db_service.chat_room_get_readers(&room_id, &message_id).await,
+    };
+    match readers {
+        Ok(readers) => HttpResponse::Ok().json(readers),
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+#[get("/chat/{room_id}/messages/{message_id}/thread")]
+async fn chat_get_thread(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64)>,
+    query: Query<ThreadParam>
+) -> HttpResponse {
+    let (room_id, root_id) = path.into_inner();
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return HttpResponse::BadRequest().reason(BAD_PAGE_SIZE_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    match db_service.chat_room_get_message(&root_id).await {
+        Ok(root) if root.room_id == room_id => {
+            if root.thread_root_id.is_some() {
+                return HttpResponse::BadRequest().reason("Message is a reply, not a thread root").finish()
+            }
+        },
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    let after_id = query.after_id.unwrap_or(0);
+    let mut replies = match db_service.chat_room_read_thread(&root_id, &after_id, &limit).await {
+        Ok(replies) => replies,
+        Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+    };
+
+    if let Err(_) = attach_reactions(&db_service, &room_id, &user_id, &mut replies).await {
+        return HttpResponse::InternalServerError().reason("4").finish()
+    }
+    if let Err(_) = attach_files(&db_service, &room_id, &mut replies).await {
+        return HttpResponse::InternalServerError().reason("5").finish()
+    }
+
+    // A page short of the limit has reached the end of the thread
+    let next_cursor = replies.last()
+        .and_then(|reply| reply.id)
+        .filter(|_| replies.len() as u64 == limit);
+    HttpResponse::Ok().json(ChatMessagePage { messages: replies, next_cursor })
+}
+
+#[put("/chat/{room_id}/messages/{message_id}/reactions/{emoji}")]
+async fn chat_add_reaction(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64, String)>
+) -> HttpResponse {
+    let (room_id, message_id, emoji) = path.into_inner();
+
+    if !is_valid_reaction(&emoji) {
+        return HttpResponse::BadRequest().reason(BAD_REACTION_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) if message.room_id == room_id => {
+            if message.deleted_at.is_some() {
+                return HttpResponse::BadRequest().reason("Message has been deleted").finish()
+            }
+        },
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    match db_service.chat_room_add_reaction(&message_id, &user_id, &emoji).await {
+        Ok(()) => {},
+        // Already reacted with this emoji
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Ok().finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+    }
+
+    let recipients = members.iter().map(|m| m.user_id).collect();
+    let change = ReactionChange { message_id, user_id, emoji };
+    realtime.publish(room_id, recipients, RoomEventKind::ReactionAdded(change));
+
+    HttpResponse::Ok().finish()
+}
+
+#[delete("/chat/{room_id}/messages/{message_id}/reactions/{emoji}")]
+async fn chat_remove_reaction(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64, String)>
+) -> HttpResponse {
+    let (room_id, message_id, emoji) = path.into_inner();
+
+    if !is_valid_reaction(&emoji) {
+        return HttpResponse::BadRequest().reason(BAD_REACTION_REASON).finish()
+    }
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) if message.room_id == room_id => {},
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    match db_service.chat_room_remove_reaction(&message_id, &user_id, &emoji).await {
+        Ok(()) => {},
+        // Had not reacted with this emoji
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Ok().finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+    }
+
+    let recipients = members.iter().map(|m| m.user_id).collect();
+    let change = ReactionChange { message_id, user_id, emoji };
+    realtime.publish(room_id, recipients, RoomEventKind::ReactionRemoved(change));
+
+    HttpResponse::Ok().finish()
+}
+
+#[put("/chat/{room_id}/messages/{message_id}/pin")]
+async fn chat_pin_message(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64)>
+) -> HttpResponse {
+    let (room_id, message_id) = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let requester = match members.iter().find(|m| m.user_id == user_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+
+    if let Err(response) = require_pin_permission(&db_service, &room_id, requester).await {
+        return response
+    }
+
+    match db_service.chat_room_get_message(&message_id).await {
+        Ok(message) if message.room_id == room_id => {
+            if message.deleted_at.is_some() {
+                return HttpResponse::BadRequest().reason("Message has been deleted").finish()
+            }
+        },
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            return HttpResponse::NotFound().reason("Message does not exist in the room").finish()
+        },
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    let pin = match db_service.chat_room_pin_message(&room_id, &message_id, &user_id).await {
+        Ok(pin) => pin,
+        // Already pinned
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Ok().finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("3").finish(),
+    };
+
+    let recipients = members.iter().map(|m| m.user_id).collect();
+    realtime.publish(room_id, recipients, RoomEventKind::MessagePinned(pin));
+
+    HttpResponse::Ok().finish()
+}
+
+#[delete("/chat/{room_id}/messages/{message_id}/pin")]
+async fn chat_unpin_message(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    bearer: BearerAuth,
+    path: Path<(u64, u64)>
+) -> HttpResponse {
+    let (room_id, message_id) = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let requester = match members.iter().find(|m| m.user_id == user_id) {
+        Some(requester) => requester,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+
+    if let Err(response) = require_pin_permission(&db_service, &room_id, requester).await {
+        return response
+    }
+
+    match db_service.chat_room_unpin_message(&room_id, &message_id).await {
+        Ok(()) => {},
+        // Not pinned
+        Err(DatabaseServiceError::NoResult) => return HttpResponse::Ok().finish(),
+        Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
+    }
+
+    let recipients = members.iter().map(|m| m.user_id).collect();
+    realtime.publish(room_id, recipients, RoomEventKind::MessageUnpinned(message_id));
+
+    HttpResponse::Ok().finish()
+}
+
+#[get("/chat/{room_id}/pins")]
+async fn chat_get_pinned_messages(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    path: Path<u64>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    match db_service.chat_room_get_pinned_messages(&room_id).await {
+        Ok(pins) => HttpResponse::Ok().json(pins),
+        Err(_) => HttpResponse::InternalServerError().reason("2").finish(),
+    }
+}
+
+#[post("/chat/{room_id}/attachments")]
+async fn chat_upload_attachment(
+    db_service: Data<DatabaseService>,
+    attachments: Data<AttachmentService>,
+    bearer: BearerAuth,
+    path: Path<u64>,
+    mut payload: Multipart
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Check if requestng user is in the room
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == user_id) {
+        return HttpResponse::Unauthorized().reason("User is not part of the room").finish()
+    }
+
+    let mut field = match payload.next().await {
+        Some(Ok(field)) if field.name() == Some(ATTACHMENT_FIELD_NAME) => field,
+        Some(Ok(_)) | None => return HttpResponse::BadRequest().reason("Expected a single file field").finish(),
+        Some(Err(_)) => return HttpResponse::BadRequest().reason("Invalid multipart payload").finish(),
+    };
+
+    // Input validation
+    let content_type = match field.content_type() {
+        Some(mime) => mime.essence_str().to_ascii_lowercase(),
+        None => return HttpResponse::BadRequest().reason("File has no content type").finish(),
+    };
+    if !attachments.is_allowed_type(&content_type) {
+        return HttpResponse::UnsupportedMediaType().reason("File type is not allowed").finish()
+    }
+
+    // Only the final component of a path is kept
+    let file_name = field.content_disposition()
+        .and_then(|disposition| disposition.get_filename())
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default();
+    if file_name.is_empty() || file_name.chars().count() > MAX_FILE_NAME_LEN {
+        return HttpResponse::BadRequest().reason(BAD_FILE_NAME_REASON).finish()
+    }
+    if file_name.chars().any(|c| c.is_control()) {
+        return HttpResponse::BadRequest().reason("File name contains control characters").finish()
+    }
+
+    // Read the file, refusing it as soon as it exceeds the size limit
+    let mut contents = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(_) => return HttpResponse::BadRequest().reason("Invalid multipart payload").finish(),
+        };
+        if contents.len() + chunk.len() > attachments.max_size {
+            return HttpResponse::PayloadTooLarge().reason("File is larger than the attachment size limit").finish()
+        }
+        contents.extend_from_slice(&chunk);
+    }
+    if contents.is_empty() {
+        return HttpResponse::BadRequest().reason("File is empty").finish()
+    }
+    if !AttachmentService::matches_content(&content_type, &contents) {
+        return HttpResponse::UnsupportedMediaType().reason("File contents do not match its content type").finish()
+    }
+
+    // Store the contents, generating a thumbnail for images
+    let size = contents.len() as u64;
+    let service = attachments.clone();
+    let stored_content_type = content_type.clone();
+    let stored = match actix_web::web::block(move || service.store(&contents, &stored_content_type)).await {
+        Ok(Ok(stored)) => stored,
+        _ => return HttpResponse::InternalServerError().reason("2").finish(),
+    };
+
+    let attachment_id = match db_service.chat_room_create_attachment(
+        &room_id,
+        &user_id,
+        &file_name,
+        &content_type,
+        &size,
+        &stored.storage_key,
+        stored.has_thumbnail
+    ).await {
+        Ok(id) => id,
+        Err(_) => {
+            remove_stored_attachments(&attachments, vec![stored.storage_key]).await;
+            return HttpResponse::InternalServerError().reason("3").finish()
+        },
+    };
+
+    HttpResponse::Ok().json(ChatAttachment {
+        id: attachment_id,
+        file_name,
+        content_type,
+        size,
+        has_thumbnail: stored.has_thumbnail
+    })
+}
+
+#[get("/chat/{room_id}/attachments/{attachment_id}")]
+async fn chat_get_attachment(
+    db_service: Data<DatabaseService>,
+    attachments: Data<AttachmentService>,
+    tickets: Data<TicketService>,
+    path: Path<(u64, u64)>,
+    query: Query<TicketParam>
+) -> HttpResponse {
+    let (room_id, attachment_id) = path.into_inner();
+
+    // Attachments are loaded by the browser, such as for image previews,
+    // which cannot set headers. See chat_socket.
+    let user_id = match ticket_to_user_id(&db_service, &tickets, &query.ticket).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match downloadable_attachment(&db_service, &room_id, &attachment_id, &user_id).await {
+        Ok(attachment) => attachment_response(&attachments, attachment, false).await,
+        Err(response) => response,
+    }
+}
+
+#[get("/chat/{room_id}/attachments/{attachment_id}/thumbnail")]
+async fn chat_get_attachment_thumbnail(
+    db_service: Data<DatabaseService>,
+    attachments: Data<AttachmentService>,
+    tickets: Data<TicketService>,
+    path: Path<(u64, u64)>,
+    query: Query<TicketParam>
+) -> HttpResponse {
+    let (room_id, attachment_id) = path.into_inner();
+
+    // See chat_get_attachment
+    let user_id = match ticket_to_user_id(&db_service, &tickets, &query.ticket).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match downloadable_attachment(&db_service, &room_id, &attachment_id, &user_id).await {
+        Ok(attachment) if attachment.has_thumbnail.0 => attachment_response(&attachments, attachment, true).await,
+        Ok(_) => HttpResponse::NotFound().reason("Attachment has no thumbnail").finish(),
+        Err(response) => response,
+    }
+}
+
+#[post("/chat/{room_id}/typing")]
+async fn chat_typing(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    typing: Data<TypingService>,
+    bearer: BearerAuth,
+    path: Path<u64>
+) -> HttpResponse {
+    let room_id = path.into_inner();
+
+    // Identify requesting user
+    let user_id = match token_to_user_id(&db_service, bearer.token()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let members = match db_service.chat_room_get_users(&room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
+    };
+
+    let typist = match members.iter().find(|m| m.user_id == user_id) {
+        Some(typist) => typist,
+        None => return HttpResponse::Unauthorized().reason("User is not part of the room").finish(),
+    };
+
+    if typing.start_typing(room_id, user_id) {
+        let recipients = members.iter()
+            .map(|m| m.user_id)
+            .filter(|id| *id != user_id)
+            .collect();
+        let typist = UserInfo { id: typist.user_id, username: typist.username.clone() };
+        realtime.publish_ephemeral(room_id, recipients, RoomEventKind::Typing(typist));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[get("/chat/ws")]
+async fn chat_socket(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    presence: Data<PresenceService>,
+    tickets: Data<TicketService>,
+    req: HttpRequest,
+    body: Payload,
+    query: Query<TicketParam>
+) -> HttpResponse {
+    // Browsers cannot set headers on a WebSocket upgrade, so a ticket issued
+    // for the bearer token is provided as a query parameter instead.
+    let user_session = match ticket_to_session(&db_service, &tickets, &query.ticket).await {
+        Ok(user_session) => user_session,
+        Err(response) => return response,
+    };
+    let user_id = user_session.user_id;
+
+    let (response, mut session, mut msg_stream) = match actix_ws::handle(&req, body) {
+        Ok(socket) => socket,
+        Err(_) => return HttpResponse::BadRequest().reason("WebSocket handshake failed").finish(),
+    };
+
+    let mut deliveries = realtime.subscribe();
+    let connection = presence.connect(user_id);
+    let session_ended = session_ended(db_service.clone(), user_session.session_id);
+
+    actix_web::rt::spawn(async move {
+        // Connected until the socket closes
+        let _connection = connection;
+        tokio::pin!(session_ended);
+        let close_reason = loop {
+            tokio::select! {
+                delivery = deliveries.recv() => match delivery {
+                    Ok(delivery) if delivery.recipients.contains(&user_id) => {
+                        let payload = match serde_json::to_string(&delivery.event) {
+                            Ok(payload) => payload,
+                            Err(_) => continue,
+                        };
+                        if session.text(payload).await.is_err() {
+                            return;
+                        }
+                    },
+                    Ok(_) => {},
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("Chat socket for user {} skipped {} deliveries", user_id, skipped);
+                        if session.text(SOCKET_RESYNC_MESSAGE).await.is_err() {
+                            return;
+                        }
+                    },
+                    Err(RecvError::Closed) => break None,
+                },
+                msg = msg_stream.recv() => match msg {
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    },
+                    Some(Ok(actix_ws::Message::Close(reason))) => break reason,
+                    // Clients send messages over HTTP, anything else is ignored
+                    Some(Ok(_)) => {},
+                    Some(Err(_)) | None => break None,
+                },
+                _ = &mut session_ended => break Some(CloseReason {
+                    code: CloseCode::Policy,
+                    description: Some(INVALID_TOKEN_REASON.to_string())
+                }),
+            }
+        };
+        let _ = session.close(close_reason).await;
+    });
+
+    response
+}
+
+#[get("/events")]
+async fn room_event_stream(
+    db_service: Data<DatabaseService>,
+    realtime: Data<RealtimeService>,
+    presence: Data<PresenceService>,
+    tickets: Data<TicketService>,
+    req: HttpRequest,
+    query: Query<EventStreamParam>
+) -> HttpResponse {
+    // EventSource cannot set headers either, see chat_socket
+    let user_session = match ticket_to_session(&db_service, &tickets, &query.ticket).await {
+        Ok(user_session) => user_session,
+        Err(response) => return response,
+    };
+    let user_id = user_session.user_id;
+
+    // Sent by clients when reconnecting to resume from the last received event
+    let last_event_id = req.headers()
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .or(query.last_event_id);
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(
+            realtime.event_stream(user_id, last_event_id, presence.connect(user_id))
+                .take_until(session_ended(db_service.clone(), user_session.session_id))
+        )
+}
+
+// User interaction
+
+#[get("/users")]
+async fn user_search_global(
+    db_service: Data<DatabaseService>,
+    bearer: BearerAuth,
+    query: Query<UserSearchParam>
+) -> HttpResponse {
+    // Search must contain some text
+    if query.username.is_empty() {
+        return HttpResponse::BadRequest().reason("username query cannot be empty").finish()
+    }
+    
     // User must be logged in to search. They may be blocked
     let user_id = match token_to_user_id(&db_service, bearer.token()).await {
         Ok(id) => id,
@@ -641,6 +2790,7 @@ async fn user_association(
 #[get("/users/associations")]
 async fn user_get_associations(
     db_service: Data<DatabaseService>,
+    presence: Data<PresenceService>,
     bearer: BearerAuth
 ) -> HttpResponse {
     // Find requester user id
@@ -654,6 +2804,21 @@ async fn user_get_associations(
         Err(_) => return HttpResponse::InternalServerError().reason("1").finish(),
     };
 
+    let friends_last_seen = match db_service.user_association_get_friends_last_seen(&user_id).await {
+        Ok(last_seen) => last_seen,
+        Err(_) => return HttpResponse::InternalServerError().reason("5").finish(),
+    };
+
+    let requester_friends = requester_friends.into_iter()
+        .map(|friend| {
+            let friend_presence = friends_last_seen.iter()
+                .find(|seen| seen.user_id == friend.id)
+                .map(|seen| presence.presence(seen.user_id, seen.last_seen_at, seen.last_seen_visible.0))
+                .unwrap_or_default();
+            UserPresenceInfo { user: friend, presence: friend_presence }
+        })
+        .collect();
+
     let incoming_friend_reqs = match db_service.user_association_get_friend_requesters(&user_id).await {
         Ok(incoming) => incoming,
         Err(_) => return HttpResponse::InternalServerError().reason("2").finish(),
@@ -688,13 +2853,348 @@ async fn user_get_associations(
 /// * bad token format - HTTP 400 Bad Request
 /// * no mapped user - HTTP 401 Unauthorized
 async fn token_to_user_id(db_service: &DatabaseService, bearer_token: &str) -> Result<u64, HttpResponse> {
-    let token = match Uuid::from_str(bearer_token) {
-        Ok(uuid) => uuid,
+    token_to_session(db_service, bearer_token).await.map(|session| session.user_id)
+}
+
+/// Find the session of the provided `bearer_token`, as with `token_to_user_id`.
+async fn token_to_session(db_service: &DatabaseService, bearer_token: &str) -> Result<DBSession, HttpResponse> {
+    let token = match AuthToken::from_str(bearer_token) {
+        Ok(token) => token,
         Err(_) => return Err(HttpResponse::BadRequest().reason(BAD_TOKEN_FORMAT_REASON).finish()),
     };
-    match db_service.user_id_from_token(&token).await {
-        Ok(user_id) => Ok(user_id),
-        Err(DatabaseServiceError::NoResult) => Err(HttpResponse::Unauthorized().finish()),
-        Err(_) => Err(HttpResponse::InternalServerError().reason("map").finish()),
+    let session = match db_service.user_get_session(&token).await {
+        Ok(session) => session,
+        Err(DatabaseServiceError::NoResult) => return Err(invalid_token_response()),
+        Err(_) => return Err(HttpResponse::InternalServerError().reason("map").finish()),
+    };
+    if db_service.session_policy().is_expired(&session, chrono::Utc::now()) {
+        // Expired tokens are also purged periodically, this just does it sooner
+        let _ = db_service.user_expire_token(&token).await;
+        return Err(invalid_token_response());
+    }
+
+    // Any authenticated request counts as activity, and keeps the session
+    // alive. Failing to record it does not fail the request.
+    let _ = db_service.user_touch_token(&token).await;
+    let _ = db_service.user_record_activity(&session.user_id).await;
+    Ok(session)
+}
+
+/// Wait until the session specified by `session_id` has expired or been
+/// logged out, checking every `SESSION_CHECK_INTERVAL`. Database errors are
+/// taken as the session still being valid.
+async fn session_ended(db_service: Data<DatabaseService>, session_id: String) {
+    loop {
+        tokio::time::sleep(SESSION_CHECK_INTERVAL).await;
+        match db_service.user_get_session_by_id(&session_id).await {
+            Ok(session) if db_service.session_policy().is_expired(&session, chrono::Utc::now()) => return,
+            Ok(_) => {},
+            Err(DatabaseServiceError::NoResult) => return,
+            Err(_) => {},
+        }
+    }
+}
+
+/// Log in the user specified by `user_id` from the client making `req`,
+/// responding with a new auth token.
+async fn issue_token(db_service: &DatabaseService, user_id: &u64, req: &HttpRequest) -> HttpResponse {
+    // Get user-agent header
+    let headers = req.headers();
+    let user_agent = match headers.get(header::USER_AGENT) {
+        Some(data) => data.to_str().unwrap_or("Unknown client"),
+        None => "Unknown client"
+    };
+    let ip_address = req.peer_addr().map(|addr| addr.ip().to_string());
+
+    // Generate and store token before sending back to client
+    // In the very unlikely chance a token clash occurs, re-try
+    let mut token = generate_token();
+    let mut token_set_result = Err(DatabaseServiceError::KeyAlreadyExists);
+    while let Err(DatabaseServiceError::KeyAlreadyExists) = token_set_result {
+        token = generate_token();
+        token_set_result = db_service.user_set_token(user_id, &token, user_agent, ip_address.as_deref()).await;
+    }
+
+    match token_set_result {
+        Ok(()) => HttpResponse::Ok().json(LoginResponse {
+            user_id: *user_id,
+            token: token.to_string()
+        }),
+        Err(_) => HttpResponse::InternalServerError().reason("3").finish(),
+    }
+}
+
+/// Check a second factor `code` given by the user specified by `user_id`,
+/// which is either a TOTP code or one of their recovery codes. A correct code
+/// is used up, so that it is not accepted again.
+async fn verify_second_factor(
+    db_service: &DatabaseService,
+    argon2: &Argon2<'_>,
+    user_id: &u64,
+    code: &str
+) -> Result<bool, DatabaseServiceError> {
+    let code = code.trim();
+    if totp::is_totp_code(code) {
+        let user_totp = db_service.user_get_totp(user_id).await?;
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let Some(step) = totp::matching_step(&user_totp.secret, code, now) else {
+            return Ok(false)
+        };
+        return match db_service.user_use_totp_step(user_id, &step).await {
+            Ok(()) => Ok(true),
+            // The code has already been used
+            Err(DatabaseServiceError::NoResult) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    let code = totp::normalise_recovery_code(code);
+    for recovery_code in db_service.user_get_recovery_codes(user_id).await? {
+        let Ok(hash) = PasswordHash::new(&recovery_code.code_hash) else {
+            continue
+        };
+        if argon2.verify_password(code.as_bytes(), &hash).is_ok() {
+            return match db_service.user_use_recovery_code(&recovery_code.id).await {
+                Ok(()) => Ok(true),
+                Err(DatabaseServiceError::NoResult) => Ok(false),
+                Err(e) => Err(e),
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// A new auth token, drawn from the operating system's secure random source.
+fn generate_token() -> AuthToken {
+    let mut bytes = [0; AUTH_TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    AuthToken(bytes)
+}
+
+/// The response to a bearer token that is unknown or has expired.
+fn invalid_token_response() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, INVALID_TOKEN_CHALLENGE))
+        .reason(INVALID_TOKEN_REASON)
+        .finish()
+}
+
+/// Use up the provided `ticket`, finding the user id of the token it was
+/// issued for.
+/// 
+/// As with `token_to_user_id`, the appropriate HttpResponse is returned if
+/// the ticket cannot be used.
+/// * bad ticket format - HTTP 400 Bad Request
+/// * unknown, expired or used ticket, or no mapped user - HTTP 401 Unauthorized
+async fn ticket_to_user_id(
+    db_service: &DatabaseService,
+    tickets: &TicketService,
+    ticket: &str
+) -> Result<u64, HttpResponse> {
+    ticket_to_session(db_service, tickets, ticket).await.map(|session| session.user_id)
+}
+
+/// Use up the provided `ticket`, finding the session of the token it was
+/// issued for, as with `ticket_to_user_id`.
+async fn ticket_to_session(
+    db_service: &DatabaseService,
+    tickets: &TicketService,
+    ticket: &str
+) -> Result<DBSession, HttpResponse> {
+    let ticket = match AuthToken::from_str(ticket) {
+        Ok(ticket) => ticket,
+        Err(_) => return Err(HttpResponse::BadRequest().reason(BAD_TICKET_FORMAT_REASON).finish()),
+    };
+    let token = match tickets.redeem(&ticket) {
+        Some(token) => token,
+        None => return Err(HttpResponse::Unauthorized().reason(INVALID_TICKET_REASON).finish()),
+    };
+
+    // The token may have been logged out since the ticket was issued
+    token_to_session(db_service, &token.to_string()).await
+}
+
+/// Attach reaction counts, from the perspective of `user_id`, to each of the
+/// `messages` from the room specified by `room_id`. Deleted messages are left
+/// without reactions.
+async fn attach_reactions(
+    db_service: &DatabaseService,
+    room_id: &u64,
+    user_id: &u64,
+    messages: &mut Vec<ChatMessage>
+) -> Result<(), DatabaseServiceError> {
+    let message_ids = messages.iter().filter_map(|m| m.id);
+    let (Some(first_id), Some(last_id)) = (message_ids.clone().min(), message_ids.max()) else {
+        return Ok(())
+    };
+
+    let counts = db_service.chat_room_get_reactions(room_id, &first_id, &last_id, user_id).await?;
+    for count in counts {
+        let message = messages.iter_mut()
+            .find(|m| m.id == Some(count.message_id) && m.deleted_at.is_none());
+        if let Some(message) = message {
+            message.reactions.push(count.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Attach the files sent with each of the `messages` from the room specified
+/// by `room_id`. Deleted messages are left without attachments.
+async fn attach_files(
+    db_service: &DatabaseService,
+    room_id: &u64,
+    messages: &mut Vec<ChatMessage>
+) -> Result<(), DatabaseServiceError> {
+    let message_ids = messages.iter().filter_map(|m| m.id);
+    let (Some(first_id), Some(last_id)) = (message_ids.clone().min(), message_ids.max()) else {
+        return Ok(())
+    };
+
+    let attachments = db_service.chat_room_get_attachments(room_id, &first_id, &last_id).await?;
+    for attachment in &attachments {
+        let message = messages.iter_mut()
+            .find(|m| m.id == attachment.message_id && m.deleted_at.is_none());
+        if let Some(message) = message {
+            message.attachments.push(attachment.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Retrieve the attachment specified by `attachment_id` for `user_id` to
+/// download from the room specified by `room_id`. Uploads that have yet to be
+/// sent with a message can only be downloaded by their uploader.
+/// * not a member of the room - HTTP 401 Unauthorized
+/// * no such attachment in the room, or its message was deleted - HTTP 404 Not Found
+async fn downloadable_attachment(
+    db_service: &DatabaseService,
+    room_id: &u64,
+    attachment_id: &u64,
+    user_id: &u64
+) -> Result<DBAttachment, HttpResponse> {
+    // Same membership check as chat_get_messages
+    let members = match db_service.chat_room_get_users(room_id).await {
+        Ok(members) => members,
+        Err(DatabaseServiceError::NoResult) => Vec::new(),
+        Err(_) => return Err(HttpResponse::InternalServerError().reason("1").finish()),
+    };
+
+    if !members.iter().map(|m| m.user_id).any(|id| id == *user_id) {
+        return Err(HttpResponse::Unauthorized().reason("User is not part of the room").finish())
+    }
+
+    match db_service.chat_room_get_attachment(attachment_id).await {
+        Ok(attachment) if attachment.room_id == *room_id
+            && !attachment.message_deleted.0
+            && (attachment.message_id.is_some() || attachment.uploader_id == *user_id) => Ok(attachment),
+        Ok(_) | Err(DatabaseServiceError::NoResult) => {
+            Err(HttpResponse::NotFound().reason("Attachment does not exist in the room").finish())
+        },
+        Err(_) => Err(HttpResponse::InternalServerError().reason("2").finish()),
+    }
+}
+
+/// Respond with the contents of the `attachment`, or its thumbnail. Images are
+/// shown inline, and anything else is downloaded.
+async fn attachment_response(attachments: &Data<AttachmentService>, attachment: DBAttachment, thumbnail: bool) -> HttpResponse {
+    let service = attachments.clone();
+    let storage_key = attachment.storage_key.clone();
+    let contents = match actix_web::web::block(move || service.read(&storage_key, thumbnail)).await {
+        Ok(Ok(contents)) => contents,
+        _ => return HttpResponse::InternalServerError().reason("3").finish(),
+    };
+
+    let content_type = match thumbnail {
+        true => "image/png".to_string(),
+        false => attachment.content_type,
+    };
+    let disposition = match content_type.starts_with("image/") {
+        true => DispositionType::Inline,
+        false => DispositionType::Attachment,
+    };
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .insert_header(ContentDisposition {
+            disposition,
+            parameters: vec![DispositionParam::Filename(attachment.file_name)]
+        })
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        // Each load has a new ticket, so a cached copy would never be used
+        .insert_header((header::CACHE_CONTROL, "private, no-store"))
+        .body(contents)
+}
+
+/// Remove the stored contents of attachments under `storage_keys`. Failures
+/// are only logged, as the attachments are already gone from the database.
+pub async fn remove_stored_attachments(attachments: &Data<AttachmentService>, storage_keys: Vec<String>) {
+    if storage_keys.is_empty() {
+        return
+    }
+    let service = attachments.clone();
+    let removal = actix_web::web::block(move || {
+        for storage_key in &storage_keys {
+            if let Err(e) = service.remove(storage_key) {
+                log::warn!("Failed to remove stored attachment {}: {}", storage_key, e);
+            }
+        }
+    });
+    if let Err(e) = removal.await {
+        log::warn!("Failed to remove stored attachments: {}", e);
+    }
+}
+
+/// Check that `emoji` is plausibly a single emoji to react with.
+/// 
+/// Emoji are made up of non-ASCII characters, except for keycap sequences
+/// which begin with a digit, `#` or `*`.
+fn is_valid_reaction(emoji: &str) -> bool {
+    !emoji.is_empty()
+        && emoji.len() <= MAX_REACTION_LEN
+        && emoji.chars().any(|c| !c.is_ascii())
+        && emoji.chars().all(|c| !c.is_whitespace() && !c.is_control())
+        && emoji.chars()
+            .filter(|c| c.is_ascii())
+            .all(|c| c.is_ascii_digit() || c == '#' || c == '*')
+}
+
+/// Ensure the room specified by `room_id` is a group room, as the members of
+/// direct rooms cannot be changed.
+/// * direct room - HTTP 403 Forbidden
+async fn require_group_room(db_service: &DatabaseService, room_id: &u64) -> Result<(), HttpResponse> {
+    match db_service.chat_room_get(room_id).await {
+        Ok(room) if room.kind == RoomKind::Direct => Err(HttpResponse::Forbidden().reason(DIRECT_ROOM_REASON).finish()),
+        Ok(_) => Ok(()),
+        Err(_) => Err(HttpResponse::InternalServerError().reason("room").finish()),
+    }
+}
+
+/// Ensure `requester` may pin and unpin messages in the room specified by
+/// `room_id`. Either member of a direct room may, as neither moderates it.
+/// * not permitted - HTTP 403 Forbidden
+async fn require_pin_permission(db_service: &DatabaseService, room_id: &u64, requester: &DBRoomMember) -> Result<(), HttpResponse> {
+    if requester.role.permits(RoomAction::PinMessage) {
+        return Ok(())
+    }
+    match db_service.chat_room_get(room_id).await {
+        Ok(room) if room.kind == RoomKind::Direct => Ok(()),
+        Ok(_) => Err(HttpResponse::Forbidden().reason(RoomAction::PinMessage.forbidden_reason()).finish()),
+        Err(_) => Err(HttpResponse::InternalServerError().reason("room").finish()),
+    }
+}
+
+/// Retrieve the direct room specified by `room_id`, named after the other
+/// member `other_id` as it is listed by `GET /chat/rooms`.
+async fn direct_room(db_service: &DatabaseService, room_id: &u64, other_id: &u64) -> Result<ChatRoom, HttpResponse> {
+    let mut room = match db_service.chat_room_get(room_id).await {
+        Ok(room) => room,
+        Err(_) => return Err(HttpResponse::InternalServerError().reason("room").finish()),
+    };
+    if let Ok(other) = db_service.user_get_by_id(other_id).await {
+        room.name = other.username;
     }
+    Ok(room)
 }
\ No newline at end of file
@@ -1,6 +1,15 @@
+mod attachment;
 mod database;
 mod handler;
 mod models;
+mod presence;
+mod realtime;
+mod storage;
+mod ticket;
+mod totp;
+mod typing;
+
+use std::time::Duration;
 
 use dotenv::dotenv;
 
@@ -11,34 +20,141 @@ use actix_web::{
     middleware::Logger
 };
 use argon2::Argon2;
+use attachment::AttachmentService;
 use database::DatabaseService;
-use log::info;
+use log::{info, warn};
+use models::{EmptyRoomPolicy, SessionPolicy};
+use presence::PresenceService;
+use realtime::RealtimeService;
+use storage::LocalStorage;
+use ticket::TicketService;
+use typing::TypingService;
 
 const SERVER_ADDR: &str = "127.0.0.1";
 const SERVER_PORT: u16 = 8000;
+const DEFAULT_ATTACHMENT_DIR: &str = "attachments";
+const DEFAULT_ATTACHMENT_MAX_BYTES: usize = 10 * 1024 * 1024;
+const DEFAULT_SESSION_LIFETIME_SECS: u64 = 30 * 24 * 60 * 60;
+const DEFAULT_SESSION_IDLE_TIMEOUT_SECS: u64 = 7 * 24 * 60 * 60;
+/// How often expired auth tokens and unsent attachments are removed
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_ATTACHMENT_TYPES: &str = "image/png,image/jpeg,image/gif,image/webp,text/plain,application/pdf";
+const DEFAULT_UNSENT_ATTACHMENT_MAX_AGE_SECS: u64 = 24 * 60 * 60;
 
 fn get_var_or_panic(var_name: &str) -> String {
     std::env::var(var_name).expect(&format!("{} is not set", var_name))
 }
 
+/// Remove expired auth tokens and login challenges, and attachments not sent
+/// within `unsent_attachment_max_age_secs` of being uploaded, every
+/// `PURGE_INTERVAL`, for as long as the server runs.
+async fn purge_expired(
+    db_service: actix_web::web::Data<DatabaseService>,
+    attachment_service: actix_web::web::Data<AttachmentService>,
+    unsent_attachment_max_age_secs: u64
+) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match db_service.user_purge_expired_tokens().await {
+            Ok(0) => {},
+            Ok(count) => info!("Purged {} expired sessions", count),
+            Err(e) => warn!("Failed to purge expired sessions: {}", e),
+        }
+        if let Err(e) = db_service.user_purge_expired_login_challenges().await {
+            warn!("Failed to purge expired login challenges: {}", e);
+        }
+        match db_service.chat_purge_unsent_attachments(&unsent_attachment_max_age_secs).await {
+            Ok(storage_keys) if storage_keys.is_empty() => {},
+            Ok(storage_keys) => {
+                info!("Purged {} unsent attachments", storage_keys.len());
+                handler::remove_stored_attachments(&attachment_service, storage_keys).await;
+            },
+            Err(e) => warn!("Failed to purge unsent attachments: {}", e),
+        }
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "info");
     dotenv().ok();
 
-    let db_service = DatabaseService::new(&get_var_or_panic("DATABASE_URL")).await;
+    let session_policy = SessionPolicy {
+        lifetime_secs: match std::env::var("SESSION_LIFETIME_SECS") {
+            Ok(secs) => secs.parse::<u64>().unwrap(),
+            Err(_) => DEFAULT_SESSION_LIFETIME_SECS
+        },
+        idle_timeout_secs: match std::env::var("SESSION_IDLE_TIMEOUT_SECS") {
+            Ok(secs) => secs.parse::<u64>().unwrap(),
+            Err(_) => DEFAULT_SESSION_IDLE_TIMEOUT_SECS
+        }
+    };
+    let db_service = DatabaseService::new(
+        &get_var_or_panic("DATABASE_URL"),
+        session_policy,
+        get_var_or_panic("TOKEN_HASH_KEY").as_bytes()
+    ).await;
     let argon2 = Argon2::default();
+    let realtime_service = RealtimeService::new();
+    let ticket_service = TicketService::new();
+    let typing_service = TypingService::new();
+    let presence_service = PresenceService::new();
+    let empty_room_policy = match std::env::var("EMPTY_ROOM_POLICY") {
+        Ok(policy) => policy.parse::<EmptyRoomPolicy>().unwrap(),
+        Err(_) => EmptyRoomPolicy::Delete
+    };
+    let attachment_storage = LocalStorage::new(
+        std::env::var("ATTACHMENT_DIR").unwrap_or(DEFAULT_ATTACHMENT_DIR.to_string())
+    )?;
+    let attachment_max_bytes = match std::env::var("ATTACHMENT_MAX_BYTES") {
+        Ok(max_bytes) => max_bytes.parse::<usize>().unwrap(),
+        Err(_) => DEFAULT_ATTACHMENT_MAX_BYTES
+    };
+    let attachment_types = std::env::var("ATTACHMENT_TYPES")
+        .unwrap_or(DEFAULT_ATTACHMENT_TYPES.to_string())
+        .split(',')
+        .map(|content_type| content_type.trim().to_ascii_lowercase())
+        .filter(|content_type| !content_type.is_empty())
+        .collect();
+    let attachment_service = AttachmentService::new(Box::new(attachment_storage), attachment_max_bytes, attachment_types);
+    let unsent_attachment_max_age_secs = match std::env::var("ATTACHMENT_UNSENT_MAX_AGE_SECS") {
+        Ok(secs) => secs.parse::<u64>().unwrap(),
+        Err(_) => DEFAULT_UNSENT_ATTACHMENT_MAX_AGE_SECS
+    };
     
     let db_service_data = actix_web::web::Data::new(db_service);
     let argon2_data = actix_web::web::Data::new(argon2);
+    let realtime_data = actix_web::web::Data::new(realtime_service);
+    let ticket_data = actix_web::web::Data::new(ticket_service);
+    let typing_data = actix_web::web::Data::new(typing_service);
+    let presence_data = actix_web::web::Data::new(presence_service);
+    let empty_room_policy_data = actix_web::web::Data::new(empty_room_policy);
+    let attachment_data = actix_web::web::Data::new(attachment_service);
+
+    actix_web::rt::spawn(purge_expired(
+        db_service_data.clone(),
+        attachment_data.clone(),
+        unsent_attachment_max_age_secs
+    ));
 
     let app = HttpServer::new(move ||
         App::new()
-            .wrap(Logger::new("%a \"%r\" %s %b %T"))
+            // The query string is left out of the log, as it can hold tickets
+            .wrap(Logger::new("%a \"%{request_line}xi\" %s %b %T")
+                .custom_request_replace("request_line", |req| {
+                    format!("{} {} {:?}", req.method(), req.path(), req.version())
+                }))
             .wrap(Cors::permissive())
             .configure(handler::config)
             .app_data(db_service_data.clone())
             .app_data(argon2_data.clone())
+            .app_data(realtime_data.clone())
+            .app_data(ticket_data.clone())
+            .app_data(typing_data.clone())
+            .app_data(presence_data.clone())
+            .app_data(empty_room_policy_data.clone())
+            .app_data(attachment_data.clone())
     ).bind((SERVER_ADDR, SERVER_PORT))?;
 
     env_logger::init();
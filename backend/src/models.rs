@@ -1,5 +1,7 @@
+use std::str::FromStr;
+
 use chrono::{DateTime, Utc};
-use common::LoginTokenInfo;
+use common::{ChatAttachment, ChatMessage, ChatPinnedMessage, ChatRoom, ChatRoomInvitation, ChatRoomInviteCode, ChatRoomListing, ChatRoomMember, LastSeenVisibility, LoginTokenInfo, ReactionCount, RoomKind, RoomRole, RoomVisibility, UserInfo};
 use serde;
 
 #[derive(Debug, serde::Deserialize)]
@@ -9,33 +11,618 @@ pub struct DBUser {
     pub(crate) password_hash: String,
 }
 
+pub struct DBRoom {
+    pub id: u64,
+    pub name: String,
+    pub kind: DBRoomKind,
+    pub visibility: DBRoomVisibility
+}
+
+impl Into<ChatRoom> for DBRoom {
+    fn into(self) -> ChatRoom {
+        ChatRoom {
+            id: self.id,
+            name: self.name,
+            kind: self.kind.into(),
+            visibility: self.visibility.into(),
+            unread_count: 0
+        }
+    }
+}
+
+/// A chat room the user is a member of, with their unread message count
+pub struct DBMemberRoom {
+    pub id: u64,
+    pub name: String,
+    pub kind: DBRoomKind,
+    pub visibility: DBRoomVisibility,
+    pub unread_count: i64
+}
+
+impl Into<ChatRoom> for DBMemberRoom {
+    fn into(self) -> ChatRoom {
+        ChatRoom {
+            id: self.id,
+            name: self.name,
+            kind: self.kind.into(),
+            visibility: self.visibility.into(),
+            unread_count: self.unread_count as u64
+        }
+    }
+}
+
+pub struct DBRoomListing {
+    pub id: u64,
+    pub name: String,
+    pub visibility: DBRoomVisibility,
+    pub member_count: i64,
+    pub is_member: MySqlBool
+}
+
+impl Into<ChatRoomListing> for DBRoomListing {
+    fn into(self) -> ChatRoomListing {
+        ChatRoomListing {
+            room: ChatRoom {
+                id: self.id,
+                name: self.name,
+                kind: RoomKind::Group,
+                visibility: self.visibility.into(),
+                unread_count: 0
+            },
+            member_count: self.member_count as u64,
+            is_member: self.is_member.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, serde::Deserialize)]
+#[sqlx(rename_all = "UPPERCASE")]
+pub enum DBRoomKind {
+    Group,
+    Direct
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, serde::Deserialize)]
+#[sqlx(rename_all = "UPPERCASE")]
+pub enum DBRoomVisibility {
+    Private,
+    Public
+}
+
+impl From<RoomVisibility> for DBRoomVisibility {
+    fn from(visibility: RoomVisibility) -> Self {
+        match visibility {
+            RoomVisibility::Private => DBRoomVisibility::Private,
+            RoomVisibility::Public => DBRoomVisibility::Public
+        }
+    }
+}
+
+impl From<DBRoomVisibility> for RoomVisibility {
+    fn from(visibility: DBRoomVisibility) -> Self {
+        match visibility {
+            DBRoomVisibility::Private => RoomVisibility::Private,
+            DBRoomVisibility::Public => RoomVisibility::Public
+        }
+    }
+}
+
+impl From<DBRoomKind> for RoomKind {
+    fn from(kind: DBRoomKind) -> Self {
+        match kind {
+            DBRoomKind::Group => RoomKind::Group,
+            DBRoomKind::Direct => RoomKind::Direct
+        }
+    }
+}
+
+/// What becomes of a chat room once its last member leaves. Configured with
+/// the `EMPTY_ROOM_POLICY` environment variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmptyRoomPolicy {
+    /// Keep the room and its messages, marking the room as archived
+    Archive,
+    /// Delete the room along with its messages
+    Delete
+}
+
+impl FromStr for EmptyRoomPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "archive" => Ok(EmptyRoomPolicy::Archive),
+            "delete" => Ok(EmptyRoomPolicy::Delete),
+            _ => Err(format!("Unknown empty room policy: {}", s))
+        }
+    }
+}
+
+/// How long auth tokens last. Configured with the `SESSION_LIFETIME_SECS`
+/// and `SESSION_IDLE_TIMEOUT_SECS` environment variables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionPolicy {
+    /// Seconds after login that a token expires, however active it is
+    pub lifetime_secs: u64,
+    /// Seconds without use after which a token expires
+    pub idle_timeout_secs: u64
+}
+
+impl SessionPolicy {
+    /// Whether `session` has expired as of `now`
+    pub fn is_expired(&self, session: &DBSession, now: DateTime<Utc>) -> bool {
+        let age = (now - session.time_set).num_seconds();
+        let idle = (now - session.last_used).num_seconds();
+        age >= self.lifetime_secs as i64 || idle >= self.idle_timeout_secs as i64
+    }
+}
+
+/// The outcome of a member leaving a chat room.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DBRoomLeave {
+    Left,
+    /// The owner left, and ownership passed to the member with this user id
+    OwnershipTransferred(u64),
+    /// The last member left, and the room was cleaned up. Holds the storage
+    /// keys of attachments that were deleted along with the room, if any.
+    Emptied(Vec<String>)
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct DBRoomMember {
     pub(crate) user_id: u64,
-    pub(crate) username: String
+    pub(crate) username: String,
+    pub(crate) role: DBRoomRole
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, serde::Deserialize)]
+#[sqlx(rename_all = "UPPERCASE")]
+pub enum DBRoomRole {
+    Owner,
+    Admin,
+    Member
+}
+
+impl DBRoomRole {
+    /// The room permission matrix. Whether a member with this role may take
+    /// the provided `action`.
+    /// 
+    /// | Action                  | Owner | Admin | Member |
+    /// |-------------------------|-------|-------|--------|
+    /// | Rename                  | Yes   | Yes   | No     |
+    /// | AddMember               | Yes   | Yes   | No     |
+    /// | RemoveMember(Owner)     | No    | No    | No     |
+    /// | RemoveMember(Admin)     | Yes   | No    | No     |
+    /// | RemoveMember(Member)    | Yes   | Yes   | No     |
+    /// | DeleteMessage           | Yes   | Yes   | No     |
+    /// | PinMessage              | Yes   | Yes   | No     |
+    /// | ChangeRole              | Yes   | No    | No     |
+    /// | ManageInviteCodes       | Yes   | No    | No     |
+    /// | ChangeVisibility        | Yes   | No    | No     |
+    pub fn permits(&self, action: RoomAction) -> bool {
+        match (self, action) {
+            (_, RoomAction::RemoveMember(DBRoomRole::Owner)) => false,
+            (DBRoomRole::Owner, _) => true,
+            (DBRoomRole::Admin, RoomAction::Rename) => true,
+            (DBRoomRole::Admin, RoomAction::AddMember) => true,
+            (DBRoomRole::Admin, RoomAction::RemoveMember(DBRoomRole::Member)) => true,
+            (DBRoomRole::Admin, RoomAction::DeleteMessage) => true,
+            (DBRoomRole::Admin, RoomAction::PinMessage) => true,
+            (DBRoomRole::Admin, _) => false,
+            (DBRoomRole::Member, _) => false,
+        }
+    }
+}
+
+impl From<RoomRole> for DBRoomRole {
+    fn from(role: RoomRole) -> Self {
+        match role {
+            RoomRole::Owner => DBRoomRole::Owner,
+            RoomRole::Admin => DBRoomRole::Admin,
+            RoomRole::Member => DBRoomRole::Member
+        }
+    }
+}
+
+impl From<DBRoomRole> for RoomRole {
+    fn from(role: DBRoomRole) -> Self {
+        match role {
+            DBRoomRole::Owner => RoomRole::Owner,
+            DBRoomRole::Admin => RoomRole::Admin,
+            DBRoomRole::Member => RoomRole::Member
+        }
+    }
+}
+
+/// Actions within a room that are restricted by the role of the member
+/// taking them. See `DBRoomRole::permits`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoomAction {
+    Rename,
+    AddMember,
+    /// Remove another member, who has the provided role
+    RemoveMember(DBRoomRole),
+    /// Delete another member's message
+    DeleteMessage,
+    /// Pin or unpin any message
+    PinMessage,
+    ChangeRole,
+    /// Create, list and revoke the room's invite codes
+    ManageInviteCodes,
+    /// Make the room public or private
+    ChangeVisibility
+}
+
+impl RoomAction {
+    /// The reason given when a member is not permitted to take this action.
+    pub fn forbidden_reason(&self) -> &'static str {
+        match self {
+            RoomAction::Rename => "Only the owner or an admin can rename the room",
+            RoomAction::AddMember => "Only the owner or an admin can add members",
+            RoomAction::RemoveMember(DBRoomRole::Owner) => "The owner cannot be removed from the room",
+            RoomAction::RemoveMember(DBRoomRole::Admin) => "Only the owner can remove an admin",
+            RoomAction::RemoveMember(DBRoomRole::Member) => "Only the owner or an admin can remove members",
+            RoomAction::DeleteMessage => "Only the sender, the owner or an admin can delete a message",
+            RoomAction::PinMessage => "Only the owner or an admin can pin or unpin messages",
+            RoomAction::ChangeRole => "Only the owner can change member roles",
+            RoomAction::ManageInviteCodes => "Only the owner can manage invite codes",
+            RoomAction::ChangeVisibility => "Only the owner can change the room's visibility"
+        }
+    }
+}
+
+pub struct DBMessage {
+    pub id: u64,
+    pub room_id: u64,
+    pub sender_id: u64,
+    pub body: String,
+    pub time_sent: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub reply_to: Option<u64>,
+    pub thread_root_id: Option<u64>,
+    pub reply_count: i64
+}
+
+impl Into<ChatMessage> for DBMessage {
+    fn into(self) -> ChatMessage {
+        ChatMessage {
+            id: Some(self.id),
+            room_id: self.room_id,
+            sender_id: Some(self.sender_id),
+            body: self.body,
+            time_sent: Some(self.time_sent),
+            edited_at: self.edited_at,
+            deleted_at: self.deleted_at,
+            reactions: Vec::new(),
+            reply_to: self.reply_to,
+            thread_root_id: self.thread_root_id,
+            reply_count: self.reply_count as u64,
+            attachments: Vec::new()
+        }
+    }
+}
+
+pub struct DBPinnedMessage {
+    pub id: u64,
+    pub room_id: u64,
+    pub sender_id: u64,
+    pub body: String,
+    pub time_sent: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub reply_to: Option<u64>,
+    pub thread_root_id: Option<u64>,
+    pub reply_count: i64,
+    pub pinned_by: u64,
+    pub time_pinned: DateTime<Utc>
+}
+
+impl Into<ChatPinnedMessage> for DBPinnedMessage {
+    fn into(self) -> ChatPinnedMessage {
+        let message = DBMessage {
+            id: self.id,
+            room_id: self.room_id,
+            sender_id: self.sender_id,
+            body: self.body,
+            time_sent: self.time_sent,
+            edited_at: self.edited_at,
+            deleted_at: self.deleted_at,
+            reply_to: self.reply_to,
+            thread_root_id: self.thread_root_id,
+            reply_count: self.reply_count
+        };
+        ChatPinnedMessage {
+            message: message.into(),
+            pinned_by: self.pinned_by,
+            time_pinned: self.time_pinned
+        }
+    }
+}
+
+pub struct DBAttachment {
+    pub id: u64,
+    pub room_id: u64,
+    pub uploader_id: u64,
+    /// `None` until the upload is sent with a message
+    pub message_id: Option<u64>,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: u64,
+    pub storage_key: String,
+    pub has_thumbnail: MySqlBool,
+    /// Whether the message the attachment was sent with has been deleted
+    pub message_deleted: MySqlBool
+}
+
+impl Into<ChatAttachment> for &DBAttachment {
+    fn into(self) -> ChatAttachment {
+        ChatAttachment {
+            id: self.id,
+            file_name: self.file_name.clone(),
+            content_type: self.content_type.clone(),
+            size: self.size,
+            has_thumbnail: self.has_thumbnail.0
+        }
+    }
+}
+
+pub struct DBReactionCount {
+    pub message_id: u64,
+    pub emoji: String,
+    pub count: i64,
+    pub reacted: MySqlBool
+}
+
+impl Into<ReactionCount> for DBReactionCount {
+    fn into(self) -> ReactionCount {
+        ReactionCount {
+            emoji: self.emoji,
+            count: self.count as u64,
+            reacted: self.reacted.0
+        }
+    }
+}
+
+impl Into<ChatRoomMember> for &DBRoomMember {
+    fn into(self) -> ChatRoomMember {
+        ChatRoomMember {
+            id: self.user_id,
+            username: self.username.clone(),
+            role: self.role.into(),
+            presence: None
+        }
+    }
+}
+
+pub struct DBRoomInvitation {
+    pub room_id: u64,
+    pub room_name: String,
+    pub room_visibility: DBRoomVisibility,
+    pub inviter_id: u64,
+    pub inviter_username: String,
+    pub time_sent: DateTime<Utc>
+}
+
+impl Into<ChatRoomInvitation> for DBRoomInvitation {
+    fn into(self) -> ChatRoomInvitation {
+        ChatRoomInvitation {
+            room: ChatRoom {
+                id: self.room_id,
+                name: self.room_name,
+                kind: RoomKind::Group,
+                visibility: self.room_visibility.into(),
+                unread_count: 0
+            },
+            inviter: UserInfo { id: self.inviter_id, username: self.inviter_username },
+            time_sent: self.time_sent
+        }
+    }
+}
+
+pub struct DBRoomInviteCode {
+    pub code: String,
+    pub room_id: u64,
+    pub time_created: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_uses: Option<u64>,
+    pub uses: u64
+}
+
+impl Into<ChatRoomInviteCode> for DBRoomInviteCode {
+    fn into(self) -> ChatRoomInviteCode {
+        ChatRoomInviteCode {
+            code: self.code,
+            room_id: self.room_id,
+            time_created: self.time_created,
+            expires_at: self.expires_at,
+            max_uses: self.max_uses,
+            uses: self.uses
+        }
+    }
 }
 
 #[derive(sqlx::Type, serde::Deserialize)]
 #[sqlx(transparent)]
 pub struct MySqlBool (pub bool);
 
+pub struct DBSession {
+    pub session_id: String,
+    pub user_id: u64,
+    pub time_set: DateTime<Utc>,
+    pub last_used: DateTime<Utc>
+}
+
+pub struct DBTotp {
+    pub secret: Vec<u8>,
+    /// `None` until enrolment is confirmed
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<u64>
+}
+
+pub struct DBRecoveryCode {
+    pub id: u64,
+    pub code_hash: String
+}
+
 pub struct DBAuthInfo {
+    pub session_id: String,
     pub user_agent: String,
+    pub ip_address: Option<String>,
     pub time_set: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
     pub is_requester: MySqlBool
 }
 
 impl Into<LoginTokenInfo> for &DBAuthInfo {
     fn into(self) -> LoginTokenInfo {
         LoginTokenInfo {
+            id: self.session_id.clone(),
             user_agent: self.user_agent.clone(),
+            ip_address: self.ip_address.clone(),
             time_set: self.time_set,
+            last_used: self.last_used,
             is_requester: self.is_requester.0
         }
     }
 }
 
+/// When a user was last active, and whether the requesting user may see it
+pub struct DBLastSeen {
+    pub user_id: u64,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub last_seen_visible: MySqlBool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, serde::Deserialize)]
+#[sqlx(rename_all = "UPPERCASE")]
+pub enum DBLastSeenVisibility {
+    Everyone,
+    Friends
+}
+
+impl From<LastSeenVisibility> for DBLastSeenVisibility {
+    fn from(visibility: LastSeenVisibility) -> Self {
+        match visibility {
+            LastSeenVisibility::Everyone => DBLastSeenVisibility::Everyone,
+            LastSeenVisibility::Friends => DBLastSeenVisibility::Friends
+        }
+    }
+}
+
+impl From<DBLastSeenVisibility> for LastSeenVisibility {
+    fn from(visibility: DBLastSeenVisibility) -> Self {
+        match visibility {
+            DBLastSeenVisibility::Everyone => LastSeenVisibility::Everyone,
+            DBLastSeenVisibility::Friends => LastSeenVisibility::Friends
+        }
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct UserSearchParam {
     pub username: String
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct MessagePageParam {
+    pub before_id: Option<u64>,
+    pub after_id: Option<u64>,
+    pub limit: Option<u64>
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct MessageSearchParam {
+    pub q: String,
+    pub room_id: Option<u64>,
+    pub sender_id: Option<u64>,
+    /// Inclusive lower bound of when matching messages were sent
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound of when matching messages were sent
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<u64>
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DirectoryParam {
+    /// Only list rooms with this in their name
+    pub q: Option<String>,
+    pub after_id: Option<u64>,
+    pub limit: Option<u64>
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct MentionParam {
+    pub limit: Option<u64>
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ThreadParam {
+    pub after_id: Option<u64>,
+    pub limit: Option<u64>
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TicketParam {
+    pub ticket: String
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct EventStreamParam {
+    pub ticket: String,
+    /// As with the `Last-Event-ID` header, for clients that reconnect with a
+    /// new ticket and so cannot rely on the browser resending it
+    pub last_event_id: Option<u64>
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permits_follows_the_permission_matrix() {
+        use DBRoomRole::{Admin, Member, Owner};
+
+        // (action, owner, admin, member), as in the table on `permits`
+        let matrix = [
+            (RoomAction::Rename, true, true, false),
+            (RoomAction::AddMember, true, true, false),
+            (RoomAction::RemoveMember(Owner), false, false, false),
+            (RoomAction::RemoveMember(Admin), true, false, false),
+            (RoomAction::RemoveMember(Member), true, true, false),
+            (RoomAction::DeleteMessage, true, true, false),
+            (RoomAction::PinMessage, true, true, false),
+            (RoomAction::ChangeRole, true, false, false),
+            (RoomAction::ManageInviteCodes, true, false, false),
+            (RoomAction::ChangeVisibility, true, false, false)
+        ];
+        for (action, owner, admin, member) in matrix {
+            assert_eq!(Owner.permits(action), owner, "owner {action:?}");
+            assert_eq!(Admin.permits(action), admin, "admin {action:?}");
+            assert_eq!(Member.permits(action), member, "member {action:?}");
+        }
+    }
+
+    fn session(time_set: DateTime<Utc>, last_used: DateTime<Utc>) -> DBSession {
+        DBSession { session_id: String::new(), user_id: 1, time_set, last_used }
+    }
+
+    #[test]
+    fn sessions_expire_at_their_lifetime() {
+        let policy = SessionPolicy { lifetime_secs: 100, idle_timeout_secs: 1000 };
+        let login = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let now = login + chrono::Duration::seconds(99);
+        assert!(!policy.is_expired(&session(login, now), now));
+        let now = login + chrono::Duration::seconds(100);
+        assert!(policy.is_expired(&session(login, now), now));
+    }
+
+    #[test]
+    fn sessions_expire_when_idle() {
+        let policy = SessionPolicy { lifetime_secs: 1000, idle_timeout_secs: 10 };
+        let login = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let last_used = login + chrono::Duration::seconds(50);
+        assert!(!policy.is_expired(&session(login, last_used), last_used + chrono::Duration::seconds(9)));
+        assert!(policy.is_expired(&session(login, last_used), last_used + chrono::Duration::seconds(10)));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex}
+};
+
+use chrono::{DateTime, Duration, Utc};
+
+use common::{PresenceStatus, UserPresence};
+
+/// Users are away once they have been inactive for this many minutes
+const AWAY_AFTER_MINUTES: i64 = 5;
+
+/// Tracks which users have open chat sockets or event streams. Activity is
+/// recorded in the database as each user's last seen time.
+pub struct PresenceService {
+    /// Number of open connections, by user id
+    connections: Arc<Mutex<HashMap<u64, usize>>>
+}
+
+/// An open connection of a user, which is closed when dropped.
+pub struct Connection {
+    user_id: u64,
+    connections: Arc<Mutex<HashMap<u64, usize>>>
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(count) = connections.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                connections.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+impl PresenceService {
+    pub fn new() -> Self {
+        PresenceService { connections: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Record that the user specified by `user_id` has opened a connection,
+    /// which lasts until the returned `Connection` is dropped.
+    pub fn connect(&self, user_id: u64) -> Connection {
+        *self.connections.lock().unwrap().entry(user_id).or_insert(0) += 1;
+        Connection { user_id, connections: self.connections.clone() }
+    }
+
+    /// The presence of the user specified by `user_id`, who was last active at
+    /// `last_seen_at`. `last_seen_visible` is whether the requesting user may
+    /// see that time. If not, the status only tells whether the user is
+    /// connected, as being away would reveal how recently they were active.
+    pub fn presence(&self, user_id: u64, last_seen_at: Option<DateTime<Utc>>, last_seen_visible: bool) -> UserPresence {
+        let connected = self.connections.lock().unwrap().contains_key(&user_id);
+        if !last_seen_visible {
+            let status = match connected {
+                true => PresenceStatus::Online,
+                false => PresenceStatus::Offline,
+            };
+            return UserPresence { status, last_seen: None }
+        }
+        let recently_active = last_seen_at.is_some_and(|at| Utc::now() - at < Duration::minutes(AWAY_AFTER_MINUTES));
+
+        let status = match (connected, recently_active) {
+            (true, true) => PresenceStatus::Online,
+            (true, false) | (false, true) => PresenceStatus::Away,
+            (false, false) => PresenceStatus::Offline,
+        };
+        UserPresence { status, last_seen: last_seen_at }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration
+};
+
+use actix_web::web::Bytes;
+use futures_util::{stream, Stream};
+use tokio::{
+    sync::broadcast::{
+        self,
+        error::RecvError,
+        Receiver,
+        Sender
+    },
+    time::{self, Interval}
+};
+
+use common::{RoomEvent, RoomEventKind};
+
+use crate::presence::Connection;
+
+const DELIVERY_BUFFER_SIZE: usize = 256;
+const EVENT_HISTORY_SIZE: usize = 1024;
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A room event to be pushed to each user in `recipients` that has an open
+/// chat socket or event stream.
+#[derive(Debug)]
+pub struct Delivery {
+    pub recipients: Vec<u64>,
+    pub event: RoomEvent
+}
+
+/// Deliveries published before a subscription was made, followed by a
+/// receiver for all later deliveries.
+pub struct Subscription {
+    pub missed: Vec<Arc<Delivery>>,
+    /// `false` when some of the requested missed events are no longer held,
+    /// meaning the subscriber must re-fetch its state.
+    pub missed_complete: bool,
+    pub receiver: Receiver<Arc<Delivery>>
+}
+
+struct EventLog {
+    next_event_id: u64,
+    history: VecDeque<Arc<Delivery>>
+}
+
+pub struct RealtimeService {
+    sender: Sender<Arc<Delivery>>,
+    log: Mutex<EventLog>
+}
+
+impl RealtimeService {
+    /// Create the real-time event bus. Event ids start from 1 and increase by
+    /// one for every published event.
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(DELIVERY_BUFFER_SIZE);
+        let log = EventLog {
+            next_event_id: 1,
+            history: VecDeque::with_capacity(EVENT_HISTORY_SIZE)
+        };
+        RealtimeService { sender, log: Mutex::new(log) }
+    }
+
+    /// Publish an event in the room specified by `room_id` to `recipients`.
+    ///
+    /// This should only be called once the change the event describes has been
+    /// successfully written to the database.
+    pub fn publish(&self, room_id: u64, recipients: Vec<u64>, kind: RoomEventKind) {
+        let mut log = self.log.lock().unwrap();
+
+        let event = RoomEvent { id: log.next_event_id, room_id, kind };
+        let delivery = Arc::new(Delivery { recipients, event });
+        log.next_event_id += 1;
+
+        if log.history.len() == EVENT_HISTORY_SIZE {
+            log.history.pop_front();
+        }
+        log.history.push_back(delivery.clone());
+
+        // Sending only fails when there are no subscribers to deliver to.
+        // Sent while the log is held so that receivers observe ids in order.
+        let _ = self.sender.send(delivery);
+    }
+
+    /// Publish an ephemeral event in the room specified by `room_id` to
+    /// `recipients`. Ephemeral events have an id of 0, and are only pushed to
+    /// current subscribers rather than being held for those that reconnect.
+    pub fn publish_ephemeral(&self, room_id: u64, recipients: Vec<u64>, kind: RoomEventKind) {
+        let event = RoomEvent { id: 0, room_id, kind };
+        let _ = self.sender.send(Arc::new(Delivery { recipients, event }));
+    }
+
+    /// Subscribe to all deliveries published from now on. It is up to the
+    /// subscriber to filter out deliveries that it is not a recipient of.
+    pub fn subscribe(&self) -> Receiver<Arc<Delivery>> {
+        self.sender.subscribe()
+    }
+
+    /// Subscribe to all deliveries, including held deliveries with an event id
+    /// greater than `last_event_id`.
+    pub fn subscribe_from(&self, last_event_id: Option<u64>) -> Subscription {
+        let log = self.log.lock().unwrap();
+        let receiver = self.sender.subscribe();
+
+        let last_event_id = match last_event_id {
+            Some(id) => id,
+            None => return Subscription { missed: Vec::new(), missed_complete: true, receiver },
+        };
+
+        let missed = log.history.iter()
+            .filter(|delivery| delivery.event.id > last_event_id)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // Complete if the event following `last_event_id` is still held (or
+        // has not happened yet). Ids from before a restart are never complete.
+        let oldest_held_id = log.history.front()
+            .map(|delivery| delivery.event.id)
+            .unwrap_or(log.next_event_id);
+        let missed_complete = last_event_id < log.next_event_id
+            && oldest_held_id <= last_event_id + 1;
+
+        Subscription { missed, missed_complete, receiver }
+    }
+
+    /// Create a Server-Sent Events stream of the events delivered to `user_id`,
+    /// resuming after `last_event_id` if provided. The user's `connection` is
+    /// held open until the stream is dropped.
+    ///
+    /// A `resync` event is sent when events have been missed and cannot be
+    /// resent. Comments are periodically sent to keep idle connections open.
+    pub fn event_stream(&self, user_id: u64, last_event_id: Option<u64>, connection: Connection) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+        let subscription = self.subscribe_from(last_event_id);
+
+        let mut keep_alive = time::interval(KEEP_ALIVE_INTERVAL);
+        keep_alive.reset();
+
+        let state = EventStreamState {
+            user_id,
+            resync_needed: !subscription.missed_complete,
+            pending: subscription.missed.into(),
+            receiver: subscription.receiver,
+            keep_alive,
+            _connection: connection
+        };
+
+        stream::unfold(state, |mut state| async move {
+            let frame = state.next_frame().await?;
+            Some((Ok(frame), state))
+        })
+    }
+}
+
+struct EventStreamState {
+    user_id: u64,
+    resync_needed: bool,
+    pending: VecDeque<Arc<Delivery>>,
+    receiver: Receiver<Arc<Delivery>>,
+    keep_alive: Interval,
+    _connection: Connection
+}
+
+impl EventStreamState {
+    /// Wait for the next frame to send. `None` ends the stream.
+    async fn next_frame(&mut self) -> Option<Bytes> {
+        loop {
+            if self.resync_needed {
+                self.resync_needed = false;
+                return Some(Bytes::from_static(b"event: resync\ndata: \n\n"));
+            }
+
+            if let Some(delivery) = self.pending.pop_front() {
+                if delivery.recipients.contains(&self.user_id) {
+                    return Some(sse_frame(&delivery.event));
+                }
+                continue;
+            }
+
+            tokio::select! {
+                delivery = self.receiver.recv() => match delivery {
+                    Ok(delivery) => self.pending.push_back(delivery),
+                    Err(RecvError::Lagged(_)) => self.resync_needed = true,
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.keep_alive.tick() => return Some(Bytes::from_static(b": keep-alive\n\n")),
+            }
+        }
+    }
+}
+
+fn sse_frame(event: &RoomEvent) -> Bytes {
+    // Serialising these types does not fail
+    let data = serde_json::to_string(event).unwrap();
+    // Ephemeral events leave the client's last event id unchanged
+    if event.id == 0 {
+        return Bytes::from(format!("event: room_event\ndata: {}\n\n", data));
+    }
+    Bytes::from(format!("id: {}\nevent: room_event\ndata: {}\n\n", event.id, data))
+}
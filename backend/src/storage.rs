@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    io,
+    path::PathBuf
+};
+
+/// Where the contents of attachments are kept. Contents are written once
+/// under a key generated by the caller, and never modified.
+/// 
+/// Methods block, so should be called via `actix_web::web::block`.
+pub trait AttachmentStorage: Send + Sync {
+    fn put(&self, key: &str, contents: &[u8]) -> io::Result<()>;
+    fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    /// Removing a key that does not exist is not an error
+    fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Keeps attachments as files in a directory on local disk, named by key
+pub struct LocalStorage {
+    root: PathBuf
+}
+
+impl LocalStorage {
+    /// Use the directory at `root`, creating it if it does not exist
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(LocalStorage { root })
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        // Keys are generated, but never let one escape the directory
+        let is_plain_name = !key.is_empty()
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+            && !key.starts_with('.');
+        if !is_plain_name {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid storage key"))
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+impl AttachmentStorage for LocalStorage {
+    fn put(&self, key: &str, contents: &[u8]) -> io::Result<()> {
+        fs::write(self.path(key)?, contents)
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(key)?)
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant}
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use common::{AuthToken, AUTH_TOKEN_BYTES};
+
+/// Tickets must be redeemed within this many seconds of being issued
+pub const TICKET_LIFETIME_SECS: u64 = 30;
+const TICKET_LIFETIME: Duration = Duration::from_secs(TICKET_LIFETIME_SECS);
+
+struct IssuedTicket {
+    token: AuthToken,
+    issued_at: Instant
+}
+
+/// Issues tickets that stand in for an auth token where the browser cannot
+/// send one in a header, such as chat sockets and image loads. A ticket is
+/// put in the address in place of the token, so it can only be used once and
+/// expires after `TICKET_LIFETIME_SECS`. Nothing is persisted.
+pub struct TicketService {
+    issued: Mutex<HashMap<AuthToken, IssuedTicket>>
+}
+
+impl TicketService {
+    pub fn new() -> Self {
+        TicketService { issued: Mutex::new(HashMap::new()) }
+    }
+
+    /// Issue a new ticket standing in for the auth `token`.
+    pub fn issue(&self, token: AuthToken) -> AuthToken {
+        let mut issued = self.issued.lock().unwrap();
+        let now = Instant::now();
+
+        // Forget tickets that were never redeemed
+        issued.retain(|_, ticket| now.duration_since(ticket.issued_at) < TICKET_LIFETIME);
+
+        // Tickets are as hard to guess as the tokens they stand in for
+        let mut bytes = [0; AUTH_TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        let ticket = AuthToken(bytes);
+        issued.insert(ticket, IssuedTicket { token, issued_at: now });
+        ticket
+    }
+
+    /// Use up the provided `ticket`, returning the auth token it was issued
+    /// for, unless it has expired or was already redeemed.
+    pub fn redeem(&self, ticket: &AuthToken) -> Option<AuthToken> {
+        let issued = self.issued.lock().unwrap().remove(ticket)?;
+        match issued.issued_at.elapsed() < TICKET_LIFETIME {
+            true => Some(issued.token),
+            false => None
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,176 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+/// Number of recovery codes given out when enrolment is confirmed
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Length of a TOTP secret. 160 bits, as recommended by RFC 4226.
+const SECRET_BYTES: usize = 20;
+/// Name of the service shown in authenticator apps
+const ISSUER: &str = "Chat";
+/// Seconds that each code is valid for
+const STEP_SECS: u64 = 30;
+const CODE_DIGITS: u32 = 6;
+/// Codes this many steps either side of the current one are accepted, to
+/// allow for clock drift
+const ALLOWED_DRIFT_STEPS: u64 = 1;
+/// Characters of recovery codes, either side of a dash
+const RECOVERY_CODE_HALF_LEN: usize = 5;
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A new random secret to generate codes from.
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_BYTES];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// The secret as unpadded base32, which is how authenticator apps take it.
+pub fn encode_secret(secret: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in secret.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer.iter().fold(0u64, |bits, byte| bits << 8 | *byte as u64);
+        // Each 5 bits of the chunk is one character
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            let index = (bits >> (35 - i * 5)) & 0b11111;
+            encoded.push(BASE32_ALPHABET[index as usize] as char);
+        }
+    }
+    encoded
+}
+
+/// The `otpauth://` URI of the secret, which authenticator apps can scan as
+/// a QR code. `username` must be alphanumeric, as all usernames are.
+pub fn otpauth_uri(secret: &[u8], username: &str) -> String {
+    format!(
+        "otpauth://totp/{ISSUER}:{username}?secret={}&issuer={ISSUER}&algorithm=SHA1&digits={CODE_DIGITS}&period={STEP_SECS}",
+        encode_secret(secret)
+    )
+}
+
+/// The time step that `code` is valid for, if it is valid at `unix_time`
+/// with the `secret`.
+pub fn matching_step(secret: &[u8], code: &str, unix_time: u64) -> Option<u64> {
+    if !is_totp_code(code) {
+        return None
+    }
+    let code: u32 = code.parse().ok()?;
+    let current_step = unix_time / STEP_SECS;
+
+    (current_step.saturating_sub(ALLOWED_DRIFT_STEPS)..=current_step + ALLOWED_DRIFT_STEPS)
+        .find(|step| code_at_step(secret, *step) == code)
+}
+
+/// Whether `code` is in the format of a TOTP code, rather than a recovery code
+pub fn is_totp_code(code: &str) -> bool {
+    code.len() == CODE_DIGITS as usize && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The HOTP value of the `secret` for the counter `step`, per RFC 4226.
+fn code_at_step(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset], digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    (value & 0x7fff_ffff) % 10u32.pow(CODE_DIGITS)
+}
+
+/// A new single use recovery code of lowercase base32, such as `abcde-23456`.
+pub fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; RECOVERY_CODE_HALF_LEN * 2];
+    OsRng.fill_bytes(&mut bytes);
+    let chars: Vec<char> = bytes.iter()
+        .map(|byte| BASE32_ALPHABET[(*byte & 0b11111) as usize].to_ascii_lowercase() as char)
+        .collect();
+
+    format!(
+        "{}-{}",
+        chars[..RECOVERY_CODE_HALF_LEN].iter().collect::<String>(),
+        chars[RECOVERY_CODE_HALF_LEN..].iter().collect::<String>()
+    )
+}
+
+/// A recovery code as entered by the user, in the form it was hashed in.
+/// Case and surrounding whitespace are ignored.
+pub fn normalise_recovery_code(code: &str) -> String {
+    code.trim().to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 secret of the RFC 4226 and RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn hotp_matches_rfc_4226() {
+        let expected = [755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489];
+        for (step, code) in expected.into_iter().enumerate() {
+            assert_eq!(code_at_step(RFC_SECRET, step as u64), code, "counter {step}");
+        }
+    }
+
+    #[test]
+    fn totp_matches_rfc_6238() {
+        // The last six digits of the eight digit SHA-1 values in Appendix B
+        let expected = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130")
+        ];
+        for (unix_time, code) in expected {
+            assert_eq!(matching_step(RFC_SECRET, code, unix_time), Some(unix_time / STEP_SECS), "time {unix_time}");
+        }
+    }
+
+    #[test]
+    fn codes_from_adjacent_steps_are_accepted() {
+        let unix_time = 1234567890;
+        let step = unix_time / STEP_SECS;
+        let code_at = |step| format!("{:06}", code_at_step(RFC_SECRET, step));
+
+        assert_eq!(matching_step(RFC_SECRET, &code_at(step - 1), unix_time), Some(step - 1));
+        assert_eq!(matching_step(RFC_SECRET, &code_at(step + 1), unix_time), Some(step + 1));
+        assert_eq!(matching_step(RFC_SECRET, &code_at(step + 2), unix_time), None);
+        assert_eq!(matching_step(RFC_SECRET, "12345", unix_time), None);
+        assert_eq!(matching_step(RFC_SECRET, "abcdef", unix_time), None);
+    }
+
+    #[test]
+    fn base32_matches_rfc_4648() {
+        // Appendix 10 of RFC 4648, without the padding
+        let expected = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI")
+        ];
+        for (secret, encoded) in expected {
+            assert_eq!(encode_secret(secret.as_bytes()), encoded);
+        }
+        assert_eq!(encode_secret(RFC_SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    }
+
+    #[test]
+    fn recovery_codes() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), RECOVERY_CODE_HALF_LEN * 2 + 1);
+        assert!(!is_totp_code(&code));
+        assert_eq!(normalise_recovery_code(&format!(" {} ", code.to_uppercase())), code);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,61 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant}
+};
+
+use common::TYPING_TIMEOUT_SECS;
+
+const TYPING_TIMEOUT: Duration = Duration::from_secs(TYPING_TIMEOUT_SECS);
+/// Typing signals repeated within this long of the last published signal are
+/// not published again, as the indicator is still showing
+const REPUBLISH_INTERVAL: Duration = Duration::from_secs(TYPING_TIMEOUT_SECS / 2);
+
+/// Tracks which users are typing in each room. Nothing is persisted, typing
+/// signals are only relevant for a few seconds.
+pub struct TypingService {
+    /// When a typing signal was last published for each user, by room id and
+    /// then user id
+    published: Mutex<HashMap<u64, HashMap<u64, Instant>>>
+}
+
+impl TypingService {
+    pub fn new() -> Self {
+        TypingService { published: Mutex::new(HashMap::new()) }
+    }
+
+    /// Record that the user specified by `user_id` is typing in the room
+    /// specified by `room_id`. Returns whether a typing signal should be
+    /// published to the other members of the room.
+    pub fn start_typing(&self, room_id: u64, user_id: u64) -> bool {
+        let mut published = self.published.lock().unwrap();
+        let now = Instant::now();
+
+        // Forget expired signals, and the rooms left without any
+        published.retain(|_, room| {
+            room.retain(|_, at| now.duration_since(*at) < TYPING_TIMEOUT);
+            !room.is_empty()
+        });
+
+        let room = published.entry(room_id).or_default();
+        match room.get(&user_id) {
+            Some(at) if now.duration_since(*at) < REPUBLISH_INTERVAL => false,
+            _ => {
+                room.insert(user_id, now);
+                true
+            }
+        }
+    }
+
+    /// Record that the user specified by `user_id` is no longer typing in the
+    /// room specified by `room_id`, such as once they have sent their message.
+    pub fn stop_typing(&self, room_id: u64, user_id: u64) {
+        let mut published = self.published.lock().unwrap();
+        if let Some(room) = published.get_mut(&room_id) {
+            room.remove(&user_id);
+            if room.is_empty() {
+                published.remove(&room_id);
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod markdown;
+
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 
@@ -16,23 +18,181 @@ pub struct AccountPasswordChange {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LoginResponse {
     pub user_id: u64,
+    /// An `AuthToken`, as a string
     pub token: String
 }
 
+/// Number of random bytes in an auth token
+pub const AUTH_TOKEN_BYTES: usize = 32;
+
+/// A bearer token granting access to a user account. Written as lowercase hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AuthToken(pub [u8; AUTH_TOKEN_BYTES]);
+
+impl std::fmt::Display for AuthToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for AuthToken {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != AUTH_TOKEN_BYTES * 2 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Auth token must be 64 hex characters".to_string());
+        }
+        let mut bytes = [0; AUTH_TOKEN_BYTES];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|e| e.to_string())?;
+        }
+        Ok(AuthToken(bytes))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct LoginTokenInfo {
+    /// Identifies the session when revoking it, without revealing its token
+    pub id: String,
     pub user_agent: String,
+    /// Address of the client, recorded along with `user_agent`
+    pub ip_address: Option<String>,
     pub time_set: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
     pub is_requester: bool
 }
 
+/// Authenticates a single chat socket, event stream or attachment download in
+/// place of an auth token, for requests that cannot carry a header. Written
+/// like an `AuthToken`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConnectionTicket {
+    pub ticket: String,
+    pub expires_at: DateTime<Utc>
+}
+
+/// Returned by login in place of a `LoginResponse` when the account has
+/// two-factor authentication enabled. The login is completed by sending the
+/// challenge back with a code.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoginChallenge {
+    pub challenge: String,
+    pub expires_at: DateTime<Utc>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoginTotpRequest {
+    pub challenge: String,
+    /// A code from the user's authenticator app, or one of their recovery codes
+    pub code: String
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    /// Recovery codes that have not been used yet
+    pub recovery_codes_remaining: u64
+}
+
+/// A secret to add to an authenticator app, which is not enabled until a
+/// code generated from it is confirmed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TotpEnrolment {
+    /// The secret, as base32
+    pub secret: String,
+    /// The secret as an `otpauth://` URI, for showing as a QR code
+    pub otpauth_uri: String
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TotpCode {
+    pub code: String
+}
+
+/// Single use codes that can be entered in place of a TOTP code. Only shown
+/// once, when two-factor authentication is enabled.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TotpRecoveryCodes {
+    pub codes: Vec<String>
+}
+
+/// Who can see when the user was last seen. Users can always see their own.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum LastSeenVisibility {
+    #[default]
+    Everyone,
+    Friends
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PrivacySettings {
+    pub last_seen_visibility: LastSeenVisibility
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum RoomKind {
+    #[default]
+    Group,
+    /// A conversation between exactly two friends. Direct rooms are named
+    /// after the other member, and cannot be renamed or have members added.
+    Direct
+}
+
+/// Public rooms are listed in the room directory, and can be joined by anyone
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum RoomVisibility {
+    #[default]
+    Private,
+    Public
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ChatRoom {
     pub id: u64,
     pub name: String,
+    #[serde(default)]
+    pub kind: RoomKind,
+    #[serde(default)]
+    pub visibility: RoomVisibility,
+    /// Messages from other members that the user has not read. Only counted
+    /// when listing the user's rooms.
+    #[serde(default)]
+    pub unread_count: u64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatReadMarker {
+    /// The latest message read
+    pub message_id: u64
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+pub struct ChatRoomVisibility {
+    pub visibility: RoomVisibility
+}
+
+/// A public room as listed in the room directory
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatRoomListing {
+    pub room: ChatRoom,
+    pub member_count: u64,
+    /// Whether the requesting user is a member of the room
+    pub is_member: bool
+}
+
+/// A page of the room directory, in room id order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatRoomDirectoryPage {
+    pub rooms: Vec<ChatRoomListing>,
+    /// The cursor to request the following page with, or `None` if there are
+    /// no more rooms
+    pub next_cursor: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ChatRoomName {
     pub room_name: String,
 }
@@ -43,19 +203,146 @@ pub enum ChatRoomManageUserAction {
     RemoveUser
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RoomRole {
+    Owner,
+    Admin,
+    Member
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatRoomMember {
+    pub id: u64,
+    pub username: String,
+    pub role: RoomRole,
+    /// Only included when listing a room's members
+    #[serde(default)]
+    pub presence: Option<UserPresence>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatRoomRoleUpdate {
+    pub role: RoomRole
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChatRoomManageUser {
     pub user_id: u64,
     pub action: ChatRoomManageUserAction
 }
 
+/// A pending invitation for the requesting user to join a chat room
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatRoomInvitation {
+    pub room: ChatRoom,
+    pub inviter: UserInfo,
+    pub time_sent: DateTime<Utc>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ChatRoomInviteCodeRequest {
+    /// No expiry when `None`
+    pub expires_at: Option<DateTime<Utc>>,
+    /// No usage limit when `None`
+    pub max_uses: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatRoomInviteCode {
+    pub code: String,
+    pub room_id: u64,
+    pub time_created: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_uses: Option<u64>,
+    pub uses: u64
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ChatMessage {
     pub id: Option<u64>,  // is 2^64 enough? also in schema
     pub room_id: u64,
     pub sender_id: Option<u64>,
     pub body: String,
-    pub time_sent: Option<DateTime<Utc>>
+    pub time_sent: Option<DateTime<Utc>>,
+    pub edited_at: Option<DateTime<Utc>>,
+    /// Deleted messages are returned with an empty body
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub reactions: Vec<ReactionCount>,
+    /// The message being replied to, if any
+    #[serde(default)]
+    pub reply_to: Option<u64>,
+    /// The top-level message of the thread a reply belongs to
+    #[serde(default)]
+    pub thread_root_id: Option<u64>,
+    #[serde(default)]
+    pub reply_count: u64,
+    /// Files uploaded via `POST /chat/{room_id}/attachments`. When sending a
+    /// message, only the ids of the uploads are used.
+    #[serde(default)]
+    pub attachments: Vec<ChatAttachment>
+}
+
+/// A message pinned in its room
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatPinnedMessage {
+    pub message: ChatMessage,
+    /// The member that pinned the message
+    pub pinned_by: u64,
+    pub time_pinned: DateTime<Utc>
+}
+
+/// A file attached to a message
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatAttachment {
+    pub id: u64,
+    pub file_name: String,
+    pub content_type: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Whether a thumbnail was generated, which is the case for images
+    pub has_thumbnail: bool
+}
+
+/// A page of messages, oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessagePage {
+    pub messages: Vec<ChatMessage>,
+    /// The cursor to request the following page with, or `None` if there are
+    /// no more messages in the paging direction
+    pub next_cursor: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: u64,
+    /// Whether the requesting user is one of the reactors
+    pub reacted: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReactionChange {
+    pub message_id: u64,
+    pub user_id: u64,
+    pub emoji: String
+}
+
+/// The body of a message sent to a friend via their direct room
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatDirectMessage {
+    pub body: String
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatMessageEdit {
+    pub body: String
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessageRevision {
+    pub body: String,
+    pub time_set: DateTime<Utc>
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -64,6 +351,32 @@ pub struct UserInfo {
     pub username: String
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum PresenceStatus {
+    /// Connected for real-time events, and recently active
+    Online,
+    /// Connected but idle, or recently active without being connected
+    Away,
+    #[default]
+    Offline
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct UserPresence {
+    pub status: PresenceStatus,
+    /// When the user was last active. `None` if they have never been active,
+    /// or have hidden it with their privacy settings.
+    pub last_seen: Option<DateTime<Utc>>
+}
+
+/// A user along with their presence
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserPresenceInfo {
+    #[serde(flatten)]
+    pub user: UserInfo,
+    pub presence: UserPresence
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum UserAssociationType {
     Friend,
@@ -79,8 +392,43 @@ pub struct UserAssociationUpdate {
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct UserAssociations {
-    pub friends: Vec<UserInfo>,
+    pub friends: Vec<UserPresenceInfo>,
     pub incoming_requests: Vec<UserInfo>,
     pub unaccepted_requests: Vec<UserInfo>,
     pub blocked: Vec<UserInfo>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RoomEventKind {
+    MessageSent(ChatMessage),
+    MessageEdited(ChatMessage),
+    MessageDeleted(ChatMessage),
+    ReactionAdded(ReactionChange),
+    ReactionRemoved(ReactionChange),
+    MessagePinned(ChatPinnedMessage),
+    /// Holds the id of the message that is no longer pinned
+    MessageUnpinned(u64),
+    MemberAdded(ChatRoomMember),
+    MemberRemoved(UserInfo),
+    MemberRoleChanged(ChatRoomMember),
+    RoomRenamed(ChatRoomName),
+    /// A member is typing a message. Lasts for `TYPING_TIMEOUT_SECS` unless
+    /// repeated, or until the member sends a message.
+    Typing(UserInfo)
+}
+
+/// How long a typing indicator is shown for after the last `Typing` event
+pub const TYPING_TIMEOUT_SECS: u64 = 5;
+
+/// Sent over a chat socket in place of room events that were skipped, as the
+/// socket fell behind. Clients must re-fetch any state they hold.
+pub const SOCKET_RESYNC_MESSAGE: &str = "resync";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoomEvent {
+    /// Ephemeral events, such as `Typing`, have an id of 0 and are never
+    /// resent to clients that missed them
+    pub id: u64,
+    pub room_id: u64,
+    pub kind: RoomEventKind
 }
\ No newline at end of file
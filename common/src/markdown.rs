@@ -0,0 +1,605 @@
+//! The subset of markdown supported in message bodies: bold, italics, inline
+//! and fenced code, links, lists, quotes and `@username` mentions.
+//!
+//! Bodies are parsed into a tree of `Block`s and `Inline`s, which clients
+//! render without ever treating the body as HTML. Parsing never fails, as
+//! anything that is not recognised is kept as text.
+
+use serde::{Serialize, Deserialize};
+
+/// Quotes and lists nested deeper than this are kept as text
+const MAX_NESTING: usize = 8;
+/// Closing markers for emphasis, links and code spans are only looked for
+/// this many characters ahead, so that unclosed markers cannot make parsing
+/// quadratic
+const MAX_INLINE_SCAN: usize = 500;
+/// Link addresses must use one of these schemes
+const SAFE_URL_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    /// A fenced code block, with the language named after the opening fence
+    CodeBlock { language: Option<String>, code: String },
+    Quote(Vec<Block>),
+    BulletList(Vec<Vec<Block>>),
+    /// A numbered list, counting up from `start`
+    OrderedList { start: u64, items: Vec<Vec<Block>> }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Code(String),
+    Link { text: Vec<Inline>, url: String },
+    /// An `@username` mention, holding the username without the `@`. The
+    /// user may not exist.
+    Mention(String),
+    LineBreak
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkdownError {
+    /// A link address does not use http, https or mailto
+    UnsafeLink(String)
+}
+
+/// Parse a message `body` into blocks.
+pub fn parse(body: &str) -> Vec<Block> {
+    let lines: Vec<&str> = body.lines().collect();
+    parse_blocks(&lines, 0)
+}
+
+/// Check that a message `body` can be sent, which is the case unless it links
+/// to an unsafe address.
+pub fn validate(body: &str) -> Result<(), MarkdownError> {
+    validate_blocks(&parse(body))
+}
+
+/// The usernames mentioned in a message `body`, in the order they are first
+/// mentioned. Mentions inside code are not counted.
+pub fn mentions(body: &str) -> Vec<String> {
+    let mut usernames = Vec::new();
+    collect_block_mentions(&parse(body), &mut usernames);
+    usernames
+}
+
+/// Whether `url` may be linked to, rather than shown as text
+pub fn is_safe_url(url: &str) -> bool {
+    SAFE_URL_SCHEMES.iter().any(|scheme| {
+        url.len() > scheme.len() && url.get(..scheme.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    })
+}
+
+fn validate_blocks(blocks: &[Block]) -> Result<(), MarkdownError> {
+    for block in blocks {
+        match block {
+            Block::Paragraph(inlines) => validate_inlines(inlines)?,
+            Block::CodeBlock { .. } => {},
+            Block::Quote(blocks) => validate_blocks(blocks)?,
+            Block::BulletList(items) | Block::OrderedList { items, .. } => {
+                for item in items {
+                    validate_blocks(item)?;
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+fn validate_inlines(inlines: &[Inline]) -> Result<(), MarkdownError> {
+    for inline in inlines {
+        match inline {
+            Inline::Bold(inlines) | Inline::Italic(inlines) => validate_inlines(inlines)?,
+            Inline::Link { text, url } => {
+                if !is_safe_url(url) {
+                    return Err(MarkdownError::UnsafeLink(url.clone()))
+                }
+                validate_inlines(text)?;
+            },
+            Inline::Text(_) | Inline::Code(_) | Inline::Mention(_) | Inline::LineBreak => {},
+        }
+    }
+    Ok(())
+}
+
+fn collect_block_mentions(blocks: &[Block], usernames: &mut Vec<String>) {
+    for block in blocks {
+        match block {
+            Block::Paragraph(inlines) => collect_inline_mentions(inlines, usernames),
+            Block::CodeBlock { .. } => {},
+            Block::Quote(blocks) => collect_block_mentions(blocks, usernames),
+            Block::BulletList(items) | Block::OrderedList { items, .. } => {
+                for item in items {
+                    collect_block_mentions(item, usernames);
+                }
+            },
+        }
+    }
+}
+
+fn collect_inline_mentions(inlines: &[Inline], usernames: &mut Vec<String>) {
+    for inline in inlines {
+        match inline {
+            Inline::Bold(inlines) | Inline::Italic(inlines) => collect_inline_mentions(inlines, usernames),
+            Inline::Link { text, .. } => collect_inline_mentions(text, usernames),
+            Inline::Mention(username) => {
+                if !usernames.iter().any(|known| known.eq_ignore_ascii_case(username)) {
+                    usernames.push(username.clone());
+                }
+            },
+            Inline::Text(_) | Inline::Code(_) | Inline::LineBreak => {},
+        }
+    }
+}
+
+// Blocks
+
+/// The kind of block a line starts
+enum LineStart<'a> {
+    Fence { language: Option<String> },
+    Quote(&'a str),
+    Bullet(&'a str),
+    Numbered { number: u64, rest: &'a str },
+    Blank,
+    Text
+}
+
+fn line_start(line: &str, depth: usize) -> LineStart<'_> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() {
+        return LineStart::Blank
+    }
+    if let Some(info) = trimmed.strip_prefix("```") {
+        let language = info.split_whitespace().next().map(str::to_string);
+        return LineStart::Fence { language }
+    }
+    if depth >= MAX_NESTING {
+        return LineStart::Text
+    }
+    if let Some(rest) = trimmed.strip_prefix('>') {
+        return LineStart::Quote(rest.strip_prefix(' ').unwrap_or(rest))
+    }
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(rest) = trimmed.strip_prefix(bullet) {
+            return LineStart::Bullet(rest)
+        }
+    }
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    if (1..=9).contains(&digits) {
+        let rest = &trimmed[digits..];
+        if let Some(rest) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+            if let Ok(number) = trimmed[..digits].parse() {
+                return LineStart::Numbered { number, rest }
+            }
+        }
+    }
+    LineStart::Text
+}
+
+/// The number of columns a line is indented by
+fn indent(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+fn parse_blocks(lines: &[&str], depth: usize) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        match line_start(lines[i], depth) {
+            LineStart::Blank => i += 1,
+            LineStart::Fence { language } => {
+                // Runs until the closing fence, or the end of the body
+                let fence_indent = indent(lines[i]);
+                let mut code_lines = Vec::new();
+                i += 1;
+                while i < lines.len() && !lines[i].trim_start().starts_with("```") {
+                    code_lines.push(strip_indent(lines[i], fence_indent));
+                    i += 1;
+                }
+                i += 1;
+                blocks.push(Block::CodeBlock { language, code: code_lines.join("\n") });
+            },
+            LineStart::Quote(_) => {
+                let mut quoted = Vec::new();
+                while i < lines.len() {
+                    match line_start(lines[i], depth) {
+                        LineStart::Quote(rest) => quoted.push(rest),
+                        _ => break,
+                    }
+                    i += 1;
+                }
+                blocks.push(Block::Quote(parse_blocks(&quoted, depth + 1)));
+            },
+            LineStart::Bullet(_) => {
+                let (items, next) = parse_list_items(lines, i, depth, false);
+                blocks.push(Block::BulletList(items));
+                i = next;
+            },
+            LineStart::Numbered { number, .. } => {
+                let (items, next) = parse_list_items(lines, i, depth, true);
+                blocks.push(Block::OrderedList { start: number, items });
+                i = next;
+            },
+            LineStart::Text => {
+                // Runs until a blank line or the start of another block
+                let mut text_lines = vec![lines[i].trim()];
+                i += 1;
+                while i < lines.len() {
+                    match line_start(lines[i], depth) {
+                        LineStart::Text => text_lines.push(lines[i].trim()),
+                        _ => break,
+                    }
+                    i += 1;
+                }
+                blocks.push(Block::Paragraph(parse_inlines(&text_lines.join("\n"), depth)));
+            },
+        }
+    }
+
+    blocks
+}
+
+/// Parse the items of a list starting at `lines[start]`, returning them along
+/// with the index of the first line after the list. Lines indented past the
+/// marker of an item continue it, so items can contain nested blocks.
+fn parse_list_items(lines: &[&str], start: usize, depth: usize, ordered: bool) -> (Vec<Vec<Block>>, usize) {
+    let mut items = Vec::new();
+    let mut i = start;
+
+    while i < lines.len() {
+        let rest = match (line_start(lines[i], depth), ordered) {
+            (LineStart::Bullet(rest), false) => rest,
+            (LineStart::Numbered { rest, .. }, true) => rest,
+            _ => break,
+        };
+        let content_indent = lines[i].len() - rest.len();
+        let mut item_lines = vec![rest];
+        i += 1;
+
+        while i < lines.len() {
+            let line = lines[i];
+            let continues = indent(line) >= content_indent
+                || (line.trim().is_empty() && lines.get(i + 1).is_some_and(|next| indent(next) >= content_indent));
+            if !continues {
+                break
+            }
+            item_lines.push(strip_indent(line, content_indent));
+            i += 1;
+        }
+
+        items.push(parse_blocks(&item_lines, depth + 1));
+    }
+
+    (items, i)
+}
+
+/// Remove up to `columns` of leading whitespace from `line`
+fn strip_indent(line: &str, columns: usize) -> &str {
+    let mut removed = 0;
+    for (index, c) in line.char_indices() {
+        if removed >= columns || !c.is_whitespace() {
+            return &line[index..]
+        }
+        removed += if c == '\t' { 4 } else { 1 };
+    }
+    ""
+}
+
+// Inlines
+
+fn parse_inlines(text: &str, depth: usize) -> Vec<Inline> {
+    let mut parser = InlineParser { inlines: Vec::new(), text: String::new() };
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+
+        // Escaped punctuation is kept as is
+        if c == '\\' && rest.get(1).is_some_and(|next| next.is_ascii_punctuation()) {
+            parser.text.push(rest[1]);
+            i += 2;
+            continue
+        }
+        if c == '\n' {
+            parser.push(Inline::LineBreak);
+            i += 1;
+            continue
+        }
+        if c == '`' {
+            if let Some((code, len)) = code_span(rest) {
+                parser.push(Inline::Code(code));
+                i += len;
+                continue
+            }
+        }
+        if depth < MAX_NESTING && (c == '*' || c == '_') {
+            let previous = i.checked_sub(1).map(|index| chars[index]);
+            if let Some((inline, len)) = emphasis(rest, previous, depth) {
+                parser.push(inline);
+                i += len;
+                continue
+            }
+        }
+        if c == '[' && depth < MAX_NESTING {
+            if let Some((inline, len)) = link(rest, depth) {
+                parser.push(inline);
+                i += len;
+                continue
+            }
+        }
+        // Mentions start at a word boundary, so email addresses are left alone
+        if c == '@' && (i == 0 || !chars[i - 1].is_alphanumeric()) {
+            let len = rest[1..].iter().take_while(|c| c.is_ascii_alphanumeric()).count();
+            if len > 0 {
+                parser.push(Inline::Mention(rest[1..=len].iter().collect()));
+                i += len + 1;
+                continue
+            }
+        }
+        if c == 'h' || c == 'H' {
+            let at_word_start = i == 0 || !chars[i - 1].is_alphanumeric();
+            if let Some(len) = bare_url_len(rest).filter(|_| at_word_start) {
+                let url: String = rest[..len].iter().collect();
+                parser.push(Inline::Link { text: vec![Inline::Text(url.clone())], url });
+                i += len;
+                continue
+            }
+        }
+
+        parser.text.push(c);
+        i += 1;
+    }
+
+    parser.finish()
+}
+
+/// Collects inlines, merging consecutive text
+struct InlineParser {
+    inlines: Vec<Inline>,
+    text: String
+}
+
+impl InlineParser {
+    fn push(&mut self, inline: Inline) {
+        self.flush_text();
+        self.inlines.push(inline);
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            self.inlines.push(Inline::Text(std::mem::take(&mut self.text)));
+        }
+    }
+
+    fn finish(mut self) -> Vec<Inline> {
+        self.flush_text();
+        self.inlines
+    }
+}
+
+/// A code span opened by the backticks at the start of `chars`, closed by a
+/// run of as many backticks. Returns the code and the length of the span.
+fn code_span(chars: &[char]) -> Option<(String, usize)> {
+    let ticks = chars.iter().take_while(|c| **c == '`').count();
+    let end = chars.len().min(MAX_INLINE_SCAN);
+    let mut i = ticks;
+    while i < end {
+        if chars[i] == '`' {
+            let run = chars[i..].iter().take_while(|c| **c == '`').count();
+            if run == ticks {
+                let code: String = chars[ticks..i].iter().collect();
+                let code = match code.trim() {
+                    "" => code,
+                    trimmed => trimmed.to_string(),
+                };
+                return Some((code, i + run))
+            }
+            i += run;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Bold (`**`/`__`) or italic (`*`/`_`) text opened at the start of `chars`.
+/// Underscores only apply at word boundaries, so that snake_case is left
+/// alone. Returns the emphasis and the length of the text it spans.
+fn emphasis(chars: &[char], previous: Option<char>, depth: usize) -> Option<(Inline, usize)> {
+    let marker = chars[0];
+    let width = if chars.get(1) == Some(&marker) { 2 } else { 1 };
+    if marker == '_' && previous.is_some_and(char::is_alphanumeric) {
+        return None
+    }
+    // Must be followed by text, not whitespace
+    if chars.get(width).is_none_or(|c| c.is_whitespace()) {
+        return None
+    }
+
+    let end = chars.len().min(MAX_INLINE_SCAN);
+    let mut i = width;
+    while i + width <= end {
+        if chars[i] == '\\' {
+            i += 2;
+            continue
+        }
+        if chars[i] == '`' {
+            i += code_span(&chars[i..]).map_or(1, |(_, len)| len);
+            continue
+        }
+        let closes = chars[i..i + width].iter().all(|c| *c == marker)
+            && !chars[i - 1].is_whitespace()
+            && (marker != '_' || !chars.get(i + width).is_some_and(|c| c.is_alphanumeric()))
+            // A single marker is not closed by the start of a double one
+            && (width == 2 || chars.get(i + 1) != Some(&marker));
+        if closes {
+            let inner: String = chars[width..i].iter().collect();
+            let inlines = parse_inlines(&inner, depth + 1);
+            let inline = match width {
+                2 => Inline::Bold(inlines),
+                _ => Inline::Italic(inlines),
+            };
+            return Some((inline, i + width))
+        }
+        // Skip over nested double markers as a whole
+        if width == 1 && chars[i] == marker && chars.get(i + 1) == Some(&marker) {
+            i += 2;
+            continue
+        }
+        i += 1;
+    }
+    None
+}
+
+/// A `[text](url)` link opened at the start of `chars`. Returns the link and
+/// the length of the text it spans.
+fn link(chars: &[char], depth: usize) -> Option<(Inline, usize)> {
+    let chars = &chars[..chars.len().min(MAX_INLINE_SCAN)];
+    let text_end = chars.iter().position(|c| *c == ']')?;
+    if chars.get(text_end + 1) != Some(&'(') {
+        return None
+    }
+    // Parentheses within the address are allowed as long as they balance
+    let url_start = text_end + 2;
+    let mut open_parens = 0;
+    let url_len = chars[url_start..].iter().position(|c| match c {
+        '(' => {
+            open_parens += 1;
+            false
+        },
+        ')' if open_parens > 0 => {
+            open_parens -= 1;
+            false
+        },
+        ')' => true,
+        _ => false,
+    })?;
+    let url: String = chars[url_start..url_start + url_len].iter().collect();
+    let url = url.trim();
+    if url.is_empty() || url.contains(char::is_whitespace) {
+        return None
+    }
+
+    let text: String = chars[1..text_end].iter().collect();
+    let text = match text.is_empty() {
+        true => vec![Inline::Text(url.to_string())],
+        false => parse_inlines(&text, depth + 1),
+    };
+    Some((Inline::Link { text, url: url.to_string() }, url_start + url_len + 1))
+}
+
+/// The length of a bare http or https address at the start of `chars`.
+/// Trailing punctuation is left out, as it is more likely to end a sentence.
+fn bare_url_len(chars: &[char]) -> Option<usize> {
+    let prefix: String = chars.iter().take(8).collect::<String>().to_ascii_lowercase();
+    let scheme_len = if prefix.starts_with("https://") {
+        8
+    } else if prefix.starts_with("http://") {
+        7
+    } else {
+        return None
+    };
+
+    let mut len = chars.iter().take_while(|c| !c.is_whitespace() && **c != '<' && **c != '>').count();
+    while len > scheme_len && matches!(chars[len - 1], '.' | ',' | ':' | ';' | '!' | '?' | ')' | '\'' | '"' | '*' | '_') {
+        len -= 1;
+    }
+    (len > scheme_len).then_some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(text.to_string())
+    }
+
+    #[test]
+    fn safe_urls() {
+        assert!(is_safe_url("https://example.com"));
+        assert!(is_safe_url("HTTP://example.com"));
+        assert!(is_safe_url("mailto:someone@example.com"));
+        assert!(!is_safe_url("https://"));
+        assert!(!is_safe_url("javascript:alert(1)"));
+        assert!(!is_safe_url("example.com"));
+    }
+
+    #[test]
+    fn multibyte_urls_are_unsafe_rather_than_panicking() {
+        assert!(!is_safe_url("ééééé"));
+        assert!(!is_safe_url("httpé://example.com"));
+        assert_eq!(validate("[a](ééééé)"), Err(MarkdownError::UnsafeLink("ééééé".to_string())));
+    }
+
+    #[test]
+    fn validate_rejects_nested_unsafe_links() {
+        assert!(validate("see [here](https://example.com)").is_ok());
+        assert!(validate("> - **[x](javascript:alert(1))**").is_err());
+        assert!(validate("`[x](javascript:alert(1))`").is_ok());
+        assert!(validate("```\n[x](javascript:alert(1))\n```").is_ok());
+    }
+
+    #[test]
+    fn inlines() {
+        assert_eq!(parse("a **b** *c* `d`"), vec![Block::Paragraph(vec![
+            text("a "),
+            Inline::Bold(vec![text("b")]),
+            text(" "),
+            Inline::Italic(vec![text("c")]),
+            text(" "),
+            Inline::Code("d".to_string())
+        ])]);
+        assert_eq!(parse("snake_case_name"), vec![Block::Paragraph(vec![text("snake_case_name")])]);
+        assert_eq!(parse("\\*not italic\\*"), vec![Block::Paragraph(vec![text("*not italic*")])]);
+    }
+
+    #[test]
+    fn unclosed_markers_are_parsed_in_linear_time() {
+        for unit in ["*a ", "**a ", "_a ", "[a](b ", "[a"] {
+            let body = unit.repeat(20_000 / unit.len());
+            assert_eq!(parse(&body), vec![Block::Paragraph(vec![text(body.trim_end())])]);
+        }
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(parse("[docs](https://example.com/a_(b))"), vec![Block::Paragraph(vec![
+            Inline::Link { text: vec![text("docs")], url: "https://example.com/a_(b)".to_string() }
+        ])]);
+        assert_eq!(parse("go to https://example.com."), vec![Block::Paragraph(vec![
+            text("go to "),
+            Inline::Link { text: vec![text("https://example.com")], url: "https://example.com".to_string() },
+            text(".")
+        ])]);
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(parse("```rust\nlet a = 1;\n```\n> quoted"), vec![
+            Block::CodeBlock { language: Some("rust".to_string()), code: "let a = 1;".to_string() },
+            Block::Quote(vec![Block::Paragraph(vec![text("quoted")])])
+        ]);
+        assert_eq!(parse("3. three\n4. four\n   - nested"), vec![Block::OrderedList { start: 3, items: vec![
+            vec![Block::Paragraph(vec![text("three")])],
+            vec![
+                Block::Paragraph(vec![text("four")]),
+                Block::BulletList(vec![vec![Block::Paragraph(vec![text("nested")])]])
+            ]
+        ] }]);
+    }
+
+    #[test]
+    fn mentions_are_collected_once_outside_code() {
+        assert_eq!(mentions("@alice hi @Bob and @alice, not `@carol` or me@example.com"), vec!["alice", "Bob"]);
+    }
+}
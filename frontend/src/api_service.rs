@@ -1,28 +1,80 @@
 use common::{
     AccountPasswordChange,
     AccountRequest,
+    AuthToken,
+    ChatAttachment,
+    ChatDirectMessage,
     ChatMessage,
+    ChatMessageEdit,
+    ChatMessagePage,
+    ChatMessageRevision,
+    ChatPinnedMessage,
+    ChatReadMarker,
     ChatRoom,
+    ChatRoomDirectoryPage,
+    ChatRoomInvitation,
+    ChatRoomInviteCode,
+    ChatRoomInviteCodeRequest,
     ChatRoomManageUser,
+    ChatRoomMember,
     ChatRoomName,
+    ChatRoomRoleUpdate,
+    ChatRoomVisibility,
+    LoginChallenge,
+    ConnectionTicket,
     LoginResponse,
     LoginTokenInfo,
+    LoginTotpRequest,
+    PrivacySettings,
+    RoomRole,
+    RoomVisibility,
+    TotpCode,
+    TotpEnrolment,
+    TotpRecoveryCodes,
+    TotpStatus,
     UserAssociationUpdate,
     UserAssociations,
     UserInfo
 };
 
-use gloo::console::log;
+use gloo::{
+    console::log,
+    net::{
+        eventsource::futures::EventSource,
+        websocket::futures::WebSocket
+    }
+};
 
-use reqwest::{self, StatusCode};
-use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use reqwest::{self, header, StatusCode};
+use yewdux::Dispatch;
+
+use crate::store::{Store, StoreDispatchExt};
 
 const BASE_URI: &str = "http://127.0.0.1:8000";
+const SOCKET_BASE_URI: &str = "ws://127.0.0.1:8000";
+
+/// The position of a page of messages in a chat room
+pub enum MessageCursor {
+    Latest,
+    Before(u64),
+    After(u64)
+}
+
+/// The outcome of logging in with a correct username and password
+pub enum LoginStep {
+    LoggedIn(LoginResponse),
+    /// A two-factor code is needed to complete the login
+    Challenged(LoginChallenge)
+}
 
 #[derive(Debug)]
 pub enum ApiError {
     Timeout,
+    /// The session has expired or been logged out, so the user is logged out
     Unauthorized,
+    /// The user is not permitted to perform the request
+    Forbidden,
     BadRequest,
     ResponseParseFailure,
     Other{ _desc: String }
@@ -56,7 +108,11 @@ impl From<reqwest::Response> for ApiError {
     fn from(value: reqwest::Response) -> Self {
         let result = match value.status() {
             StatusCode::GATEWAY_TIMEOUT | StatusCode::REQUEST_TIMEOUT => ApiError::Timeout,
-            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ApiError::Unauthorized,
+            StatusCode::UNAUTHORIZED if rejects_token(&value) => {
+                Dispatch::<Store>::global().logout_reduce();
+                ApiError::Unauthorized
+            },
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ApiError::Forbidden,
             StatusCode::BAD_REQUEST => ApiError::BadRequest,
             _ => ApiError::Other{ _desc: value.status().canonical_reason().unwrap().into() }
         };
@@ -66,6 +122,14 @@ impl From<reqwest::Response> for ApiError {
     }
 }
 
+/// Whether a 401 response was caused by the bearer token being unknown or
+/// expired, rather than the user lacking permission
+fn rejects_token(response: &reqwest::Response) -> bool {
+    response.headers().get(header::WWW_AUTHENTICATE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("invalid_token"))
+}
+
 type ApiResult<T> = Result<T, ApiError>;
 
 // Account management
@@ -85,9 +149,32 @@ pub async fn account_register(details: AccountRequest) -> ApiResult<()> {
     }
 }
 
-pub async fn account_login(details: &AccountRequest) -> ApiResult<LoginResponse> {
+pub async fn account_login(details: &AccountRequest) -> ApiResult<LoginStep> {
     let endpoint = format!("{}/account/login", BASE_URI);
 
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .json(&details)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => match res.json::<LoginResponse>().await {
+            Ok(login) => Ok(LoginStep::LoggedIn(login)),
+            Err(err) => Err(err.into())
+        },
+        Ok(res) if res.status() == StatusCode::ACCEPTED => match res.json::<LoginChallenge>().await {
+            Ok(challenge) => Ok(LoginStep::Challenged(challenge)),
+            Err(err) => Err(err.into())
+        },
+        Ok(res) => Err(res.into()),
+        Err(err)   => Err(err.into())
+    }
+}
+
+pub async fn account_login_totp(details: &LoginTotpRequest) -> ApiResult<LoginResponse> {
+    let endpoint = format!("{}/account/login/totp", BASE_URI);
+
     let response = reqwest::Client::new()
         .post(endpoint)
         .json(&details)
@@ -106,7 +193,7 @@ pub async fn account_login(details: &AccountRequest) -> ApiResult<LoginResponse>
     }
 }
 
-pub async fn account_change_password(token: &Uuid, details: AccountPasswordChange) -> ApiResult<()> {
+pub async fn account_change_password(token: &AuthToken, details: AccountPasswordChange) -> ApiResult<()> {
     let endpoint = format!("{}/account/change-password", BASE_URI);
 
     let response = reqwest::Client::new()
@@ -123,7 +210,7 @@ pub async fn account_change_password(token: &Uuid, details: AccountPasswordChang
     }
 }
 
-pub async fn account_logout(token: &Uuid) -> ApiResult<()> {
+pub async fn account_logout(token: &AuthToken) -> ApiResult<()> {
     let endpoint = format!("{}/account/logout", BASE_URI);
 
     let response = reqwest::Client::new()
@@ -139,7 +226,7 @@ pub async fn account_logout(token: &Uuid) -> ApiResult<()> {
     }
 }
 
-pub async fn account_get_active_token_info(token: &Uuid) -> ApiResult<Vec<LoginTokenInfo>> {
+pub async fn account_get_active_token_info(token: &AuthToken) -> ApiResult<Vec<LoginTokenInfo>> {
     let endpoint = format!("{}/account/tokens", BASE_URI);
 
     let response = reqwest::Client::new()
@@ -160,7 +247,23 @@ pub async fn account_get_active_token_info(token: &Uuid) -> ApiResult<Vec<LoginT
     }
 }
 
-pub async fn account_clear_tokens(token: &Uuid) -> ApiResult<()> {
+pub async fn account_revoke_session(token: &AuthToken, session_id: &str) -> ApiResult<()> {
+    let endpoint = format!("{}/account/tokens/{}", BASE_URI, session_id);
+
+    let response = reqwest::Client::new()
+        .delete(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn account_clear_tokens(token: &AuthToken) -> ApiResult<()> {
     let endpoint= format!("{}/account/clear-tokens", BASE_URI);
 
     let response = reqwest::Client::new()
@@ -176,9 +279,151 @@ pub async fn account_clear_tokens(token: &Uuid) -> ApiResult<()> {
     }
 }
 
+/// A single use ticket, for opening a chat socket or event stream, or loading
+/// an attachment, without putting the token in the address.
+pub async fn account_get_ticket(token: &AuthToken) -> ApiResult<ConnectionTicket> {
+    let endpoint = format!("{}/account/ticket", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ConnectionTicket>().await {
+        Ok(ticket) => Ok(ticket),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn account_get_totp_status(token: &AuthToken) -> ApiResult<TotpStatus> {
+    let endpoint = format!("{}/account/totp", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<TotpStatus>().await {
+        Ok(status) => Ok(status),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn account_start_totp(token: &AuthToken) -> ApiResult<TotpEnrolment> {
+    let endpoint = format!("{}/account/totp", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<TotpEnrolment>().await {
+        Ok(enrolment) => Ok(enrolment),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn account_confirm_totp(token: &AuthToken, code: &TotpCode) -> ApiResult<TotpRecoveryCodes> {
+    let endpoint = format!("{}/account/totp/confirm", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .json(code)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<TotpRecoveryCodes>().await {
+        Ok(codes) => Ok(codes),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn account_disable_totp(token: &AuthToken, code: &TotpCode) -> ApiResult<()> {
+    let endpoint = format!("{}/account/totp/disable", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .json(code)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn account_get_privacy(token: &AuthToken) -> ApiResult<PrivacySettings> {
+    let endpoint = format!("{}/account/privacy", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<PrivacySettings>().await {
+        Ok(settings) => Ok(settings),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn account_set_privacy(token: &AuthToken, settings: &PrivacySettings) -> ApiResult<()> {
+    let endpoint = format!("{}/account/privacy", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .put(endpoint)
+        .bearer_auth(token.to_string())
+        .json(settings)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
 // Room management
 
-pub async fn chat_get_rooms(token: &Uuid) -> ApiResult<Vec<ChatRoom>> {
+pub async fn chat_get_rooms(token: &AuthToken) -> ApiResult<Vec<ChatRoom>> {
     let endpoint= format!("{}/chat/rooms", BASE_URI);
 
     let response = reqwest::Client::new()
@@ -199,7 +444,7 @@ pub async fn chat_get_rooms(token: &Uuid) -> ApiResult<Vec<ChatRoom>> {
     }
 }
 
-pub async fn chat_create_room(token: &Uuid, room_name: &str) -> ApiResult<()> {
+pub async fn chat_create_room(token: &AuthToken, room_name: &str) -> ApiResult<()> {
     let endpoint= format!("{}/chat/create-room", BASE_URI);
 
     let body = ChatRoomName { room_name: room_name.to_string() };
@@ -218,7 +463,7 @@ pub async fn chat_create_room(token: &Uuid, room_name: &str) -> ApiResult<()> {
     }
 }
 
-pub async fn chat_change_name(token: &Uuid, room_id: u64, new_name: &str) -> ApiResult<()> {
+pub async fn chat_change_name(token: &AuthToken, room_id: u64, new_name: &str) -> ApiResult<()> {
     let endpoint = format!("{}/chat/{}/change-name", BASE_URI, room_id);
 
     let body = ChatRoomName { room_name: new_name.to_string() };
@@ -237,7 +482,7 @@ pub async fn chat_change_name(token: &Uuid, room_id: u64, new_name: &str) -> Api
     }
 }
 
-pub async fn chat_get_members(token: &Uuid, room_id: u64) -> ApiResult<Vec<UserInfo>> {
+pub async fn chat_get_members(token: &AuthToken, room_id: u64) -> ApiResult<Vec<ChatRoomMember>> {
     let endpoint = format!("{}/chat/{}/members", BASE_URI, room_id);
 
     let response = reqwest::Client::new()
@@ -252,13 +497,13 @@ pub async fn chat_get_members(token: &Uuid, room_id: u64) -> ApiResult<Vec<UserI
         Err(err) => return Err(err.into())
     };
 
-    match response.json::<Vec<UserInfo>>().await {
+    match response.json::<Vec<ChatRoomMember>>().await {
         Ok(members) => Ok(members),
         Err(err) => Err(err.into())
     }
 }
 
-pub async fn chat_manage_user(token: &Uuid, room_id: u64, action: ChatRoomManageUser) -> ApiResult<()> {
+pub async fn chat_manage_user(token: &AuthToken, room_id: u64, action: ChatRoomManageUser) -> ApiResult<()> {
     let endpoint = format!("{}/chat/{}/manage-user", BASE_URI, room_id);
 
     let response = reqwest::Client::new()
@@ -275,10 +520,25 @@ pub async fn chat_manage_user(token: &Uuid, room_id: u64, action: ChatRoomManage
     }
 }
 
-// Chat interaction
+pub async fn chat_change_member_role(token: &AuthToken, room_id: u64, user_id: u64, role: RoomRole) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/members/{}/role", BASE_URI, room_id, user_id);
+
+    let response = reqwest::Client::new()
+        .put(endpoint)
+        .bearer_auth(token.to_string())
+        .json(&ChatRoomRoleUpdate { role })
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
 
-pub async fn chat_get_messages(token: &Uuid, room_id: u64, offset: u64, limit: u64) -> ApiResult<Vec<ChatMessage>> {
-    let endpoint = format!("{}/chat/{}/{}/{}", BASE_URI, room_id, offset, limit);
+pub async fn chat_get_invitations(token: &AuthToken) -> ApiResult<Vec<ChatRoomInvitation>> {
+    let endpoint = format!("{}/chat/invitations", BASE_URI);
 
     let response = reqwest::Client::new()
         .get(endpoint)
@@ -292,33 +552,638 @@ pub async fn chat_get_messages(token: &Uuid, room_id: u64, offset: u64, limit: u
         Err(err) => return Err(err.into())
     };
 
-    match response.json::<Vec<ChatMessage>>().await {
-        Ok(message_window) => Ok(message_window),
+    match response.json::<Vec<ChatRoomInvitation>>().await {
+        Ok(invitations) => Ok(invitations),
         Err(err) => Err(err.into())
-        
     }
 }
 
-pub async fn chat_send_message(token: &Uuid, message: ChatMessage) -> ApiResult<()> {
-    let endpoint = format!("{}/chat", BASE_URI);
+/// Accept (`accept == true`) or decline the invitation to `room_id`
+pub async fn chat_respond_to_invitation(token: &AuthToken, room_id: u64, accept: bool) -> ApiResult<()> {
+    let response = if accept { "accept" } else { "decline" };
+    let endpoint = format!("{}/chat/invitations/{}/{}", BASE_URI, room_id, response);
 
     let response = reqwest::Client::new()
         .post(endpoint)
-        .bearer_auth(token)
-        .json(&message)
+        .bearer_auth(token.to_string())
         .send()
         .await;
 
     match response {
         Ok(res) if res.status() == StatusCode::OK => Ok(()),
         Ok(res) => Err(res.into()),
-        Err(err) => Err(err.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn chat_create_invite_code(token: &AuthToken, room_id: u64, request: ChatRoomInviteCodeRequest) -> ApiResult<ChatRoomInviteCode> {
+    let endpoint = format!("{}/chat/{}/invite-codes", BASE_URI, room_id);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .json(&request)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatRoomInviteCode>().await {
+        Ok(invite_code) => Ok(invite_code),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn chat_get_invite_codes(token: &AuthToken, room_id: u64) -> ApiResult<Vec<ChatRoomInviteCode>> {
+    let endpoint = format!("{}/chat/{}/invite-codes", BASE_URI, room_id);
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<Vec<ChatRoomInviteCode>>().await {
+        Ok(invite_codes) => Ok(invite_codes),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn chat_revoke_invite_code(token: &AuthToken, room_id: u64, code: &str) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/invite-codes/{}", BASE_URI, room_id, code);
+
+    let response = reqwest::Client::new()
+        .delete(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
+/// Join the room of an invite `code`, returning the joined room
+pub async fn chat_join_by_code(token: &AuthToken, code: &str) -> ApiResult<ChatRoom> {
+    let endpoint = format!("{}/chat/join/{}", BASE_URI, code);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatRoom>().await {
+        Ok(room) => Ok(room),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn chat_get_direct_room(token: &AuthToken, user_id: u64) -> ApiResult<ChatRoom> {
+    let endpoint = format!("{}/chat/direct/{}", BASE_URI, user_id);
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatRoom>().await {
+        Ok(room) => Ok(room),
+        Err(err) => Err(err.into())
+    }
+}
+
+/// Send a message to a friend, returning the direct room it was sent in
+pub async fn chat_send_direct_message(token: &AuthToken, user_id: u64, body: String) -> ApiResult<ChatRoom> {
+    let endpoint = format!("{}/chat/direct/{}", BASE_URI, user_id);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .json(&ChatDirectMessage { body })
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatRoom>().await {
+        Ok(room) => Ok(room),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn chat_set_visibility(token: &AuthToken, room_id: u64, visibility: RoomVisibility) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/visibility", BASE_URI, room_id);
+
+    let response = reqwest::Client::new()
+        .put(endpoint)
+        .bearer_auth(token.to_string())
+        .json(&ChatRoomVisibility { visibility })
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
+/// Retrieve a page of public rooms, optionally filtered by name
+pub async fn chat_get_directory(token: &AuthToken, query: &str, after_id: Option<u64>) -> ApiResult<ChatRoomDirectoryPage> {
+    let endpoint = format!("{}/chat/directory", BASE_URI);
+
+    let mut params = Vec::new();
+    if !query.is_empty() {
+        params.push(("q", query.to_string()));
+    }
+    if let Some(after_id) = after_id {
+        params.push(("after_id", after_id.to_string()));
+    }
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .query(&params)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatRoomDirectoryPage>().await {
+        Ok(page) => Ok(page),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn chat_join_room(token: &AuthToken, room_id: u64) -> ApiResult<ChatRoom> {
+    let endpoint = format!("{}/chat/{}/join", BASE_URI, room_id);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatRoom>().await {
+        Ok(room) => Ok(room),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub async fn chat_leave_room(token: &AuthToken, room_id: u64) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/leave", BASE_URI, room_id);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
+// Chat interaction
+
+pub async fn chat_get_messages(token: &AuthToken, room_id: u64, cursor: MessageCursor, limit: u64) -> ApiResult<ChatMessagePage> {
+    let endpoint = match cursor {
+        MessageCursor::Latest => format!("{}/chat/{}/messages?limit={}", BASE_URI, room_id, limit),
+        MessageCursor::Before(before_id) => format!("{}/chat/{}/messages?before_id={}&limit={}", BASE_URI, room_id, before_id, limit),
+        MessageCursor::After(after_id) => format!("{}/chat/{}/messages?after_id={}&limit={}", BASE_URI, room_id, after_id, limit),
+    };
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatMessagePage>().await {
+        Ok(page) => Ok(page),
+        Err(err) => Err(err.into())
+        
+    }
+}
+
+pub async fn chat_search_messages(
+    token: &AuthToken,
+    query: &str,
+    room_id: Option<u64>,
+    sender_id: Option<u64>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>
+) -> ApiResult<Vec<ChatMessage>> {
+    let endpoint = format!("{}/chat/search", BASE_URI);
+
+    let mut params = vec![("q", query.to_string())];
+    if let Some(room_id) = room_id {
+        params.push(("room_id", room_id.to_string()));
+    }
+    if let Some(sender_id) = sender_id {
+        params.push(("sender_id", sender_id.to_string()));
+    }
+    if let Some(from) = from {
+        params.push(("from", from.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)));
+    }
+    if let Some(to) = to {
+        params.push(("to", to.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)));
+    }
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .query(&params)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<Vec<ChatMessage>>().await {
+        Ok(messages) => Ok(messages),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_get_unread_mentions(token: &AuthToken) -> ApiResult<Vec<ChatMessage>> {
+    let endpoint = format!("{}/chat/mentions", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<Vec<ChatMessage>>().await {
+        Ok(messages) => Ok(messages),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_send_message(token: &AuthToken, message: ChatMessage) -> ApiResult<()> {
+    let endpoint = format!("{}/chat", BASE_URI);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token)
+        .json(&message)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_edit_message(token: &AuthToken, room_id: u64, message_id: u64, body: &str) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/messages/{}", BASE_URI, room_id, message_id);
+
+    let edit = ChatMessageEdit { body: body.to_string() };
+
+    let response = reqwest::Client::new()
+        .put(endpoint)
+        .bearer_auth(token)
+        .json(&edit)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_delete_message(token: &AuthToken, room_id: u64, message_id: u64) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/messages/{}", BASE_URI, room_id, message_id);
+
+    let response = reqwest::Client::new()
+        .delete(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_get_thread(token: &AuthToken, room_id: u64, root_id: u64, after_id: Option<u64>, limit: u64) -> ApiResult<ChatMessagePage> {
+    let endpoint = match after_id {
+        Some(after_id) => format!("{}/chat/{}/messages/{}/thread?after_id={}&limit={}", BASE_URI, room_id, root_id, after_id, limit),
+        None => format!("{}/chat/{}/messages/{}/thread?limit={}", BASE_URI, room_id, root_id, limit),
+    };
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatMessagePage>().await {
+        Ok(page) => Ok(page),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_add_reaction(token: &AuthToken, room_id: u64, message_id: u64, emoji: &str) -> ApiResult<()> {
+    let endpoint = reaction_endpoint(room_id, message_id, emoji)?;
+
+    let response = reqwest::Client::new()
+        .put(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_remove_reaction(token: &AuthToken, room_id: u64, message_id: u64, emoji: &str) -> ApiResult<()> {
+    let endpoint = reaction_endpoint(room_id, message_id, emoji)?;
+
+    let response = reqwest::Client::new()
+        .delete(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_pin_message(token: &AuthToken, room_id: u64, message_id: u64) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/messages/{}/pin", BASE_URI, room_id, message_id);
+
+    let response = reqwest::Client::new()
+        .put(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_unpin_message(token: &AuthToken, room_id: u64, message_id: u64) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/messages/{}/pin", BASE_URI, room_id, message_id);
+
+    let response = reqwest::Client::new()
+        .delete(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn chat_get_pinned_messages(token: &AuthToken, room_id: u64) -> ApiResult<Vec<ChatPinnedMessage>> {
+    let endpoint = format!("{}/chat/{}/pins", BASE_URI, room_id);
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<Vec<ChatPinnedMessage>>().await {
+        Ok(pins) => Ok(pins),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Build the endpoint of an `emoji` reaction, with the emoji percent-encoded
+/// as a path segment.
+fn reaction_endpoint(room_id: u64, message_id: u64, emoji: &str) -> ApiResult<reqwest::Url> {
+    let endpoint = format!("{}/chat/{}/messages/{}/reactions", BASE_URI, room_id, message_id);
+    let mut url = reqwest::Url::parse(&endpoint)
+        .map_err(|e| ApiError::Other { _desc: e.to_string() })?;
+    url.path_segments_mut()
+        .map_err(|_| ApiError::Other { _desc: "Cannot extend endpoint path".to_string() })?
+        .push(emoji);
+    Ok(url)
+}
+
+pub async fn chat_get_message_history(token: &AuthToken, room_id: u64, message_id: u64) -> ApiResult<Vec<ChatMessageRevision>> {
+    let endpoint = format!("{}/chat/{}/messages/{}/history", BASE_URI, room_id, message_id);
+
+    let response = reqwest::Client::new()
+        .get(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<Vec<ChatMessageRevision>>().await {
+        Ok(revisions) => Ok(revisions),
+        Err(err) => Err(err.into())
+    }
+}
+
+/// Mark the messages in the room specified by `room_id` as read, up to and
+/// including the message specified by `message_id`
+pub async fn chat_mark_read(token: &AuthToken, room_id: u64, message_id: u64) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/read", BASE_URI, room_id);
+
+    let response = reqwest::Client::new()
+        .put(endpoint)
+        .bearer_auth(token.to_string())
+        .json(&ChatReadMarker { message_id })
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
+/// Signal that the user is typing in the room specified by `room_id`
+pub async fn chat_send_typing(token: &AuthToken, room_id: u64) -> ApiResult<()> {
+    let endpoint = format!("{}/chat/{}/typing", BASE_URI, room_id);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .send()
+        .await;
+
+    match response {
+        Ok(res) if res.status() == StatusCode::OK => Ok(()),
+        Ok(res) => Err(res.into()),
+        Err(err) => Err(err.into())
+    }
+}
+
+/// Upload a file to the room specified by `room_id`, to be attached to a
+/// message sent afterwards
+pub async fn chat_upload_attachment(
+    token: &AuthToken,
+    room_id: u64,
+    file_name: String,
+    content_type: &str,
+    contents: Vec<u8>
+) -> ApiResult<ChatAttachment> {
+    let endpoint = format!("{}/chat/{}/attachments", BASE_URI, room_id);
+
+    let file_part = match reqwest::multipart::Part::bytes(contents).file_name(file_name).mime_str(content_type) {
+        Ok(part) => part,
+        Err(err) => return Err(err.into())
+    };
+    let form = reqwest::multipart::Form::new().part("file", file_part);
+
+    let response = reqwest::Client::new()
+        .post(endpoint)
+        .bearer_auth(token.to_string())
+        .multipart(form)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(res) if res.status() == StatusCode::OK => res,
+        Ok(res) => return Err(res.into()),
+        Err(err) => return Err(err.into())
+    };
+
+    match response.json::<ChatAttachment>().await {
+        Ok(attachment) => Ok(attachment),
+        Err(err) => Err(err.into())
+    }
+}
+
+/// The address of an attachment, or of its thumbnail, for the browser to load
+/// directly. As headers cannot be set, a ticket from `account_get_ticket` is
+/// included in the address, and the address can only be loaded once.
+pub fn chat_attachment_url(ticket: &ConnectionTicket, room_id: u64, attachment_id: u64, thumbnail: bool) -> String {
+    let endpoint = format!("{}/chat/{}/attachments/{}", BASE_URI, room_id, attachment_id);
+    match thumbnail {
+        true => format!("{}/thumbnail?ticket={}", endpoint, ticket.ticket),
+        false => format!("{}?ticket={}", endpoint, ticket.ticket),
+    }
+}
+
+/// Open a chat socket with a ticket from `account_get_ticket`, as headers
+/// cannot be set on a WebSocket.
+pub fn chat_open_socket(ticket: &ConnectionTicket) -> ApiResult<WebSocket> {
+    let endpoint = format!("{}/chat/ws?ticket={}", SOCKET_BASE_URI, ticket.ticket);
+
+    match WebSocket::open(&endpoint) {
+        Ok(socket) => Ok(socket),
+        Err(err) => Err(ApiError::Other{ _desc: format!("Failed to open chat socket: {:?}", err) })
+    }
+}
+
+/// Open an event stream, resuming after `last_event_id` if provided. The
+/// browser's own reconnection reuses the ticket, which fails, so the caller
+/// must reconnect with a new ticket instead.
+pub fn chat_open_event_stream(ticket: &ConnectionTicket, last_event_id: Option<u64>) -> ApiResult<EventSource> {
+    let endpoint = match last_event_id {
+        Some(id) => format!("{}/events?ticket={}&last_event_id={}", BASE_URI, ticket.ticket, id),
+        None => format!("{}/events?ticket={}", BASE_URI, ticket.ticket),
+    };
+
+    match EventSource::new(&endpoint) {
+        Ok(event_source) => Ok(event_source),
+        Err(err) => Err(ApiError::Other{ _desc: format!("Failed to open event stream: {:?}", err) })
     }
 }
 
 // User search
 
-pub async fn user_search(token: &Uuid, search_term: &str) -> ApiResult<Vec<UserInfo>> {
+pub async fn user_search(token: &AuthToken, search_term: &str) -> ApiResult<Vec<UserInfo>> {
     let endpoint_with_param = format!("{}/users?username={}", BASE_URI, search_term);
 
     let response = reqwest::Client::new()
@@ -339,7 +1204,7 @@ pub async fn user_search(token: &Uuid, search_term: &str) -> ApiResult<Vec<UserI
     }
 }
 
-pub async fn user_associate(token: &Uuid, association: UserAssociationUpdate) -> ApiResult<()> {
+pub async fn user_associate(token: &AuthToken, association: UserAssociationUpdate) -> ApiResult<()> {
     let endpoint = format!("{}/users", BASE_URI);
 
     let response = reqwest::Client::new()
@@ -356,7 +1221,7 @@ pub async fn user_associate(token: &Uuid, association: UserAssociationUpdate) ->
     }
 }
 
-pub async fn user_get_associations(token: &Uuid) -> ApiResult<UserAssociations> {
+pub async fn user_get_associations(token: &AuthToken) -> ApiResult<UserAssociations> {
     let endpoint = format!("{}/users/associations", BASE_URI);
 
     let response = reqwest::Client::new()
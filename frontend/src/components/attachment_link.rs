@@ -0,0 +1,85 @@
+use common::ChatAttachment;
+use yew::prelude::*;
+use yewdux::use_store;
+
+use crate::{api_service, store::Store};
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub room_id: u64,
+    pub attachment: ChatAttachment
+}
+
+/// A link to an attachment, previewed by its thumbnail if it has one. Each
+/// load of an attachment needs a new ticket, so tickets are fetched as the
+/// thumbnail is shown and as the link is followed.
+#[function_component(AttachmentLink)]
+pub fn attachment_link(props: &Props) -> Html {
+    let (store, _) = use_store::<Store>();
+    let token = store.user.as_ref().map(|user| user.token);
+    let thumbnail_url = use_state_eq(|| None::<String>);
+
+    {
+        let thumbnail_url = thumbnail_url.clone();
+        let has_thumbnail = props.attachment.has_thumbnail;
+        use_effect_with((token, props.room_id, props.attachment.id), move |(token, room_id, attachment_id)| {
+            let (token, room_id, attachment_id) = (*token, *room_id, *attachment_id);
+            if let (Some(token), true) = (token, has_thumbnail) {
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Ok(ticket) = api_service::account_get_ticket(&token).await {
+                        thumbnail_url.set(Some(api_service::chat_attachment_url(&ticket, room_id, attachment_id, true)));
+                    }
+                });
+            }
+        });
+    }
+
+    let on_open = {
+        let room_id = props.room_id;
+        let attachment_id = props.attachment.id;
+        Callback::from(move |event: MouseEvent| {
+            event.prevent_default();
+            let Some(token) = token else {
+                return
+            };
+            // The window is opened straight away, as it may be blocked once the
+            // click is no longer being handled, and pointed at the attachment
+            // once a ticket has been issued
+            let Ok(Some(opened)) = gloo::utils::window().open_with_url_and_target("", "_blank") else {
+                return
+            };
+            wasm_bindgen_futures::spawn_local(async move {
+                match api_service::account_get_ticket(&token).await {
+                    Ok(ticket) => {
+                        let url = api_service::chat_attachment_url(&ticket, room_id, attachment_id, false);
+                        let _ = opened.location().set_href(&url);
+                    },
+                    Err(_) => {
+                        let _ = opened.close();
+                    },
+                }
+            });
+        })
+    };
+
+    let label = format!("{} ({})", props.attachment.file_name, file_size_label(props.attachment.size));
+    match thumbnail_url.as_ref() {
+        Some(thumbnail_url) => html! {
+            <a href="#" onclick={on_open} class={classes!("attachment")}>
+                <img src={thumbnail_url.clone()} alt={label.clone()} title={label} />
+            </a>
+        },
+        None => html! {
+            <a href="#" onclick={on_open} class={classes!("attachment")}>{ label }</a>
+        },
+    }
+}
+
+/// A file size in the largest unit it is at least one of
+fn file_size_label(size: u64) -> String {
+    match size {
+        0..=1023 => format!("{} B", size),
+        1024..=1048575 => format!("{:.1} KiB", size as f64 / 1024.0),
+        _ => format!("{:.1} MiB", size as f64 / 1048576.0),
+    }
+}
\ No newline at end of file
@@ -1,17 +1,66 @@
+use std::ops::Deref;
+
+use common::{markdown, ChatMessageRevision};
 use yew::prelude::*;
 use yewdux::use_store;
 
-use crate::store::Store;
+use crate::{
+    api_service,
+    components::{
+        attachment_link::AttachmentLink,
+        button::Button,
+        input_field::InputField,
+        markdown::Markdown
+    },
+    store::Store
+};
+
+/// Emoji offered by the reaction picker
+const PICKER_EMOJI: [&str; 8] = ["👍", "👎", "❤️", "😂", "😮", "😢", "🎉", "👀"];
 
 #[derive(Properties, PartialEq)]
 pub struct Props {
     pub message: common::ChatMessage,
+    /// Emitted with the message id to open the thread the message starts
+    #[prop_or_default]
+    pub on_open_thread: Option<Callback<u64>>,
+    /// Highlight the message and scroll it into view
+    #[prop_or_default]
+    pub highlighted: bool,
+    /// The viewer may delete messages sent by other members
+    #[prop_or_default]
+    pub can_moderate: bool,
+    /// The message is pinned in its room
+    #[prop_or_default]
+    pub pinned: bool,
+    /// The viewer may pin and unpin messages
+    #[prop_or_default]
+    pub can_pin: bool
+}
+
+#[derive(PartialEq, Clone, Default)]
+struct State {
+    editing: bool,
+    picking_reaction: bool,
+    history: Option<Vec<ChatMessageRevision>>
 }
 
 #[function_component(ChatMessage)]
 pub fn chat_message(props: &Props) -> Html {
     let (store, _) = use_store::<Store>();
 
+    let component_state = use_state_eq(|| State::default());
+
+    let container_ref = use_node_ref();
+    {
+        let container_ref = container_ref.clone();
+        use_effect_with(props.highlighted, move |highlighted| {
+            if let (true, Some(container)) = (*highlighted, container_ref.cast::<web_sys::Element>()) {
+                container.scroll_into_view();
+            }
+        });
+    }
+
     let sender_name = match props.message.sender_id {
         Some(id) => store.cache.get_username_from_id(id),
         None => "Unknown user".to_string()
@@ -22,11 +71,264 @@ pub fn chat_message(props: &Props) -> Html {
         None => "Unknown".to_string(),
     };
 
+    let is_own_message = match (&store.user, props.message.sender_id) {
+        (Some(user), Some(sender_id)) => user.user_id == sender_id,
+        _ => false
+    };
+
+    // Messages mentioning the viewer stand out, unless they sent it themselves
+    let mentions_viewer = match &store.user {
+        Some(user) if !is_own_message => markdown::mentions(&props.message.body).iter()
+            .any(|username| username.eq_ignore_ascii_case(&user.username)),
+        _ => false
+    };
+
+    let state_handle = component_state.clone();
+    let on_edit_toggle = Callback::from(move |_: MouseEvent| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.editing = !updated_state.editing;
+        state_handle.set(updated_state);
+    });
+
+    // The edited message is delivered back as a room event
+    let state_handle = component_state.clone();
+    let on_edit_submit = {
+        let token = store.user.as_ref().map(|user| user.token);
+        let room_id = props.message.room_id;
+        let message_id = props.message.id;
+        Callback::from(move |text: String| {
+            let (Some(token), Some(message_id)) = (token, message_id) else {
+                return
+            };
+            let state_handle = state_handle.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(()) = api_service::chat_edit_message(&token, room_id, message_id, &text).await {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.editing = false;
+                    state_handle.set(updated_state);
+                }
+            });
+        })
+    };
+
+    // The deleted message is delivered back as a room event
+    let on_delete = {
+        let token = store.user.as_ref().map(|user| user.token);
+        let room_id = props.message.room_id;
+        let message_id = props.message.id;
+        Callback::from(move |_: MouseEvent| {
+            let (Some(token), Some(message_id)) = (token, message_id) else {
+                return
+            };
+            wasm_bindgen_futures::spawn_local(async move {
+                let _ = api_service::chat_delete_message(&token, room_id, message_id).await;
+            });
+        })
+    };
+
+    // The pin or unpin is delivered back as a room event
+    let on_pin_toggle = {
+        let token = store.user.as_ref().map(|user| user.token);
+        let room_id = props.message.room_id;
+        let message_id = props.message.id;
+        let pinned = props.pinned;
+        Callback::from(move |_: MouseEvent| {
+            let (Some(token), Some(message_id)) = (token, message_id) else {
+                return
+            };
+            wasm_bindgen_futures::spawn_local(async move {
+                let _ = match pinned {
+                    true => api_service::chat_unpin_message(&token, room_id, message_id).await,
+                    false => api_service::chat_pin_message(&token, room_id, message_id).await,
+                };
+            });
+        })
+    };
+
+    let state_handle = component_state.clone();
+    let on_picker_toggle = Callback::from(move |_: MouseEvent| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.picking_reaction = !updated_state.picking_reaction;
+        state_handle.set(updated_state);
+    });
+
+    // Add the emoji reaction if not yet reacted with, otherwise remove it.
+    // The change is delivered back as a room event.
+    let state_handle = component_state.clone();
+    let on_reaction_toggle = {
+        let token = store.user.as_ref().map(|user| user.token);
+        let room_id = props.message.room_id;
+        let message_id = props.message.id;
+        let reactions = props.message.reactions.clone();
+        Callback::from(move |emoji: String| {
+            let (Some(token), Some(message_id)) = (token, message_id) else {
+                return
+            };
+            let reacted = reactions.iter()
+                .any(|count| count.emoji == emoji && count.reacted);
+            wasm_bindgen_futures::spawn_local(async move {
+                let _ = match reacted {
+                    true => api_service::chat_remove_reaction(&token, room_id, message_id, &emoji).await,
+                    false => api_service::chat_add_reaction(&token, room_id, message_id, &emoji).await,
+                };
+            });
+            let mut updated_state = state_handle.deref().clone();
+            updated_state.picking_reaction = false;
+            state_handle.set(updated_state);
+        })
+    };
+
+    let state_handle = component_state.clone();
+    let on_history_toggle = {
+        let token = store.user.as_ref().map(|user| user.token);
+        let room_id = props.message.room_id;
+        let message_id = props.message.id;
+        Callback::from(move |_: MouseEvent| {
+            let mut updated_state = state_handle.deref().clone();
+            if updated_state.history.is_some() {
+                updated_state.history = None;
+                state_handle.set(updated_state);
+                return
+            }
+            let (Some(token), Some(message_id)) = (token, message_id) else {
+                return
+            };
+            let state_handle = state_handle.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(revisions) = api_service::chat_get_message_history(&token, room_id, message_id).await {
+                    updated_state.history = Some(revisions);
+                    state_handle.set(updated_state);
+                }
+            });
+        })
+    };
+
+    let history_html: Vec<Html> = component_state.history.iter()
+        .flatten()
+        .map(|revision| html! {
+            <p>
+                { revision.time_set.to_rfc3339_opts(chrono::SecondsFormat::Secs, true) }
+                {": "}{ revision.body.clone() }
+            </p>
+        })
+        .collect();
+
+    let on_thread_click = {
+        let on_open_thread = props.on_open_thread.clone();
+        let message_id = props.message.id;
+        Callback::from(move |_: MouseEvent| {
+            if let (Some(callback), Some(message_id)) = (&on_open_thread, message_id) {
+                callback.emit(message_id);
+            }
+        })
+    };
+
+    let thread_label = match props.message.reply_count {
+        0 => "Reply".to_string(),
+        1 => "1 reply".to_string(),
+        count => format!("{} replies", count),
+    };
+
+    let reactions_html: Vec<Html> = props.message.reactions.iter()
+        .map(|count| {
+            let on_reaction_toggle = on_reaction_toggle.clone();
+            let emoji = count.emoji.clone();
+            let onclick = Callback::from(move |_: MouseEvent| on_reaction_toggle.emit(emoji.clone()));
+            html! {
+                <button {onclick} class={classes!("reaction", count.reacted.then_some("reacted"))}>
+                    { format!("{} {}", count.emoji, count.count) }
+                </button>
+            }
+        })
+        .collect();
+
+    let attachments_html: Vec<Html> = props.message.attachments.iter()
+        .map(|attachment| html! {
+            <AttachmentLink room_id={props.message.room_id} attachment={attachment.clone()} />
+        })
+        .collect();
+
+    let picker_html: Vec<Html> = PICKER_EMOJI.iter()
+        .copied()
+        .map(|emoji| {
+            let on_reaction_toggle = on_reaction_toggle.clone();
+            let onclick = Callback::from(move |_: MouseEvent| on_reaction_toggle.emit(emoji.to_string()));
+            html! {
+                <button {onclick} class={classes!("reaction")}>{ emoji }</button>
+            }
+        })
+        .collect();
+
+    if props.message.deleted_at.is_some() {
+        return html! {
+            <div ref={container_ref} class={classes!("message_container", "deleted", props.highlighted.then_some("highlighted"))}>
+                <p>{"sender: "}{ sender_name }</p>
+                <p>{ "message deleted" }</p>
+                <p>{"time sent: "}{ time_sent }</p>
+                if props.on_open_thread.is_some() && props.message.reply_count > 0 {
+                    <Button label={thread_label} on_click={on_thread_click} />
+                }
+            </div>
+        }
+    }
+
     html! {
-        <div class={classes!("message_container")}>
-            <p>{"sender: "}{ sender_name }</p>
-            <p>{"body: "}{ props.message.body.clone() }</p>
+        <div ref={container_ref} class={classes!("message_container", mentions_viewer.then_some("mentioned"), props.highlighted.then_some("highlighted"))}>
+            <p>
+                {"sender: "}{ sender_name }
+                if props.pinned {
+                    <span class={classes!("pinned_marker")}>{ " (pinned)" }</span>
+                }
+            </p>
+            if component_state.editing {
+                <InputField name={""} prefill={props.message.body.clone()} autofocus=true
+                    on_change={on_edit_submit} />
+            } else {
+                <div class={classes!("message_body")}>
+                    <Markdown source={props.message.body.clone()} />
+                    if props.message.edited_at.is_some() {
+                        <span onclick={on_history_toggle} class={classes!("edited_marker")}>
+                            { " (edited)" }
+                        </span>
+                    }
+                </div>
+            }
+            if !attachments_html.is_empty() {
+                <div class={classes!("attachment_list")}>
+                    { for attachments_html }
+                </div>
+            }
             <p>{"time sent: "}{ time_sent }</p>
+            <div class={classes!("reaction_bar")}>
+                { for reactions_html }
+                <button onclick={on_picker_toggle} class={classes!("reaction")}>
+                    { if component_state.picking_reaction { "x" } else { "+" } }
+                </button>
+                if component_state.picking_reaction {
+                    <div class={classes!("reaction_picker")}>
+                        { for picker_html }
+                    </div>
+                }
+            </div>
+            if props.on_open_thread.is_some() && props.message.thread_root_id.is_none() {
+                <Button label={thread_label} on_click={on_thread_click} />
+            }
+            if is_own_message {
+                <Button label={ if component_state.editing { "Cancel" } else { "Edit" } }
+                    on_click={on_edit_toggle} />
+                <Button label={"Delete"} on_click={on_delete} />
+            } else if props.can_moderate && props.message.deleted_at.is_none() {
+                <Button label={"Delete"} on_click={on_delete} />
+            }
+            if props.can_pin {
+                <Button label={ if props.pinned { "Unpin" } else { "Pin" } } on_click={on_pin_toggle} />
+            }
+            if component_state.history.is_some() {
+                <div class={classes!("message_history")}>
+                    <p>{"Previous versions:"}</p>
+                    { for history_html }
+                </div>
+            }
         </div>
     }
-}
\ No newline at end of file
+}
@@ -30,7 +30,12 @@ pub fn chat_room_preview(props: &Props) -> Html {
 
     html! {
         <div onclick={on_click} class={classes!("message_container")}>
-            <p>{ props.chat.name.clone() }</p>
+            <p>
+                { props.chat.name.clone() }
+                if props.chat.unread_count > 0 {
+                    <span class={classes!("unread_badge")}>{ props.chat.unread_count }</span>
+                }
+            </p>
             <p>{ last_message_preview }</p>
         </div>
     }
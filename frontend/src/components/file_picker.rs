@@ -0,0 +1,37 @@
+use gloo::file::{File, FileList};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub label: String,
+    /// Called with the chosen files each time files are chosen
+    pub on_pick: Callback<Vec<File>>
+}
+
+#[function_component(FilePicker)]
+pub fn file_picker(props: &Props) -> Html {
+    let on_pick = props.on_pick.clone();
+    let internal_on_change = Callback::from(move |event: Event| {
+        let Some(input) = event.target().map(|target| target.unchecked_into::<HtmlInputElement>()) else {
+            return
+        };
+        let files: Vec<File> = match input.files() {
+            Some(files) => FileList::from(files).iter().cloned().collect(),
+            None => Vec::new()
+        };
+        // Clear the input so the same file can be chosen again
+        input.set_value("");
+        if !files.is_empty() {
+            on_pick.emit(files)
+        }
+    });
+
+    html! {
+        <label class={classes!("file_picker")}>
+            { props.label.clone() }
+            <input type="file" multiple=true onchange={internal_on_change} />
+        </label>
+    }
+}
\ No newline at end of file
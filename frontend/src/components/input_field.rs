@@ -11,7 +11,11 @@ pub struct Props {
     pub autofocus: bool,
     #[prop_or(false)]
     pub password: bool,
-    pub on_change: Callback<String>
+    pub on_change: Callback<String>,
+    /// Called with the current value on every edit, rather than once editing
+    /// is finished
+    #[prop_or_default]
+    pub on_input: Option<Callback<String>>
 }
 
 #[function_component(InputField)]
@@ -30,9 +34,22 @@ pub fn input_field(props: &Props) -> Html {
         on_change.emit(value)
     });
 
+    let on_input = props.on_input.clone();
+    let internal_on_input = Callback::from(move |event: InputEvent| {
+        let Some(on_input) = &on_input else {
+            return
+        };
+        let value = match event.target() {
+            Some(target) => target.unchecked_into::<HtmlInputElement>().value(),
+            None => "".to_string()
+        };
+        on_input.emit(value)
+    });
+
     html! {
         <input type={input_type} autofocus={props.autofocus}
             name={props.name.clone()} placeholder={props.name.clone()}
-            value={props.prefill.clone()} onchange={internal_on_change} />
+            value={props.prefill.clone()} onchange={internal_on_change}
+            oninput={internal_on_input} />
     }
 }
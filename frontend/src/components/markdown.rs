@@ -0,0 +1,237 @@
+use common::markdown::{self, Block, Inline};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// Message body to format, see `common::markdown`
+    pub source: String
+}
+
+/// Renders a message body formatted with markdown. The body is never treated
+/// as HTML, all text is inserted as text.
+#[function_component(Markdown)]
+pub fn markdown_view(props: &Props) -> Html {
+    let blocks = markdown::parse(&props.source);
+
+    html! {
+        <div class={classes!("markdown")}>
+            { for blocks.iter().map(render_block) }
+        </div>
+    }
+}
+
+fn render_block(block: &Block) -> Html {
+    match block {
+        Block::Paragraph(inlines) => html! {
+            <p>{ for inlines.iter().map(render_inline) }</p>
+        },
+        Block::CodeBlock { language, code } => {
+            let tokens = match language.as_deref().and_then(syntax) {
+                Some(syntax) => highlight(code, &syntax),
+                None => vec![(TokenKind::Plain, code.clone())],
+            };
+            html! {
+                <pre class={classes!("code_block")}>
+                    <code>{ for tokens.into_iter().map(render_token) }</code>
+                </pre>
+            }
+        },
+        Block::Quote(blocks) => html! {
+            <blockquote>{ for blocks.iter().map(render_block) }</blockquote>
+        },
+        Block::BulletList(items) => html! {
+            <ul class={classes!("markdown_list")}>
+                { for items.iter().map(|item| render_list_item(item)) }
+            </ul>
+        },
+        Block::OrderedList { start, items } => html! {
+            <ol class={classes!("markdown_list")} start={start.to_string()}>
+                { for items.iter().map(|item| render_list_item(item)) }
+            </ol>
+        },
+    }
+}
+
+fn render_list_item(item: &[Block]) -> Html {
+    html! {
+        <li>{ for item.iter().map(render_block) }</li>
+    }
+}
+
+fn render_inline(inline: &Inline) -> Html {
+    match inline {
+        Inline::Text(text) => html! { { text.clone() } },
+        Inline::Bold(inlines) => html! {
+            <strong>{ for inlines.iter().map(render_inline) }</strong>
+        },
+        Inline::Italic(inlines) => html! {
+            <em>{ for inlines.iter().map(render_inline) }</em>
+        },
+        Inline::Code(code) => html! {
+            <code class={classes!("inline_code")}>{ code.clone() }</code>
+        },
+        // Links to anything other than web or mail addresses are shown as text
+        Inline::Link { text, url } if markdown::is_safe_url(url) => html! {
+            <a href={url.clone()} target="_blank" rel="noopener noreferrer">
+                { for text.iter().map(render_inline) }
+            </a>
+        },
+        Inline::Link { text, .. } => html! {
+            <>{ for text.iter().map(render_inline) }</>
+        },
+        Inline::Mention(username) => html! {
+            <span class={classes!("mention")}>{ format!("@{}", username) }</span>
+        },
+        Inline::LineBreak => html! { <br /> },
+    }
+}
+
+// Syntax highlighting of code blocks
+
+#[derive(Clone, Copy, PartialEq)]
+enum TokenKind {
+    Plain,
+    Keyword,
+    String,
+    Number,
+    Comment
+}
+
+/// How to recognise the tokens of a language
+struct Syntax {
+    keywords: &'static [&'static str],
+    /// Keywords match regardless of case, as in SQL
+    ignore_case: bool,
+    line_comments: &'static [&'static str],
+    /// Whether `/* */` comments are used
+    block_comments: bool,
+    quotes: &'static [char]
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+    "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while"
+];
+const JS_KEYWORDS: &[&str] = &[
+    "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "else",
+    "export", "extends", "false", "finally", "for", "function", "if", "import", "in", "instanceof", "interface",
+    "let", "new", "null", "return", "switch", "this", "throw", "true", "try", "type", "typeof", "undefined",
+    "var", "void", "while", "yield"
+];
+const PYTHON_KEYWORDS: &[&str] = &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+    "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None",
+    "nonlocal", "not", "or", "pass", "raise", "return", "True", "try", "while", "with", "yield"
+];
+const C_KEYWORDS: &[&str] = &[
+    "break", "case", "char", "class", "const", "continue", "default", "do", "double", "else", "enum", "extends",
+    "false", "final", "float", "for", "func", "go", "if", "implements", "import", "int", "interface", "long",
+    "new", "nil", "null", "package", "private", "protected", "public", "return", "short", "static", "struct",
+    "switch", "this", "true", "typedef", "unsigned", "var", "void", "while"
+];
+const SQL_KEYWORDS: &[&str] = &[
+    "and", "as", "asc", "by", "create", "delete", "desc", "distinct", "drop", "from", "group", "having", "in",
+    "inner", "insert", "into", "is", "join", "left", "limit", "not", "null", "on", "or", "order", "outer",
+    "select", "set", "table", "update", "values", "where"
+];
+const SHELL_KEYWORDS: &[&str] = &[
+    "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in", "local",
+    "return", "then", "until", "while"
+];
+const JSON_KEYWORDS: &[&str] = &["false", "null", "true"];
+
+/// The syntax of the `language` named after a code fence, if it is known
+fn syntax(language: &str) -> Option<Syntax> {
+    let syntax = match language.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => Syntax {
+            keywords: RUST_KEYWORDS, ignore_case: false, line_comments: &["//"], block_comments: true, quotes: &['"']
+        },
+        "javascript" | "js" | "typescript" | "ts" => Syntax {
+            keywords: JS_KEYWORDS, ignore_case: false, line_comments: &["//"], block_comments: true, quotes: &['"', '\'', '`']
+        },
+        "python" | "py" => Syntax {
+            keywords: PYTHON_KEYWORDS, ignore_case: false, line_comments: &["#"], block_comments: false, quotes: &['"', '\'']
+        },
+        "c" | "cpp" | "c++" | "java" | "go" | "csharp" | "cs" => Syntax {
+            keywords: C_KEYWORDS, ignore_case: false, line_comments: &["//"], block_comments: true, quotes: &['"', '\'']
+        },
+        "sql" | "mysql" => Syntax {
+            keywords: SQL_KEYWORDS, ignore_case: true, line_comments: &["--", "#"], block_comments: true, quotes: &['"', '\'']
+        },
+        "bash" | "sh" | "shell" | "zsh" => Syntax {
+            keywords: SHELL_KEYWORDS, ignore_case: false, line_comments: &["#"], block_comments: false, quotes: &['"', '\'']
+        },
+        "json" => Syntax {
+            keywords: JSON_KEYWORDS, ignore_case: false, line_comments: &[], block_comments: false, quotes: &['"']
+        },
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+/// Split `code` into tokens to be coloured. Consecutive tokens of the same
+/// kind are merged.
+fn highlight(code: &str, syntax: &Syntax) -> Vec<(TokenKind, String)> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut tokens: Vec<(TokenKind, String)> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let rest = &chars[i..];
+        let starts_with = |prefix: &str| prefix.chars().enumerate().all(|(n, c)| rest.get(n) == Some(&c));
+
+        let (kind, len) = if syntax.block_comments && starts_with("/*") {
+            let end = (2..rest.len()).find(|&n| rest[n - 1] == '*' && rest[n] == '/');
+            (TokenKind::Comment, end.map_or(rest.len(), |n| n + 1))
+        } else if syntax.line_comments.iter().any(|prefix| starts_with(prefix)) {
+            (TokenKind::Comment, rest.iter().position(|c| *c == '\n').unwrap_or(rest.len()))
+        } else if syntax.quotes.contains(&rest[0]) {
+            // Runs to the closing quote, or the end of the line
+            let quote = rest[0];
+            let mut n = 1;
+            while n < rest.len() && rest[n] != quote && (rest[n] != '\n' || quote == '`') {
+                n += if rest[n] == '\\' { 2 } else { 1 };
+            }
+            (TokenKind::String, (n + 1).min(rest.len()))
+        } else if rest[0].is_ascii_digit() && (i == 0 || !is_word_char(chars[i - 1])) {
+            (TokenKind::Number, rest.iter().take_while(|c| is_word_char(**c) || **c == '.').count())
+        } else if is_word_char(rest[0]) {
+            let len = rest.iter().take_while(|c| is_word_char(**c)).count();
+            let word: String = rest[..len].iter().collect();
+            let is_keyword = syntax.keywords.iter().any(|keyword| match syntax.ignore_case {
+                true => keyword.eq_ignore_ascii_case(&word),
+                false => *keyword == word,
+            });
+            (if is_keyword { TokenKind::Keyword } else { TokenKind::Plain }, len)
+        } else {
+            (TokenKind::Plain, 1)
+        };
+
+        let text: String = rest[..len].iter().collect();
+        match tokens.last_mut() {
+            Some((last_kind, last_text)) if *last_kind == kind => last_text.push_str(&text),
+            _ => tokens.push((kind, text)),
+        }
+        i += len;
+    }
+
+    tokens
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn render_token((kind, text): (TokenKind, String)) -> Html {
+    let class = match kind {
+        TokenKind::Plain => return html! { { text } },
+        TokenKind::Keyword => "keyword",
+        TokenKind::String => "string",
+        TokenKind::Number => "number",
+        TokenKind::Comment => "comment",
+    };
+    html! {
+        <span class={classes!("token", class)}>{ text }</span>
+    }
+}
\ No newline at end of file
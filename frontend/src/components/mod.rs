@@ -4,4 +4,9 @@ pub mod nav_bar;
 pub mod token_info;
 pub mod chat_room_preview;
 pub mod chat_message;
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod presence;
+pub mod file_picker;
+pub mod markdown;
+pub mod attachment_link;
+pub mod qr_code;
\ No newline at end of file
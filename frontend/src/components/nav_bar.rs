@@ -16,6 +16,10 @@ pub fn NavBar() -> Html {
                 <Link<Route> to={Route::AccountLogin}> {"Login"} </Link<Route>>
             } else {
                 <Link<Route> to={Route::Chats}> {"Chat"} </Link<Route>>
+                <Link<Route> to={Route::Search}> {"Search"} </Link<Route>>
+                <Link<Route> to={Route::Mentions}> {"Mentions"} </Link<Route>>
+                <Link<Route> to={Route::Directory}> {"Browse rooms"} </Link<Route>>
+                <Link<Route> to={Route::Invitations}> {"Invitations"} </Link<Route>>
                 <Link<Route> to={Route::Associations}> {"Associations"} </Link<Route>>
                 <Link<Route> to={Route::AccountManage}> {"Manage Account"} </Link<Route>>
             }
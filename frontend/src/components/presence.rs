@@ -0,0 +1,24 @@
+use common::{PresenceStatus, UserPresence};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct Props {
+    pub presence: UserPresence
+}
+
+#[function_component(PresenceLabel)]
+pub fn presence_label(props: &Props) -> Html {
+    let (status, status_class) = match props.presence.status {
+        PresenceStatus::Online  => ("Online", "online"),
+        PresenceStatus::Away    => ("Away", "away"),
+        PresenceStatus::Offline => ("Offline", "offline"),
+    };
+    let last_seen = match (props.presence.status, props.presence.last_seen) {
+        (PresenceStatus::Online, _) | (_, None) => String::new(),
+        (_, Some(time)) => format!(" - last seen {}", time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+    };
+
+    html! {
+        <p class={classes!("presence", status_class)}>{ status }{ last_seen }</p>
+    }
+}
\ No newline at end of file
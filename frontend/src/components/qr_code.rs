@@ -0,0 +1,36 @@
+use qrcode::{Color, QrCode};
+use yew::prelude::*;
+
+/// Light modules around the code, which scanners need to find its edges
+const QUIET_ZONE: usize = 4;
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub data: String
+}
+
+/// Renders `data` as a QR code, drawn as SVG.
+#[function_component(QrCodeView)]
+pub fn qr_code_view(props: &Props) -> Html {
+    let Ok(code) = QrCode::new(props.data.as_bytes()) else {
+        return html! { <p>{ "Could not create a QR code" }</p> }
+    };
+    let width = code.width();
+    let size = width + QUIET_ZONE * 2;
+
+    let modules = code.to_colors().into_iter()
+        .enumerate()
+        .filter(|(_, color)| *color == Color::Dark)
+        .map(|(index, _)| {
+            let x = (index % width + QUIET_ZONE).to_string();
+            let y = (index / width + QUIET_ZONE).to_string();
+            html! { <rect x={x} y={y} width="1" height="1" /> }
+        });
+
+    html! {
+        <svg class={classes!("qr_code")} viewBox={format!("0 0 {size} {size}")} shape-rendering="crispEdges">
+            <rect width={size.to_string()} height={size.to_string()} fill="white" />
+            <g fill="black">{ for modules }</g>
+        </svg>
+    }
+}
\ No newline at end of file
@@ -2,10 +2,13 @@ use chrono::SecondsFormat;
 use common::LoginTokenInfo;
 use yew::prelude::*;
 
+use crate::components::button::Button;
 
 #[derive(Properties, PartialEq, Clone)]
 pub struct Props {
-    pub info: LoginTokenInfo
+    pub info: LoginTokenInfo,
+    /// Called with the id of the session to revoke
+    pub on_revoke: Callback<String>
 }
 
 #[function_component(TokenInfo)]
@@ -14,11 +17,22 @@ pub fn token_info(props: &Props) -> Html {
         true  => "Yes",
         false => "No",
     };
+    let ip_address = props.info.ip_address.clone().unwrap_or("Unknown".to_string());
+
+    let on_revoke = {
+        let on_revoke = props.on_revoke.clone();
+        let id = props.info.id.clone();
+        Callback::from(move |_: MouseEvent| on_revoke.emit(id.clone()))
+    };
+
     html! {
         <div class={classes!("token_container")}>
             <p>{ "Device name: "}{props.info.user_agent.clone()}</p>
+            <p>{ "IP address: "}{ip_address}</p>
             <p>{ "Initial login: "}{props.info.time_set.to_rfc3339_opts(SecondsFormat::Secs, true)}</p>
+            <p>{ "Last used: "}{props.info.last_used.to_rfc3339_opts(SecondsFormat::Secs, true)}</p>
             <p>{ "This device: "}{is_requester}</p>
+            <Button label={"Revoke"} on_click={Some(on_revoke)} />
         </div>
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use common::LoginTokenInfo;
+use common::{LastSeenVisibility, LoginTokenInfo, PrivacySettings};
 use gloo::console::log;
 use yew::prelude::*;
 use yew_router::{hooks::use_navigator, prelude::Redirect};
@@ -33,6 +33,21 @@ pub fn account_management_page() -> Html {
 
     // Component state
     let token_info = use_state_eq(|| Vec::<LoginTokenInfo>::new());
+    let privacy = use_state_eq(|| None::<PrivacySettings>);
+
+    // Fetch privacy settings once
+    {
+        let privacy = privacy.clone();
+        let user_token = store.user.clone().unwrap().token;
+        use_effect_with(user_token, move |user_token| {
+            let user_token = *user_token;
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(settings) = api_service::account_get_privacy(&user_token).await {
+                    privacy.set(Some(settings));
+                }
+            });
+        });
+    }
 
     // Update token_info state if needed
     if let Some(user_data) = store.user.clone() {
@@ -45,9 +60,39 @@ pub fn account_management_page() -> Html {
         })
     }
 
+    let on_revoke_token = {
+        let token_info = token_info.clone();
+        let navigator = navigator.clone();
+        let store = store.clone();
+        let dispatch = dispatch.clone();
+        Callback::from(move |session_id: String| {
+            let token_info = token_info.clone();
+            let navigator = navigator.clone();
+            let dispatch = dispatch.clone();
+            let Some(user_data) = store.user.clone() else {
+                log!("Revoke request failed - Missing auth token");
+                return
+            };
+            // Revoking this device's own session logs it out
+            let is_requester = token_info.iter().any(|info| info.id == session_id && info.is_requester);
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(_) = api_service::account_revoke_session(&user_data.token, &session_id).await {
+                    log!("Revoke request failed");
+                    return
+                }
+                if is_requester {
+                    dispatch.logout_reduce();
+                    navigator.push(&Route::Home);
+                } else if let Ok(info) = api_service::account_get_active_token_info(&user_data.token).await {
+                    token_info.set(info);
+                }
+            });
+        })
+    };
+
     // Convert token info to renderable items
     let token_children_info: Vec<Html> = token_info.iter()
-        .map(|info| html! { <TokenInfo info={info.clone()}/>})
+        .map(|info| html! { <TokenInfo info={info.clone()} on_revoke={on_revoke_token.clone()}/>})
         .collect();
 
     let on_refresh_tokens = {
@@ -115,6 +160,45 @@ pub fn account_management_page() -> Html {
         })
     };
 
+    let on_toggle_last_seen = {
+        let privacy = privacy.clone();
+        let store = store.clone();
+        Callback::from(move |_: MouseEvent| {
+            let privacy = privacy.clone();
+            let last_seen_visibility = match privacy.as_ref() {
+                Some(PrivacySettings { last_seen_visibility: LastSeenVisibility::Everyone }) => LastSeenVisibility::Friends,
+                Some(PrivacySettings { last_seen_visibility: LastSeenVisibility::Friends }) => LastSeenVisibility::Everyone,
+                None => return
+            };
+            if let Some(user_data) = store.user.clone() {
+                wasm_bindgen_futures::spawn_local(async move {
+                    let settings = PrivacySettings { last_seen_visibility };
+                    if let Ok(_) = api_service::account_set_privacy(&user_data.token, &settings).await {
+                        privacy.set(Some(settings));
+                    } else {
+                        log!("Privacy update failed");
+                    }
+                });
+            }
+        })
+    };
+
+    let last_seen_html = match privacy.as_ref() {
+        Some(settings) => {
+            let (shown_to, toggle_label) = match settings.last_seen_visibility {
+                LastSeenVisibility::Everyone => ("everyone", "Show to friends only"),
+                LastSeenVisibility::Friends => ("friends only", "Show to everyone"),
+            };
+            html! {
+                <div>
+                    <p>{ format!("Last seen time shown to {}", shown_to) }</p>
+                    <Button label={toggle_label} on_click={Some(on_toggle_last_seen)} />
+                </div>
+            }
+        },
+        None => html! {}
+    };
+
     let on_change_password = {
         let navigator = navigator.clone();
         Callback::from(move |_: MouseEvent| {
@@ -122,12 +206,23 @@ pub fn account_management_page() -> Html {
         })
     };
 
+    let on_manage_totp = {
+        let navigator = navigator.clone();
+        Callback::from(move |_: MouseEvent| {
+            navigator.push(&Route::AccountTotp);
+        })
+    };
+
     html! {
         <>
             <h>{"Currently logged in devices/tokens"}</h>
             <Button label={"Refresh list"} on_click={Some(on_refresh_tokens)} />
             <ListView children={token_children_info} />
             <Button label={"Change password"} on_click={Some(on_change_password)} />
+            <Button label={"Two-factor authentication"} on_click={Some(on_manage_totp)} />
+            <br />
+            <h>{"Privacy"}</h>
+            { last_seen_html }
             <br />
             <Button label={"Log out"} on_click={Some(on_logout)} />
             <br />
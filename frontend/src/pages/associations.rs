@@ -2,17 +2,21 @@ use std::ops::Deref;
 
 use common::{UserAssociationUpdate, UserAssociations};
 use yew::prelude::*;
-use yew_router::prelude::Redirect;
+use yew_router::prelude::{use_navigator, Redirect};
 use yewdux::use_store;
 
 use crate::{
     api_service,
     components::{
         button::Button,
+        presence::PresenceLabel,
         user::UserDetailComponent
     },
     router::Route,
-    store::Store,
+    store::{
+        Store,
+        StoreDispatchExt
+    },
     widgets::user_search::UserSearch
 };
 
@@ -35,7 +39,7 @@ impl Default for State {
 
 #[function_component(AssociationsPage)]
 pub fn associations_page() -> Html {
-    let (store, _) = use_store::<Store>();
+    let (store, dispatch) = use_store::<Store>();
 
     if store.user.is_none() {
         return html! {
@@ -109,6 +113,13 @@ pub fn associations_page() -> Html {
         });
     });
 
+    // Open the direct room with a friend, which the first message creates
+    let navigator = use_navigator().unwrap();
+    let on_message = Callback::from(move |user: common::UserInfo| {
+        dispatch.id_to_name_set_reduce(user.id, user.username);
+        navigator.push(&Route::Direct { user_id: user.id });
+    });
+
     let search_list_buttons = vec![
         ("Add friend".to_string(), on_add_friend_association.clone()),
         ("Block".to_string(), on_block_association.clone())
@@ -157,13 +168,21 @@ pub fn associations_page() -> Html {
                     <ul>
                     {
                         component_state.associations.friends.iter()
-                        .map(|user| {
-                            let user_id = user.id;
+                        .map(|friend| {
+                            let user_id = friend.user.id;
+                            let user = friend.user.clone();
+                            let message_callback = on_message.clone();
                             let remove_callback = on_remove_association.clone();
                             let block_callback = on_block_association.clone();
                             html! {
                                 <div class={classes!("user_button_row")}>
                                     <UserDetailComponent data={user.clone()} />
+                                    <PresenceLabel presence={friend.presence.clone()} />
+                                    <Button label={"Message"} on_click={
+                                        Callback::from(move |_: MouseEvent| {
+                                            message_callback.emit(user.clone())
+                                        })
+                                    } />
                                     <Button label={"Remove"} on_click={
                                         Callback::from(move |_: MouseEvent| {
                                             remove_callback.emit(user_id)
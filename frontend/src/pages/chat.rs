@@ -1,19 +1,29 @@
-use std::ops::Deref;
+use std::{ops::Deref, rc::Rc};
 
-use common::{ChatRoom, ChatRoomManageUser, ChatRoomManageUserAction, UserInfo};
+use chrono::{DateTime, Utc};
+use common::{AuthToken, ChatAttachment, ChatPinnedMessage, ChatRoom, ChatRoomManageUser, ChatRoomManageUserAction, ChatRoomMember, ReactionChange, ReactionCount, RoomEvent, RoomEventKind, RoomKind, RoomRole, RoomVisibility, UserInfo, SOCKET_RESYNC_MESSAGE, TYPING_TIMEOUT_SECS};
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+    stream,
+    StreamExt
+};
 use yew::prelude::*;
-use yew_router::prelude::Redirect;
+use yew_router::prelude::{use_navigator, Redirect};
 use yewdux::use_store;
 
-use gloo::console::log;
+use gloo::{console::log, file::File, net::websocket::Message, timers::future::TimeoutFuture};
 
 use crate::{
-    api_service,
+    api_service::{self, MessageCursor},
     components::{
         button::Button,
         chat_message::ChatMessage,
         chat_room_preview::ChatRoomPreview,
+        file_picker::FilePicker,
         input_field::InputField,
+        markdown::Markdown,
+        presence::PresenceLabel,
         user::UserDetailComponent
     },
     router::Route,
@@ -22,11 +32,16 @@ use crate::{
         StoreDispatchExt
     },
     widgets::{
-        list_view::ListView, new_room_form::NewRoomForm, user_search::UserSearch
+        invite_codes::InviteCodes, list_view::ListView, new_room_form::NewRoomForm, user_search::UserSearch
     },
 };
 
 const MSG_WINDOW_SIZE: u64 = 5;
+const THREAD_PAGE_SIZE: u64 = 20;
+/// Most member names suggested while a mention is being typed
+const MAX_MENTION_SUGGESTIONS: usize = 5;
+/// Wait before reconnecting the event stream, as the browser would
+const STREAM_RECONNECT_DELAY_MS: u32 = 3000;
 
 #[derive(PartialEq, Debug, Clone)]
 enum MsgSendStatus {
@@ -44,19 +59,44 @@ enum MainPanelMode {
 #[derive(PartialEq, Clone)]
 enum MemberPanelMode {
     ViewMembers,
-    AddMembers
+    AddMembers,
+    InviteCodes
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// Open the chat room at the window surrounding a message, given as
+    /// `(room_id, message_id)`
+    #[prop_or_default]
+    pub focus: Option<(u64, u64)>,
+    /// Open the chat room with this id at its latest messages
+    #[prop_or_default]
+    pub room: Option<u64>
 }
 
 #[derive(PartialEq, Clone)]
 struct State {
     chat_room_list: Vec::<ChatRoom>,
     selected_room_id: Option<u64>,
-    selected_room_pos: u64,
     selected_room_name: String,
     selected_room_messages: Vec<common::ChatMessage>,
-    selected_room_exhausted: bool,
-    selected_room_members: Vec<UserInfo>,
+    /// Cursor of the next page of older messages, `None` once exhausted
+    selected_room_cursor: Option<u64>,
+    /// Cursor of the next page of newer messages, `None` when the latest
+    /// messages are listed
+    selected_room_newer_cursor: Option<u64>,
+    focused_message_id: Option<u64>,
+    selected_room_members: Vec<ChatRoomMember>,
+    thread_root: Option<common::ChatMessage>,
+    thread_replies: Vec<common::ChatMessage>,
+    /// Cursor of the next page of replies, `None` once exhausted
+    thread_cursor: Option<u64>,
     sending_status: MsgSendStatus,
+    /// Uploaded to the selected room, to be attached to the next message sent
+    pending_attachments: Vec<ChatAttachment>,
+    /// Pinned in the selected room, most recently pinned first
+    pinned_messages: Vec<ChatPinnedMessage>,
+    showing_pinned: bool,
     main_panel_mode: MainPanelMode,
     member_panel_mode: MemberPanelMode
 }
@@ -66,20 +106,297 @@ impl Default for State {
         Self {
             chat_room_list: Vec::with_capacity(0),
             selected_room_id: None,
-            selected_room_pos: MSG_WINDOW_SIZE,
             selected_room_name: "".to_string(),
             selected_room_messages: Vec::with_capacity(0),
-            selected_room_exhausted: false,
+            selected_room_cursor: None,
+            selected_room_newer_cursor: None,
+            focused_message_id: None,
             selected_room_members: Vec::with_capacity(0),
+            thread_root: None,
+            thread_replies: Vec::with_capacity(0),
+            thread_cursor: None,
             sending_status: MsgSendStatus::Idle,
+            pending_attachments: Vec::with_capacity(0),
+            pinned_messages: Vec::with_capacity(0),
+            showing_pinned: false,
             main_panel_mode: MainPanelMode::Messages,
             member_panel_mode: MemberPanelMode::ViewMembers
         }
     }
 }
 
+/// A change pushed by the server that is yet to be applied to the page state.
+#[derive(PartialEq, Clone)]
+enum LiveUpdate {
+    Event(RoomEvent),
+    /// Events were missed, so the selected room must be re-fetched.
+    Resync
+}
+
+#[derive(PartialEq, Default)]
+struct IncomingUpdates {
+    queue: Vec<LiveUpdate>
+}
+
+enum IncomingAction {
+    Push(LiveUpdate),
+    Drain(usize)
+}
+
+impl Reducible for IncomingUpdates {
+    type Action = IncomingAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut queue = self.queue.clone();
+        match action {
+            IncomingAction::Push(update) => queue.push(update),
+            IncomingAction::Drain(count) => {
+                queue.drain(..count.min(queue.len()));
+            },
+        }
+        Rc::new(Self { queue })
+    }
+}
+
+/// Members shown as typing, as `(room_id, member, received_at)` where
+/// `received_at` is when their latest `Typing` event arrived.
+#[derive(PartialEq, Default)]
+struct TypingMembers {
+    typing: Vec<(u64, UserInfo, DateTime<Utc>)>
+}
+
+enum TypingAction {
+    /// A `Typing` event arrived from the member in the room
+    Started(u64, UserInfo, DateTime<Utc>),
+    /// The typing indicator from the `Typing` event that arrived at the
+    /// provided time has timed out, given as `(room_id, user_id, received_at)`
+    Expired(u64, u64, DateTime<Utc>),
+    /// The member sent a message, given as `(room_id, user_id)`
+    Stopped(u64, u64)
+}
+
+impl Reducible for TypingMembers {
+    type Action = TypingAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut typing = self.typing.clone();
+        match action {
+            TypingAction::Started(room_id, member, received_at) => {
+                typing.retain(|(typing_room_id, typing_member, _)| {
+                    (*typing_room_id, typing_member.id) != (room_id, member.id)
+                });
+                typing.push((room_id, member, received_at));
+            },
+            // A later event refreshed the indicator, so it is kept
+            TypingAction::Expired(room_id, user_id, received_at) => {
+                typing.retain(|entry| (entry.0, entry.1.id, entry.2) != (room_id, user_id, received_at));
+            },
+            TypingAction::Stopped(room_id, user_id) => {
+                typing.retain(|(typing_room_id, typing_member, _)| {
+                    (*typing_room_id, typing_member.id) != (room_id, user_id)
+                });
+            },
+        }
+        Rc::new(Self { typing })
+    }
+}
+
+/// Apply a reaction being added to or removed from a message in `messages`,
+/// if it is listed.
+fn apply_reaction_change(messages: &mut [common::ChatMessage], change: &ReactionChange, own_user_id: u64, added: bool) {
+    let reacted = change.user_id == own_user_id;
+    let listed = messages.iter_mut()
+        .find(|listed| listed.id == Some(change.message_id));
+    let Some(listed) = listed else {
+        return
+    };
+    match (listed.reactions.iter_mut().find(|count| count.emoji == change.emoji), added) {
+        (Some(count), true) => {
+            count.count += 1;
+            count.reacted |= reacted;
+        },
+        (None, true) => listed.reactions.push(ReactionCount {
+            emoji: change.emoji.clone(),
+            count: 1,
+            reacted
+        }),
+        (Some(count), false) => {
+            count.count = count.count.saturating_sub(1);
+            count.reacted &= !reacted;
+        },
+        (None, false) => {},
+    }
+    listed.reactions.retain(|count| count.count > 0);
+}
+
+/// Mark the listed messages of the selected room as read, once the latest
+/// messages are listed.
+async fn mark_listed_read(token: &AuthToken, state: &mut State) {
+    let Some(room_id) = state.selected_room_id else {
+        return
+    };
+    if state.selected_room_newer_cursor.is_some() {
+        return
+    }
+    let Some(message_id) = state.selected_room_messages.last().and_then(|message| message.id) else {
+        return
+    };
+    if let Ok(()) = api_service::chat_mark_read(token, room_id, message_id).await {
+        state.chat_room_list.iter_mut()
+            .filter(|room| room.id == room_id)
+            .for_each(|room| room.unread_count = 0);
+    }
+}
+
+/// Mark the replies of the open thread as read, once the latest replies are
+/// listed, so that mentions within them are no longer unread. Other threads
+/// and the room itself are read separately.
+async fn mark_thread_read(token: &AuthToken, state: &State) {
+    let Some(room_id) = state.selected_room_id else {
+        return
+    };
+    if state.thread_cursor.is_some() {
+        return
+    }
+    let Some(message_id) = state.thread_replies.last().and_then(|message| message.id) else {
+        return
+    };
+    if let Err(e) = api_service::chat_mark_read(token, room_id, message_id).await {
+        log!(format!("{:?}", e));
+    }
+}
+
+enum FeedEnd {
+    Stopped,
+    Failed
+}
+
+/// Queue room events received over the chat socket until it closes, or
+/// `stop` is signalled.
+async fn receive_socket_events(
+    token: &AuthToken,
+    incoming: &UseReducerDispatcher<IncomingUpdates>,
+    stop: &mut oneshot::Receiver<()>
+) -> FeedEnd {
+    let opened = match api_service::account_get_ticket(token).await {
+        Ok(ticket) => api_service::chat_open_socket(&ticket),
+        Err(e) => Err(e),
+    };
+    let mut socket = match opened {
+        Ok(socket) => socket,
+        Err(_) => return FeedEnd::Failed,
+    };
+
+    // Dropping the socket closes it
+    loop {
+        let received = match future::select(socket.next(), &mut *stop).await {
+            Either::Left((received, _)) => received,
+            Either::Right(_) => return FeedEnd::Stopped,
+        };
+        match received {
+            Some(Ok(Message::Text(text))) if text == SOCKET_RESYNC_MESSAGE => {
+                incoming.dispatch(IncomingAction::Push(LiveUpdate::Resync))
+            },
+            Some(Ok(Message::Text(text))) => match serde_json::from_str::<RoomEvent>(&text) {
+                Ok(event) => incoming.dispatch(IncomingAction::Push(LiveUpdate::Event(event))),
+                Err(_) => log!("Failed to parse event from chat socket"),
+            },
+            Some(Ok(_)) => {},
+            Some(Err(_)) | None => return FeedEnd::Failed,
+        }
+    }
+}
+
+/// Queue room events received over the event stream until `stop` is
+/// signalled. Used when the chat socket is unavailable.
+async fn receive_stream_events(
+    token: &AuthToken,
+    incoming: &UseReducerDispatcher<IncomingUpdates>,
+    stop: &mut oneshot::Receiver<()>
+) {
+    // Each connection needs a new ticket, so the stream is reconnected here
+    // rather than by the browser, resuming from the last received event
+    let mut last_event_id = None;
+    loop {
+        if let FeedEnd::Stopped = receive_stream_connection(token, incoming, stop, &mut last_event_id).await {
+            return
+        }
+        match future::select(TimeoutFuture::new(STREAM_RECONNECT_DELAY_MS), &mut *stop).await {
+            Either::Left(_) => {},
+            Either::Right(_) => return,
+        }
+    }
+}
+
+/// Queue room events received over a single connection of the event stream,
+/// until it fails or `stop` is signalled. `last_event_id` is kept up to date
+/// with the received events.
+async fn receive_stream_connection(
+    token: &AuthToken,
+    incoming: &UseReducerDispatcher<IncomingUpdates>,
+    stop: &mut oneshot::Receiver<()>,
+    last_event_id: &mut Option<u64>
+) -> FeedEnd {
+    let opened = match api_service::account_get_ticket(token).await {
+        Ok(ticket) => api_service::chat_open_event_stream(&ticket, *last_event_id),
+        Err(e) => Err(e),
+    };
+    let mut event_source = match opened {
+        Ok(event_source) => event_source,
+        Err(_) => return FeedEnd::Failed,
+    };
+
+    let (room_events, resyncs) = match (event_source.subscribe("room_event"), event_source.subscribe("resync")) {
+        (Ok(room_events), Ok(resyncs)) => (room_events, resyncs),
+        _ => {
+            log!("Failed to subscribe to event stream");
+            return FeedEnd::Failed
+        }
+    };
+    let mut received_events = stream::select(room_events, resyncs);
+
+    // Dropping the event source closes it
+    loop {
+        let received = match future::select(received_events.next(), &mut *stop).await {
+            Either::Left((received, _)) => received,
+            Either::Right(_) => return FeedEnd::Stopped,
+        };
+        match received {
+            Some(Ok((event_type, _))) if event_type == "resync" => {
+                incoming.dispatch(IncomingAction::Push(LiveUpdate::Resync))
+            },
+            Some(Ok((_, message))) => {
+                if let Ok(id) = message.last_event_id().parse() {
+                    *last_event_id = Some(id);
+                }
+                let event = message.data().as_string()
+                    .and_then(|data| serde_json::from_str::<RoomEvent>(&data).ok());
+                match event {
+                    Some(event) => incoming.dispatch(IncomingAction::Push(LiveUpdate::Event(event))),
+                    None => log!("Failed to parse event from event stream"),
+                }
+            },
+            // The browser would reconnect with the used ticket, so reconnect
+            // with a new one instead
+            Some(Err(_)) | None => return FeedEnd::Failed,
+        }
+    }
+}
+
+/// The mention being typed at the end of `draft`, as the index of its `@` along
+/// with the part of the username typed so far.
+fn partial_mention(draft: &str) -> Option<(usize, &str)> {
+    let at = draft.rfind('@')?;
+    let partial = &draft[at + 1..];
+    let at_word_start = !draft[..at].chars().next_back().is_some_and(|c| c.is_alphanumeric());
+    match at_word_start && partial.chars().all(|c| c.is_ascii_alphanumeric()) {
+        true => Some((at, partial)),
+        false => None
+    }
+}
+
 #[function_component(ChatPage)]
-pub fn chat_page() -> Html {
+pub fn chat_page(props: &Props) -> Html {
     // Global state
     let (store, dispatch) = use_store::<Store>();
 
@@ -91,9 +408,40 @@ pub fn chat_page() -> Html {
     }
 
     let token = store.user.clone().unwrap().token.clone();
+    let own_user_id = store.user.clone().unwrap().user_id;
 
+    let navigator = use_navigator().unwrap();
     let component_state = use_state_eq(|| State::default());
 
+    // Receive room events while the page is open. The event stream is used
+    // when the chat socket is unavailable.
+    let incoming = use_reducer_eq(IncomingUpdates::default);
+    {
+        let incoming = incoming.dispatcher();
+        use_effect_with(token, move |token| {
+            let token = *token;
+            let (stop_sender, mut stop_receiver) = oneshot::channel::<()>();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let FeedEnd::Failed = receive_socket_events(&token, &incoming, &mut stop_receiver).await {
+                    log!("Chat socket unavailable, using event stream");
+                    receive_stream_events(&token, &incoming, &mut stop_receiver).await;
+                }
+            });
+            move || {
+                let _ = stop_sender.send(());
+            }
+        });
+    }
+
+    let typing_members = use_reducer_eq(TypingMembers::default);
+
+    // Last typing signal sent, as `(room_id, sent_at)`, so that signals are
+    // repeated no more often than the server passes them on
+    let last_typing_signal = use_mut_ref(|| None::<(u64, DateTime<Utc>)>);
+
+    // Message being written, so that mentions can be completed
+    let message_draft = use_state_eq(String::new);
+
     // Retrieve chat room state
     let state_handle = component_state.clone();
     wasm_bindgen_futures::spawn_local(async move {
@@ -104,6 +452,60 @@ pub fn chat_page() -> Html {
         }
     });
     
+    // Open the requested room. With a focused message, the message is in the
+    // middle of the listed messages.
+    {
+        let state_handle = component_state.clone();
+        let target = props.focus
+            .map(|(room_id, message_id)| (room_id, Some(message_id)))
+            .or(props.room.map(|room_id| (room_id, None)));
+        use_effect_with(target, move |target| {
+            if let Some((room_id, focus_id)) = *target {
+                wasm_bindgen_futures::spawn_local(async move {
+                    let Ok(rooms) = api_service::chat_get_rooms(&token).await else {
+                        return
+                    };
+                    let Some(room) = rooms.iter().find(|room| room.id == room_id) else {
+                        return
+                    };
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.selected_room_name = room.name.clone();
+                    updated_state.selected_room_id = Some(room_id);
+                    updated_state.chat_room_list = rooms;
+                    updated_state.thread_root = None;
+                    updated_state.thread_replies.clear();
+                    updated_state.pending_attachments.clear();
+                    updated_state.main_panel_mode = MainPanelMode::Messages;
+                    updated_state.focused_message_id = focus_id;
+
+                    if let Some(message_id) = focus_id {
+                        // Messages up to and including the focused message, then those after it
+                        let older = api_service::chat_get_messages(&token, room_id, MessageCursor::Before(message_id + 1), MSG_WINDOW_SIZE).await;
+                        let newer = api_service::chat_get_messages(&token, room_id, MessageCursor::After(message_id), MSG_WINDOW_SIZE).await;
+                        if let (Ok(older), Ok(newer)) = (older, newer) {
+                            updated_state.selected_room_cursor = older.next_cursor;
+                            updated_state.selected_room_newer_cursor = newer.next_cursor;
+                            updated_state.selected_room_messages = older.messages.into_iter()
+                                .chain(newer.messages)
+                                .collect();
+                        }
+                    } else if let Ok(page) = api_service::chat_get_messages(&token, room_id, MessageCursor::Latest, MSG_WINDOW_SIZE).await {
+                        updated_state.selected_room_cursor = page.next_cursor;
+                        updated_state.selected_room_newer_cursor = None;
+                        updated_state.selected_room_messages = page.messages;
+                    }
+                    if let Ok(members) = api_service::chat_get_members(&token, room_id).await {
+                        updated_state.selected_room_members = members;
+                    }
+                    updated_state.pinned_messages = api_service::chat_get_pinned_messages(&token, room_id).await
+                        .unwrap_or_default();
+                    mark_listed_read(&token, &mut updated_state).await;
+                    state_handle.set(updated_state);
+                });
+            }
+        });
+    }
+
     let state_handle = component_state.clone();
     let on_chat_room_select = {
         let state_handle = state_handle.clone();
@@ -114,19 +516,19 @@ pub fn chat_page() -> Html {
             let room = state_handle.chat_room_list.iter().find(|room| room.id.eq(&chat_id)).unwrap();
             updated_state.selected_room_name = room.name.clone();
             updated_state.selected_room_id = Some(chat_id);
-            updated_state.selected_room_exhausted = false;
+            updated_state.thread_root = None;
+            updated_state.thread_replies.clear();
+            updated_state.pending_attachments.clear();
             updated_state.main_panel_mode = MainPanelMode::Messages;
+            updated_state.member_panel_mode = MemberPanelMode::ViewMembers;
+            updated_state.focused_message_id = None;
             wasm_bindgen_futures::spawn_local(async move {
                 // Messages
-                match api_service::chat_get_messages(&token, chat_id, 0, MSG_WINDOW_SIZE).await {
-                    Ok(mut messages) => {
-                        let room_starting_pos = u64::try_from(messages.len()).unwrap_or_else(|_| {
-                            log!("Failed to parse retrieved message count");
-                            0
-                        });
-                        updated_state.selected_room_pos = room_starting_pos;
-                        messages.reverse();
-                        updated_state.selected_room_messages = messages;
+                match api_service::chat_get_messages(&token, chat_id, MessageCursor::Latest, MSG_WINDOW_SIZE).await {
+                    Ok(page) => {
+                        updated_state.selected_room_cursor = page.next_cursor;
+                        updated_state.selected_room_newer_cursor = None;
+                        updated_state.selected_room_messages = page.messages;
                     },
                     _ => {}
                 }
@@ -135,6 +537,10 @@ pub fn chat_page() -> Html {
                     Ok(members) => updated_state.selected_room_members = members,
                     Err(_) => {},
                 }
+                // Pinned messages
+                updated_state.pinned_messages = api_service::chat_get_pinned_messages(&token, chat_id).await
+                    .unwrap_or_default();
+                mark_listed_read(&token, &mut updated_state).await;
                 state_handle.set(updated_state);
             });
         })
@@ -169,6 +575,16 @@ pub fn chat_page() -> Html {
         })
     };
     
+    let state_handle = component_state.clone();
+    let to_invite_codes = {
+        Callback::from(move |_: MouseEvent| {
+            let state_handle = state_handle.clone();
+            let mut updated_state = state_handle.deref().clone();
+            updated_state.member_panel_mode = MemberPanelMode::InviteCodes;
+            state_handle.set(updated_state);
+        })
+    };
+    
     let state_handle = component_state.clone();
     let on_add_member = Callback::from(move |user_id_to_add: u64| {
         let room_id = state_handle.selected_room_id.unwrap();
@@ -222,6 +638,64 @@ pub fn chat_page() -> Html {
         });
     });
 
+    let state_handle = component_state.clone();
+    let on_set_visibility = Callback::from(move |visibility: RoomVisibility| {
+        let room_id = state_handle.selected_room_id.unwrap();
+        let state_handle = state_handle.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match api_service::chat_set_visibility(&token, room_id, visibility).await {
+                Ok(()) => {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.chat_room_list.iter_mut()
+                        .filter(|room| room.id == room_id)
+                        .for_each(|room| room.visibility = visibility);
+                    state_handle.set(updated_state)
+                },
+                Err(e) => log!(format!("{:?}", e)),
+            }
+        });
+    });
+
+    let state_handle = component_state.clone();
+    let on_leave_room = Callback::from(move |_: MouseEvent| {
+        let room_id = state_handle.selected_room_id.unwrap();
+        let state_handle = state_handle.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match api_service::chat_leave_room(&token, room_id).await {
+                Ok(()) => {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.chat_room_list.retain(|room| room.id != room_id);
+                    updated_state.selected_room_id = None;
+                    updated_state.selected_room_messages.clear();
+                    updated_state.selected_room_members.clear();
+                    updated_state.pinned_messages.clear();
+                    updated_state.thread_root = None;
+                    updated_state.thread_replies.clear();
+                    state_handle.set(updated_state)
+                },
+                Err(e) => log!(format!("{:?}", e)),
+            }
+        });
+    });
+
+    let state_handle = component_state.clone();
+    let on_change_role = Callback::from(move |(user_id, role): (u64, RoomRole)| {
+        let room_id = state_handle.selected_room_id.unwrap();
+        let state_handle = state_handle.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match api_service::chat_change_member_role(&token, room_id, user_id, role).await {
+                Ok(()) => {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.selected_room_members.iter_mut()
+                        .filter(|member| member.id == user_id)
+                        .for_each(|member| member.role = role);
+                    state_handle.set(updated_state)
+                },
+                Err(e) => log!(format!("{:?}", e)),
+            }
+        });
+    });
+
     let state_handle = component_state.clone();
     let on_load_more_messages = {
         Callback::from(move |_: MouseEvent| {
@@ -229,26 +703,44 @@ pub fn chat_page() -> Html {
             let mut updated_state = state_handle.deref().clone();
             wasm_bindgen_futures::spawn_local(async move {
                 let room_id = state_handle.selected_room_id.unwrap();
-                let offset = state_handle.selected_room_pos;
-                match api_service::chat_get_messages(&token, room_id, offset, MSG_WINDOW_SIZE).await {
-                    Ok(next_messages) if next_messages.len() > 0 => {
-                        // Join existing messages to newly fetched messages
-                        let chained_iter = next_messages.iter()
-                            .rev()
-                            .map(|msg| msg.clone())
+                let Some(before_id) = state_handle.selected_room_cursor else {
+                    return
+                };
+                match api_service::chat_get_messages(&token, room_id, MessageCursor::Before(before_id), MSG_WINDOW_SIZE).await {
+                    Ok(page) => {
+                        // Join existing messages to newly fetched (older) messages
+                        let chained_iter = page.messages.into_iter()
                             .chain(state_handle.selected_room_messages.iter().map(|msg| msg.clone()));
 
                         let new_message_list = Vec::from_iter(chained_iter);
                         updated_state.selected_room_messages = new_message_list;
+                        updated_state.selected_room_cursor = page.next_cursor;
+                    },
+                    Err(_) => {},
+                }
+                state_handle.set(updated_state);
+            });
+        })
+    };
 
-                        // Adjust position of the window for next message fetch/load more messages
-                        updated_state.selected_room_pos = updated_state.selected_room_pos + MSG_WINDOW_SIZE;
+    let state_handle = component_state.clone();
+    let on_load_newer_messages = {
+        Callback::from(move |_: MouseEvent| {
+            let state_handle = state_handle.clone();
+            let mut updated_state = state_handle.deref().clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let room_id = state_handle.selected_room_id.unwrap();
+                let Some(after_id) = state_handle.selected_room_newer_cursor else {
+                    return
+                };
+                match api_service::chat_get_messages(&token, room_id, MessageCursor::After(after_id), MSG_WINDOW_SIZE).await {
+                    Ok(page) => {
+                        updated_state.selected_room_messages.extend(page.messages);
+                        updated_state.selected_room_newer_cursor = page.next_cursor;
                     },
-                    Ok(_) => {
-                        updated_state.selected_room_exhausted = true;
-                    }
                     Err(_) => {},
                 }
+                mark_listed_read(&token, &mut updated_state).await;
                 state_handle.set(updated_state);
             });
         })
@@ -269,35 +761,152 @@ pub fn chat_page() -> Html {
                 room_id: state_handle.selected_room_id.unwrap(),
                 sender_id: None,
                 body: text,
-                time_sent: None
+                time_sent: None,
+                edited_at: None,
+                deleted_at: None,
+                reactions: Vec::new(),
+                reply_to: None,
+                thread_root_id: None,
+                reply_count: 0,
+                attachments: updated_state.pending_attachments.clone()
             };
 
-            let message_clone = message.clone();
-            let room_id = message.room_id;
             wasm_bindgen_futures::spawn_local(async move {
-                // Send message and update state
-                match api_service::chat_send_message(&token, message_clone).await {
-                    Ok(()) => updated_state.sending_status = MsgSendStatus::Idle,
-                    Err(_) => updated_state.sending_status = MsgSendStatus::Failed,
+                // Send message. On success it is delivered back over the chat socket.
+                let sending_status = match api_service::chat_send_message(&token, message).await {
+                    Ok(()) => MsgSendStatus::Idle,
+                    Err(_) => MsgSendStatus::Failed,
                 };
 
-                // If success (back in idle state) add the send message to the local message list
-                if let MsgSendStatus::Idle = updated_state.sending_status {
-                    match api_service::chat_get_messages(&token, room_id, 0, MSG_WINDOW_SIZE).await {
-                        Ok(messages) => {
-                            updated_state.selected_room_messages = messages;
-                            // reset open chat room state
-                            updated_state.selected_room_pos = 0;
-                            updated_state.selected_room_exhausted = false;
-                        },
-                        _ => log!("Failed to retrieve messages after sending one")
-                    }
+                // Attachments can only be sent once
+                let sent_attachments = sending_status == MsgSendStatus::Idle && !updated_state.pending_attachments.is_empty();
+                if sent_attachments {
+                    updated_state.pending_attachments.clear();
+                }
+                if updated_state.sending_status.ne(&sending_status) || sent_attachments {
+                    updated_state.sending_status = sending_status;
+                    state_handle.set(updated_state);
                 }
-                state_handle.set(updated_state);
             });
         })
     };
+
+    // Upload the chosen files straight away, to be attached to the next message
+    let state_handle = component_state.clone();
+    let on_attach_files = Callback::from(move |files: Vec<File>| {
+        let Some(room_id) = state_handle.selected_room_id else {
+            return
+        };
+        let state_handle = state_handle.clone();
+        let mut updated_state = state_handle.deref().clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            for file in files {
+                let contents = match gloo::file::futures::read_as_bytes(&file).await {
+                    Ok(contents) => contents,
+                    Err(e) => {
+                        log!(format!("Failed to read {}: {:?}", file.name(), e));
+                        continue
+                    }
+                };
+                match api_service::chat_upload_attachment(&token, room_id, file.name(), &file.raw_mime_type(), contents).await {
+                    Ok(attachment) => updated_state.pending_attachments.push(attachment),
+                    Err(e) => log!(format!("Failed to upload {}: {:?}", file.name(), e)),
+                }
+            }
+            state_handle.set(updated_state);
+        });
+    });
+
+    let state_handle = component_state.clone();
+    let on_remove_pending_attachment = Callback::from(move |attachment_id: u64| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.pending_attachments.retain(|attachment| attachment.id != attachment_id);
+        state_handle.set(updated_state);
+    });
     
+    let state_handle = component_state.clone();
+    let on_pinned_toggle = Callback::from(move |_: MouseEvent| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.showing_pinned = !updated_state.showing_pinned;
+        state_handle.set(updated_state);
+    });
+
+    let state_handle = component_state.clone();
+    let on_open_thread = Callback::from(move |root_id: u64| {
+        let state_handle = state_handle.clone();
+        let mut updated_state = state_handle.deref().clone();
+        let room_id = updated_state.selected_room_id.unwrap();
+        updated_state.thread_root = updated_state.selected_room_messages.iter()
+            .find(|message| message.id == Some(root_id))
+            .cloned();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Ok(page) = api_service::chat_get_thread(&token, room_id, root_id, None, THREAD_PAGE_SIZE).await {
+                updated_state.thread_cursor = page.next_cursor;
+                updated_state.thread_replies = page.messages;
+                mark_thread_read(&token, &updated_state).await;
+                state_handle.set(updated_state);
+            }
+        });
+    });
+
+    let state_handle = component_state.clone();
+    let on_load_more_replies = Callback::from(move |_: MouseEvent| {
+        let state_handle = state_handle.clone();
+        let mut updated_state = state_handle.deref().clone();
+        let (Some(room_id), Some(root_id)) = (
+            updated_state.selected_room_id,
+            updated_state.thread_root.as_ref().and_then(|root| root.id)
+        ) else {
+            return
+        };
+        let Some(after_id) = updated_state.thread_cursor else {
+            return
+        };
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Ok(page) = api_service::chat_get_thread(&token, room_id, root_id, Some(after_id), THREAD_PAGE_SIZE).await {
+                updated_state.thread_cursor = page.next_cursor;
+                updated_state.thread_replies.extend(page.messages);
+                mark_thread_read(&token, &updated_state).await;
+                state_handle.set(updated_state);
+            }
+        });
+    });
+
+    let state_handle = component_state.clone();
+    let on_close_thread = Callback::from(move |_: MouseEvent| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.thread_root = None;
+        updated_state.thread_replies.clear();
+        state_handle.set(updated_state);
+    });
+
+    // The reply is delivered back over the chat socket
+    let state_handle = component_state.clone();
+    let on_reply_submit = Callback::from(move |text: String| {
+        let (Some(room_id), Some(root)) = (state_handle.selected_room_id, &state_handle.thread_root) else {
+            return
+        };
+        let message = common::ChatMessage {
+            id: None,
+            room_id,
+            sender_id: None,
+            body: text,
+            time_sent: None,
+            edited_at: None,
+            deleted_at: None,
+            reactions: Vec::new(),
+            reply_to: root.id,
+            thread_root_id: None,
+            reply_count: 0,
+            attachments: Vec::new()
+        };
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = api_service::chat_send_message(&token, message).await {
+                log!(format!("{:?}", e));
+            }
+        });
+    });
+
     let state_handle = component_state.clone();
     let on_new_room_click = {
         Callback::from(move |_: MouseEvent| {
@@ -329,13 +938,205 @@ pub fn chat_page() -> Html {
         });
     });
 
+    let state_handle = component_state.clone();
+    let draft_handle = message_draft.clone();
+    let on_message_input = Callback::from(move |text: String| {
+        draft_handle.set(text.clone());
+        let Some(room_id) = state_handle.selected_room_id else {
+            return
+        };
+        if text.is_empty() {
+            return
+        }
+        let now = Utc::now();
+        let repeat_after = chrono::Duration::seconds((TYPING_TIMEOUT_SECS / 2) as i64);
+        let recently_sent = matches!(*last_typing_signal.borrow(),
+            Some((sent_room_id, sent_at)) if sent_room_id == room_id && now - sent_at < repeat_after);
+        if recently_sent {
+            return
+        }
+        *last_typing_signal.borrow_mut() = Some((room_id, now));
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = api_service::chat_send_typing(&token, room_id).await {
+                log!(format!("{:?}", e));
+            }
+        });
+    });
+
+    let on_message_read = Callback::from(move |(room_id, message_id): (u64, u64)| {
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = api_service::chat_mark_read(&token, room_id, message_id).await {
+                log!(format!("{:?}", e));
+            }
+        });
+    });
+
+    // Apply updates received over the chat socket or event stream
+    if !incoming.queue.is_empty() {
+        let mut updated_state = component_state.deref().clone();
+        let mut resync_needed = false;
+        for update in incoming.queue.iter() {
+            let event = match update {
+                LiveUpdate::Event(event) => event,
+                LiveUpdate::Resync => {
+                    resync_needed = true;
+                    continue
+                },
+            };
+            let in_selected_room = updated_state.selected_room_id == Some(event.room_id);
+            if let RoomEventKind::MessageSent(message) = &event.kind {
+                if let Some(sender_id) = message.sender_id {
+                    typing_members.dispatch(TypingAction::Stopped(event.room_id, sender_id));
+                }
+                // Messages arriving in the open room are read straight away
+                let at_latest = updated_state.selected_room_newer_cursor.is_none();
+                if let (true, true, Some(message_id)) = (in_selected_room, at_latest, message.id) {
+                    on_message_read.emit((event.room_id, message_id));
+                }
+            }
+            match &event.kind {
+                RoomEventKind::MessageSent(message) if message.thread_root_id.is_some() => {
+                    // Replies are listed in their thread, rather than with the room's messages
+                    let already_listed = updated_state.thread_replies.iter()
+                        .any(|listed| listed.id == message.id);
+                    if already_listed {
+                        continue
+                    }
+                    updated_state.selected_room_messages.iter_mut()
+                        .chain(updated_state.thread_root.iter_mut())
+                        .filter(|listed| listed.id == message.thread_root_id)
+                        .for_each(|listed| listed.reply_count += 1);
+                    let thread_open = updated_state.thread_root.as_ref()
+                        .is_some_and(|root| root.id == message.thread_root_id);
+                    // Otherwise the reply is fetched with the next page of replies
+                    if thread_open && updated_state.thread_cursor.is_none() {
+                        updated_state.thread_replies.push(message.clone());
+                    }
+                },
+                RoomEventKind::MessageSent(message) => {
+                    let already_listed = updated_state.selected_room_messages.iter()
+                        .any(|listed| listed.id == message.id);
+                    // Newer messages are yet to be loaded when viewing older messages
+                    let at_latest = updated_state.selected_room_newer_cursor.is_none();
+                    if in_selected_room && at_latest && !already_listed {
+                        updated_state.selected_room_messages.push(message.clone());
+                    }
+                    dispatch.room_preview_msg_set_reduce(event.room_id, message.body.clone());
+                },
+                RoomEventKind::MessageEdited(message) => {
+                    // Events do not carry reactions or attachments, so keep those already listed
+                    updated_state.selected_room_messages.iter_mut()
+                        .chain(updated_state.thread_root.iter_mut())
+                        .chain(updated_state.thread_replies.iter_mut())
+                        .filter(|listed| listed.id == message.id)
+                        .for_each(|listed| *listed = common::ChatMessage {
+                            reactions: listed.reactions.clone(),
+                            attachments: listed.attachments.clone(),
+                            ..message.clone()
+                        });
+                    updated_state.pinned_messages.iter_mut()
+                        .filter(|pin| pin.message.id == message.id)
+                        .for_each(|pin| pin.message = message.clone());
+                },
+                RoomEventKind::MessageDeleted(message) => {
+                    if message.thread_root_id.is_some() {
+                        updated_state.selected_room_messages.iter_mut()
+                            .chain(updated_state.thread_root.iter_mut())
+                            .filter(|listed| listed.id == message.thread_root_id)
+                            .for_each(|listed| listed.reply_count = listed.reply_count.saturating_sub(1));
+                    }
+                    updated_state.selected_room_messages.iter_mut()
+                        .chain(updated_state.thread_root.iter_mut())
+                        .chain(updated_state.thread_replies.iter_mut())
+                        .filter(|listed| listed.id == message.id)
+                        .for_each(|listed| *listed = message.clone());
+                    // Deleted messages are no longer pinned
+                    updated_state.pinned_messages.retain(|pin| pin.message.id != message.id);
+                },
+                RoomEventKind::ReactionAdded(change) | RoomEventKind::ReactionRemoved(change) => {
+                    let added = matches!(event.kind, RoomEventKind::ReactionAdded(_));
+                    apply_reaction_change(&mut updated_state.selected_room_messages, change, own_user_id, added);
+                    apply_reaction_change(updated_state.thread_root.as_mut_slice(), change, own_user_id, added);
+                    apply_reaction_change(&mut updated_state.thread_replies, change, own_user_id, added);
+                },
+                RoomEventKind::MessagePinned(pin) => {
+                    let already_listed = updated_state.pinned_messages.iter()
+                        .any(|listed| listed.message.id == pin.message.id);
+                    if in_selected_room && !already_listed {
+                        updated_state.pinned_messages.insert(0, pin.clone());
+                    }
+                },
+                RoomEventKind::MessageUnpinned(message_id) => {
+                    if in_selected_room {
+                        updated_state.pinned_messages.retain(|pin| pin.message.id != Some(*message_id));
+                    }
+                },
+                RoomEventKind::MemberAdded(member) => {
+                    let already_listed = updated_state.selected_room_members.iter()
+                        .any(|listed| listed.id == member.id);
+                    if in_selected_room && !already_listed {
+                        updated_state.selected_room_members.push(member.clone());
+                    }
+                },
+                RoomEventKind::MemberRemoved(member) if member.id == own_user_id => {
+                    // Removed from the room, so it is no longer accessible
+                    updated_state.chat_room_list.retain(|room| room.id != event.room_id);
+                    if in_selected_room {
+                        updated_state.selected_room_id = None;
+                        updated_state.selected_room_messages.clear();
+                        updated_state.selected_room_members.clear();
+                        updated_state.pinned_messages.clear();
+                        updated_state.thread_root = None;
+                        updated_state.thread_replies.clear();
+                    }
+                },
+                RoomEventKind::MemberRemoved(member) => {
+                    if in_selected_room {
+                        updated_state.selected_room_members.retain(|listed| listed.id != member.id);
+                    }
+                },
+                RoomEventKind::MemberRoleChanged(member) => {
+                    if in_selected_room {
+                        updated_state.selected_room_members.iter_mut()
+                            .filter(|listed| listed.id == member.id)
+                            .for_each(|listed| listed.role = member.role);
+                    }
+                },
+                RoomEventKind::Typing(member) => {
+                    let received_at = Utc::now();
+                    typing_members.dispatch(TypingAction::Started(event.room_id, member.clone(), received_at));
+                    let typing_members = typing_members.dispatcher();
+                    let (room_id, user_id) = (event.room_id, member.id);
+                    wasm_bindgen_futures::spawn_local(async move {
+                        TimeoutFuture::new((TYPING_TIMEOUT_SECS * 1000) as u32).await;
+                        typing_members.dispatch(TypingAction::Expired(room_id, user_id, received_at));
+                    });
+                },
+                RoomEventKind::RoomRenamed(name) => {
+                    updated_state.chat_room_list.iter_mut()
+                        .filter(|room| room.id == event.room_id)
+                        .for_each(|room| room.name = name.room_name.clone());
+                    if in_selected_room {
+                        updated_state.selected_room_name = name.room_name.clone();
+                    }
+                },
+            }
+        }
+        component_state.set(updated_state);
+        incoming.dispatch(IncomingAction::Drain(incoming.queue.len()));
+
+        if let (true, Some(room_id)) = (resync_needed, component_state.selected_room_id) {
+            on_chat_room_select.emit(room_id);
+        }
+    }
+
     // Set/update cached data
     component_state.selected_room_members.iter()
         .for_each(|member| {
             dispatch.id_to_name_set_reduce(member.id, member.username.clone());
         });
     
-    if let Some(room_id) = component_state.selected_room_id {
+    if let (Some(room_id), None) = (component_state.selected_room_id, component_state.selected_room_newer_cursor) {
         if let Some(msg) = component_state.selected_room_messages.last() {
             dispatch.room_preview_msg_set_reduce(room_id, msg.body.clone());
         }
@@ -348,20 +1149,102 @@ pub fn chat_page() -> Html {
         })
         .collect();
 
+    // Mirrors the server's permission matrix, which has the final say
+    let own_role = component_state.selected_room_members.iter()
+        .find(|member| member.id == own_user_id)
+        .map(|member| member.role)
+        .unwrap_or(RoomRole::Member);
+    let can_moderate = matches!(own_role, RoomRole::Owner | RoomRole::Admin);
+    // Direct rooms always have the same two members and no name to change
+    let selected_room = component_state.chat_room_list.iter()
+        .find(|room| Some(room.id) == component_state.selected_room_id);
+    let is_direct_room = selected_room.is_some_and(|room| room.kind == RoomKind::Direct);
+    // Neither member moderates a direct room, so both may pin
+    let can_pin = can_moderate || is_direct_room;
+    let is_pinned = |message: &common::ChatMessage| component_state.pinned_messages.iter()
+        .any(|pin| message.id.is_some() && pin.message.id == message.id);
+    let visibility_button = match selected_room.map(|room| room.visibility) {
+        Some(RoomVisibility::Public) => ("Make private", RoomVisibility::Private),
+        _ => ("Make public", RoomVisibility::Public)
+    };
+
+    let typing_names: Vec<String> = typing_members.typing.iter()
+        .filter(|(room_id, member, _)| Some(*room_id) == component_state.selected_room_id && member.id != own_user_id)
+        .map(|(_, member, _)| member.username.clone())
+        .collect();
+
     let chat_room_mesages_html: Vec<Html> = component_state.selected_room_messages.iter()
-        .map(|message: &common::ChatMessage| html! { <ChatMessage message={message.clone()} /> })
+        .map(|message: &common::ChatMessage| html! {
+            <ChatMessage message={message.clone()} on_open_thread={on_open_thread.clone()}
+                highlighted={message.id.is_some() && message.id == component_state.focused_message_id}
+                can_moderate={can_moderate} pinned={is_pinned(message)} can_pin={can_pin} />
+        })
         .collect();
 
-    let chat_room_members_html: Vec<Html> = component_state.selected_room_members.iter()
-        .map(|member| {
-            let user_id = member.id;
-            let remove_member_callback = on_remove_member.clone();
+    let thread_replies_html: Vec<Html> = component_state.thread_replies.iter()
+        .map(|message: &common::ChatMessage| html! {
+            <ChatMessage message={message.clone()} can_moderate={can_moderate}
+                pinned={is_pinned(message)} can_pin={can_pin} />
+        })
+        .collect();
+
+    let pinned_messages_html: Vec<Html> = component_state.pinned_messages.iter()
+        .map(|pin| {
+            let sender_name = match pin.message.sender_id {
+                Some(id) => store.cache.get_username_from_id(id),
+                None => "Unknown user".to_string()
+            };
+            // Replies are shown within their thread, so jump to the thread instead
+            let navigator = navigator.clone();
+            let room_id = pin.message.room_id;
+            let focus_id = pin.message.thread_root_id.or(pin.message.id);
+            let on_jump = Callback::from(move |_: MouseEvent| {
+                if let Some(message_id) = focus_id {
+                    navigator.push(&Route::ChatMessage { room_id, message_id });
+                }
+            });
+            html! {
+                <div class={classes!("message_container")}>
+                    <p>{"sender: "}{ sender_name }</p>
+                    <Markdown source={pin.message.body.clone()} />
+                    <p>{"pinned by "}{ store.cache.get_username_from_id(pin.pinned_by) }</p>
+                    <Button label={"Jump to message"} on_click={on_jump} />
+                </div>
+            }
+        })
+        .collect();
+
+    // Members whose names complete the mention being typed
+    let mention_suggestions_html: Html = match partial_mention(&message_draft) {
+        Some((at, partial)) => component_state.selected_room_members.iter()
+            .filter(|member| member.id != own_user_id)
+            .filter(|member| member.username.to_ascii_lowercase().starts_with(&partial.to_ascii_lowercase()))
+            .take(MAX_MENTION_SUGGESTIONS)
+            .map(|member| {
+                let completed = format!("{}@{} ", &message_draft[..at], member.username);
+                let draft_handle = message_draft.clone();
+                html! {
+                    <Button label={format!("@{}", member.username)} on_click={
+                        Callback::from(move |_: MouseEvent| {
+                            draft_handle.set(completed.clone())
+                        })
+                    } />
+                }
+            })
+            .collect(),
+        None => Html::default()
+    };
+
+    let pending_attachments_html: Html = component_state.pending_attachments.iter()
+        .map(|attachment| {
+            let attachment_id = attachment.id;
+            let remove_callback = on_remove_pending_attachment.clone();
             html! {
                 <div class={classes!("user_button_row")}>
-                    <UserDetailComponent data={member.clone()} />
+                    <p>{ attachment.file_name.clone() }</p>
                     <Button label={"Remove"} on_click={
                         Callback::from(move |_: MouseEvent| {
-                            remove_member_callback.emit(user_id)
+                            remove_callback.emit(attachment_id)
                         })
                     } />
                 </div>
@@ -369,6 +1252,49 @@ pub fn chat_page() -> Html {
         })
         .collect();
 
+    let chat_room_members_html: Vec<Html> = component_state.selected_room_members.iter()
+        .map(|member| {
+            let user_id = member.id;
+            let user_info = UserInfo { id: member.id, username: member.username.clone() };
+            let can_remove = !is_direct_room && match (own_role, member.role) {
+                (_, RoomRole::Owner) => false,
+                (RoomRole::Owner, _) => true,
+                (RoomRole::Admin, RoomRole::Member) => true,
+                _ => false
+            };
+            let remove_member_callback = on_remove_member.clone();
+            let change_role_callback = on_change_role.clone();
+            let (role_label, role_button) = match member.role {
+                RoomRole::Owner => ("Owner", None),
+                RoomRole::Admin => ("Admin", Some(("Demote", RoomRole::Member))),
+                RoomRole::Member => ("Member", Some(("Promote", RoomRole::Admin))),
+            };
+            html! {
+                <div class={classes!("user_button_row")}>
+                    <UserDetailComponent data={user_info} />
+                    <p>{ role_label }</p>
+                    if let Some(presence) = member.presence.clone() {
+                        <PresenceLabel presence={presence} />
+                    }
+                    if let (RoomRole::Owner, Some((label, new_role))) = (own_role, role_button) {
+                        <Button label={label} on_click={
+                            Callback::from(move |_: MouseEvent| {
+                                change_role_callback.emit((user_id, new_role))
+                            })
+                        } />
+                    }
+                    if can_remove {
+                        <Button label={"Remove"} on_click={
+                            Callback::from(move |_: MouseEvent| {
+                                remove_member_callback.emit(user_id)
+                            })
+                        } />
+                    }
+                </div>
+            }
+        })
+        .collect();
+
     html! {
         <>
             <h>{ "Chat rooms" }</h>
@@ -377,18 +1303,49 @@ pub fn chat_page() -> Html {
                     <Button label={"Create room"} on_click={Some(on_new_room_click)} />
                     <ListView children={chat_room_preview_html} />
                 </div>
-                <div class={classes!("chat_column", "middle")}>
+                <div class={classes!("chat_column", "middle", component_state.thread_root.is_some().then_some("narrow"))}>
                     if let MainPanelMode::Messages = component_state.main_panel_mode {
                         if component_state.selected_room_id.is_some() {
-                            <InputField name="" prefill={component_state.selected_room_name.clone()}
-                                on_change={on_room_name_change.clone()} />
-                            if component_state.selected_room_exhausted {
+                            if is_direct_room {
+                                <p>{ component_state.selected_room_name.clone() }</p>
+                            } else {
+                                <InputField name="" prefill={component_state.selected_room_name.clone()}
+                                    on_change={on_room_name_change.clone()} />
+                            }
+                            <Button label={ format!("Pinned messages ({})", component_state.pinned_messages.len()) }
+                                on_click={on_pinned_toggle} />
+                            if component_state.showing_pinned {
+                                <div class={classes!("pinned_drawer")}>
+                                    if pinned_messages_html.is_empty() {
+                                        <p>{ "No pinned messages" }</p>
+                                    } else {
+                                        <ListView children={pinned_messages_html} />
+                                    }
+                                </div>
+                            }
+                            if component_state.selected_room_cursor.is_none() {
                                 <p>{ "No more messages" }</p>
                             } else {
                                 <Button label={ "Load more" } on_click={on_load_more_messages} />
                             }
                             <ListView children={chat_room_mesages_html} />
-                            <InputField name={""} on_change={input_on_submit} /> 
+                            if component_state.selected_room_newer_cursor.is_some() {
+                                <Button label={ "Load newer" } on_click={on_load_newer_messages} />
+                            }
+                            if !typing_names.is_empty() {
+                                <p>{ typing_names.join(", ") }{ if typing_names.len() == 1 { " is typing..." } else { " are typing..." } }</p>
+                            }
+                            if !component_state.pending_attachments.is_empty() {
+                                <div class={classes!("pending_attachments")}>
+                                    { pending_attachments_html }
+                                </div>
+                            }
+                            <div class={classes!("mention_suggestions")}>
+                                { mention_suggestions_html }
+                            </div>
+                            <InputField name={""} prefill={(*message_draft).clone()}
+                                on_change={input_on_submit} on_input={on_message_input} />
+                            <FilePicker label={"Attach files"} on_pick={on_attach_files} />
                         } else {
                             <p>{ "No chat selected" }</p>
                         }
@@ -402,17 +1359,46 @@ pub fn chat_page() -> Html {
                     } else {
                         if let MemberPanelMode::ViewMembers = component_state.member_panel_mode {
                             <p>{ "Room members" }</p>
-                            <Button label={ "Add Members" } on_click={to_add_members} />
+                            if !is_direct_room {
+                                <Button label={ "Add Members" } on_click={to_add_members} />
+                            }
+                            if !is_direct_room && own_role == RoomRole::Owner {
+                                <Button label={ "Invite Links" } on_click={to_invite_codes} />
+                                <Button label={ visibility_button.0 } on_click={
+                                    Callback::from(move |_: MouseEvent| {
+                                        on_set_visibility.emit(visibility_button.1)
+                                    })
+                                } />
+                            }
+                            if !is_direct_room {
+                                <Button label={ "Leave" } on_click={on_leave_room} />
+                            }
                             <ListView children={chat_room_members_html} />
-                        } else {
+                        } else if let MemberPanelMode::AddMembers = component_state.member_panel_mode {
                             <p>{ "Add members" }</p>
-                            <Button label={ "View Members" } on_click={to_view_members} />
+                            <Button label={ "View Members" } on_click={to_view_members.clone()} />
                             <UserSearch buttons={vec![
-                                ("Add".to_string(), on_add_member.clone())
+                                ("Invite".to_string(), on_add_member.clone())
                             ]} />
+                        } else {
+                            <p>{ "Invite links" }</p>
+                            <Button label={ "View Members" } on_click={to_view_members} />
+                            <InviteCodes room_id={component_state.selected_room_id.unwrap_or_default()} />
                         }
                     }
                 </div>
+                if let Some(thread_root) = component_state.thread_root.clone() {
+                    <div class={classes!("chat_column", "thread")}>
+                        <p>{ "Thread" }</p>
+                        <Button label={ "Close" } on_click={on_close_thread} />
+                        <ChatMessage pinned={is_pinned(&thread_root)} message={thread_root} />
+                        <ListView children={thread_replies_html} />
+                        if component_state.thread_cursor.is_some() {
+                            <Button label={ "Load more replies" } on_click={on_load_more_replies} />
+                        }
+                        <InputField name={""} on_change={on_reply_submit} />
+                    </div>
+                }
             </div>
         </>
     }
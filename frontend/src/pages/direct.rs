@@ -0,0 +1,88 @@
+use yew::prelude::*;
+use yew_router::prelude::{use_navigator, Redirect};
+use yewdux::use_store;
+
+use crate::{
+    api_service,
+    components::input_field::InputField,
+    router::Route,
+    store::Store
+};
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub user_id: u64
+}
+
+#[derive(PartialEq, Clone)]
+enum DirectStatus {
+    /// Looking for an existing direct room with the user
+    Finding,
+    /// No messages have been sent between the users yet
+    Composing,
+    Failed
+}
+
+#[function_component(DirectPage)]
+pub fn direct_page(props: &Props) -> Html {
+    let (store, _) = use_store::<Store>();
+
+    if store.user.is_none() {
+        return html! {
+            <Redirect<Route> to={Route::Home}/>
+        }
+    }
+    let token = store.user.clone().unwrap().token;
+
+    let navigator = use_navigator().unwrap();
+    let status = use_state_eq(|| DirectStatus::Finding);
+
+    // Open the existing direct room, if any
+    {
+        let status = status.clone();
+        let navigator = navigator.clone();
+        use_effect_with(props.user_id, move |user_id| {
+            let user_id = *user_id;
+            wasm_bindgen_futures::spawn_local(async move {
+                match api_service::chat_get_direct_room(&token, user_id).await {
+                    Ok(room) => navigator.replace(&Route::ChatRoom { room_id: room.id }),
+                    Err(_) => status.set(DirectStatus::Composing),
+                }
+            });
+        });
+    }
+
+    // The first message creates the direct room
+    let user_id = props.user_id;
+    let status_handle = status.clone();
+    let on_submit = Callback::from(move |body: String| {
+        if body.trim().is_empty() {
+            return
+        }
+        let navigator = navigator.clone();
+        let status_handle = status_handle.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match api_service::chat_send_direct_message(&token, user_id, body).await {
+                Ok(room) => navigator.push(&Route::ChatRoom { room_id: room.id }),
+                Err(_) => status_handle.set(DirectStatus::Failed),
+            }
+        });
+    });
+
+    let username = store.cache.get_username_from_id(props.user_id);
+
+    html! {
+        <div>
+            <h>{ format!("Message {}", username) }</h>
+            {
+                match *status {
+                    DirectStatus::Finding => html! { <p>{ "Loading..." }</p> },
+                    DirectStatus::Composing => html! { <InputField name={""} autofocus={true} on_change={on_submit} /> },
+                    DirectStatus::Failed => html! {
+                        <p>{ "The message could not be sent. Direct messages can only be sent to friends that have not blocked each other" }</p>
+                    },
+                }
+            }
+        </div>
+    }
+}
\ No newline at end of file
@@ -0,0 +1,161 @@
+use std::ops::Deref;
+
+use common::ChatRoomListing;
+use yew::prelude::*;
+use yew_router::prelude::{use_navigator, Redirect};
+use yewdux::use_store;
+
+use crate::{
+    api_service,
+    components::{
+        button::Button,
+        input_field::InputField
+    },
+    router::Route,
+    store::Store
+};
+
+#[derive(PartialEq, Clone, Default)]
+struct State {
+    search_text: String,
+    rooms: Vec<ChatRoomListing>,
+    next_cursor: Option<u64>
+}
+
+#[function_component(DirectoryPage)]
+pub fn directory_page() -> Html {
+    let (store, _) = use_store::<Store>();
+
+    if store.user.is_none() {
+        return html! {
+            <Redirect<Route> to={Route::Home}/>
+        }
+    }
+    let token = store.user.clone().unwrap().token;
+
+    let navigator = use_navigator().unwrap();
+    let component_state = use_state_eq(|| State::default());
+
+    {
+        let state_handle = component_state.clone();
+        use_effect_with(token, move |token| {
+            let token = *token;
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(page) = api_service::chat_get_directory(&token, "", None).await {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.rooms = page.rooms;
+                    updated_state.next_cursor = page.next_cursor;
+                    state_handle.set(updated_state);
+                }
+            });
+        });
+    }
+
+    let state_handle = component_state.clone();
+    let on_search_text_change = Callback::from(move |text: String| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.search_text = text;
+        state_handle.set(updated_state);
+    });
+
+    // A new search starts again from the first page
+    let state_handle = component_state.clone();
+    let on_search = Callback::from(move |_: MouseEvent| {
+        let state_handle = state_handle.clone();
+        let mut updated_state = state_handle.deref().clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let search_text = updated_state.search_text.trim().to_string();
+            if let Ok(page) = api_service::chat_get_directory(&token, &search_text, None).await {
+                updated_state.rooms = page.rooms;
+                updated_state.next_cursor = page.next_cursor;
+                state_handle.set(updated_state);
+            }
+        });
+    });
+
+    let state_handle = component_state.clone();
+    let on_load_more = Callback::from(move |_: MouseEvent| {
+        let state_handle = state_handle.clone();
+        let mut updated_state = state_handle.deref().clone();
+        if updated_state.next_cursor.is_none() {
+            return
+        }
+        wasm_bindgen_futures::spawn_local(async move {
+            let search_text = updated_state.search_text.trim().to_string();
+            if let Ok(page) = api_service::chat_get_directory(&token, &search_text, updated_state.next_cursor).await {
+                updated_state.rooms.extend(page.rooms);
+                updated_state.next_cursor = page.next_cursor;
+                state_handle.set(updated_state);
+            }
+        });
+    });
+
+    let on_open = {
+        let navigator = navigator.clone();
+        Callback::from(move |room_id: u64| {
+            navigator.push(&Route::ChatRoom { room_id });
+        })
+    };
+
+    let on_join = Callback::from(move |room_id: u64| {
+        let navigator = navigator.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Ok(room) = api_service::chat_join_room(&token, room_id).await {
+                navigator.push(&Route::ChatRoom { room_id: room.id });
+            }
+        });
+    });
+
+    let rooms_html: Html = component_state.rooms.iter()
+        .map(|listing| {
+            let room_id = listing.room.id;
+            let button = if listing.is_member {
+                let open_callback = on_open.clone();
+                html! {
+                    <Button label={"Open"} on_click={
+                        Callback::from(move |_: MouseEvent| {
+                            open_callback.emit(room_id)
+                        })
+                    } />
+                }
+            } else {
+                let join_callback = on_join.clone();
+                html! {
+                    <Button label={"Join"} on_click={
+                        Callback::from(move |_: MouseEvent| {
+                            join_callback.emit(room_id)
+                        })
+                    } />
+                }
+            };
+            html! {
+                <div class={classes!("user_button_row")}>
+                    <p>{ listing.room.name.clone() }</p>
+                    <p>{ format!("{} members", listing.member_count) }</p>
+                    { button }
+                </div>
+            }
+        })
+        .collect();
+
+    html! {
+        <div>
+            <h>{ "Browse rooms" }</h>
+            <div>
+                <InputField name={"room name"} prefill={component_state.search_text.clone()}
+                    on_change={on_search_text_change} />
+                <Button label={"Search"} on_click={on_search} />
+            </div>
+            if component_state.rooms.is_empty() {
+                <p>{ "No public rooms" }</p>
+            } else {
+                <ul>
+                    { rooms_html }
+                </ul>
+            }
+            if component_state.next_cursor.is_some() {
+                <Button label={"Load more"} on_click={on_load_more} />
+            }
+        </div>
+    }
+}
\ No newline at end of file
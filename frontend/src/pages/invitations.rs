@@ -0,0 +1,99 @@
+use std::ops::Deref;
+
+use common::ChatRoomInvitation;
+use yew::prelude::*;
+use yew_router::prelude::Redirect;
+use yewdux::use_store;
+
+use crate::{
+    api_service,
+    components::{
+        button::Button,
+        user::UserDetailComponent
+    },
+    router::Route,
+    store::Store
+};
+
+#[derive(PartialEq, Clone, Default)]
+struct State {
+    invitations: Vec<ChatRoomInvitation>
+}
+
+#[function_component(InvitationsPage)]
+pub fn invitations_page() -> Html {
+    let (store, _) = use_store::<Store>();
+
+    if store.user.is_none() {
+        return html! {
+            <Redirect<Route> to={Route::Home}/>
+        }
+    }
+    let token = store.user.clone().unwrap().token;
+
+    let component_state = use_state_eq(|| State::default());
+
+    {
+        let state_handle = component_state.clone();
+        use_effect_with(token, move |token| {
+            let token = *token;
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(invitations) = api_service::chat_get_invitations(&token).await {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.invitations = invitations;
+                    state_handle.set(updated_state);
+                }
+            });
+        });
+    }
+
+    // Accepting and declining both resolve the invitation
+    let state_handle = component_state.clone();
+    let on_respond = Callback::from(move |(room_id, accept): (u64, bool)| {
+        let state_handle = state_handle.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Ok(()) = api_service::chat_respond_to_invitation(&token, room_id, accept).await {
+                let mut updated_state = state_handle.deref().clone();
+                updated_state.invitations.retain(|invitation| invitation.room.id != room_id);
+                state_handle.set(updated_state);
+            }
+        });
+    });
+
+    let invitations_html: Html = component_state.invitations.iter()
+        .map(|invitation| {
+            let room_id = invitation.room.id;
+            let accept_callback = on_respond.clone();
+            let decline_callback = on_respond.clone();
+            html! {
+                <div class={classes!("user_button_row")}>
+                    <p>{ invitation.room.name.clone() }</p>
+                    <UserDetailComponent data={invitation.inviter.clone()} />
+                    <Button label={"Accept"} on_click={
+                        Callback::from(move |_: MouseEvent| {
+                            accept_callback.emit((room_id, true))
+                        })
+                    } />
+                    <Button label={"Decline"} on_click={
+                        Callback::from(move |_: MouseEvent| {
+                            decline_callback.emit((room_id, false))
+                        })
+                    } />
+                </div>
+            }
+        })
+        .collect();
+
+    html! {
+        <div>
+            <h>{ "Room Invitations" }</h>
+            if component_state.invitations.is_empty() {
+                <p>{ "None" }</p>
+            } else {
+                <ul>
+                    { invitations_html }
+                </ul>
+            }
+        </div>
+    }
+}
\ No newline at end of file
@@ -0,0 +1,51 @@
+use yew::prelude::*;
+use yew_router::prelude::{use_navigator, Redirect};
+use yewdux::use_store;
+
+use crate::{
+    api_service,
+    router::Route,
+    store::Store
+};
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub code: String
+}
+
+#[function_component(JoinPage)]
+pub fn join_page(props: &Props) -> Html {
+    let (store, _) = use_store::<Store>();
+
+    if store.user.is_none() {
+        return html! {
+            <Redirect<Route> to={Route::AccountLogin}/>
+        }
+    }
+    let token = store.user.clone().unwrap().token;
+
+    let navigator = use_navigator().unwrap();
+    let failed = use_state_eq(|| false);
+
+    // Join once, then continue to the chat page
+    {
+        let failed = failed.clone();
+        use_effect_with(props.code.clone(), move |code| {
+            let code = code.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api_service::chat_join_by_code(&token, &code).await {
+                    Ok(_) => navigator.push(&Route::Chats),
+                    Err(_) => failed.set(true),
+                }
+            });
+        });
+    }
+
+    html! {
+        if *failed {
+            <p>{ "This invite link is invalid, has expired or has no uses left" }</p>
+        } else {
+            <p>{ "Joining room..." }</p>
+        }
+    }
+}
\ No newline at end of file
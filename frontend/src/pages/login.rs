@@ -1,14 +1,14 @@
 use std::str::FromStr;
 
-use common::AccountRequest;
+use common::{AccountRequest, AuthToken, LoginChallenge, LoginResponse, LoginTotpRequest};
 use gloo::console::log;
-use uuid::Uuid;
 use yew::prelude::*;
 use yew_router::prelude::*;
 use yewdux::prelude::*;
 
-use crate::api_service;
+use crate::api_service::{self, ApiError, LoginStep};
 use crate::widgets::login_form::LoginForm;
+use crate::widgets::totp_code_form::TotpCodeForm;
 use crate::router::Route;
 use crate::store::{Store, StoreDispatchExt};
 
@@ -16,6 +16,25 @@ use crate::store::{Store, StoreDispatchExt};
 enum LoginStatus {
     NotAttempted,
     Failed,
+    /// The two-factor code was wrong
+    BadCode,
+    /// The two-factor challenge expired, so the password is needed again
+    ChallengeExpired,
+}
+
+/// Store the session of a completed login. Returns false if the returned
+/// token could not be read.
+fn complete_login(dispatch: &Dispatch<Store>, username: String, response: LoginResponse) -> bool {
+    match AuthToken::from_str(&response.token) {
+        Ok(token) => {
+            dispatch.login_reduce(username, response.user_id, token);
+            true
+        },
+        Err(_) => {
+            log!("The stored token is in an invalid format");
+            false
+        },
+    }
 }
 
 #[function_component(LoginPage)]
@@ -34,26 +53,32 @@ pub fn login_page() -> Html {
     // Component state
     let status = use_state(|| LoginStatus::NotAttempted);
     let render_status = status.clone();
+    // Set once the password is accepted for an account with two-factor
+    // authentication, along with the username
+    let challenge = use_state(|| None::<(String, LoginChallenge)>);
 
     let on_submit = {
+        let navigator = navigator.clone();
+        let dispatch = dispatch.clone();
+        let status = status.clone();
+        let challenge = challenge.clone();
         Callback::from(move |user: AccountRequest| {
             let navigator = navigator.clone();
             let dispatch = dispatch.clone();
             let status = status.clone();
+            let challenge = challenge.clone();
             wasm_bindgen_futures::spawn_local(async move {
                 match api_service::account_login(&user).await {
-                    Ok(response) => {
-                        match Uuid::from_str(&response.token) {
-                            Ok(token) => {
-                                dispatch.login_reduce(user.username, response.user_id, token);
-                                navigator.push(&Route::Home);
-                            },
-                            Err(_) => {
-                                log!("The stored token is in an invalid format");
-                                status.set(LoginStatus::Failed);
-                            },
+                    Ok(LoginStep::LoggedIn(response)) => {
+                        match complete_login(&dispatch, user.username, response) {
+                            true => navigator.push(&Route::Home),
+                            false => status.set(LoginStatus::Failed),
                         }
                     },
+                    Ok(LoginStep::Challenged(login_challenge)) => {
+                        status.set(LoginStatus::NotAttempted);
+                        challenge.set(Some((user.username, login_challenge)));
+                    },
                     Err(_) => {
                         status.set(LoginStatus::Failed);
                     }
@@ -63,13 +88,53 @@ pub fn login_page() -> Html {
         })
     };
 
+    let on_code = {
+        let challenge = challenge.clone();
+        Callback::from(move |code: String| {
+            let Some((username, login_challenge)) = (*challenge).clone() else {
+                return
+            };
+            let navigator = navigator.clone();
+            let dispatch = dispatch.clone();
+            let status = status.clone();
+            let challenge = challenge.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = LoginTotpRequest { challenge: login_challenge.challenge, code };
+                match api_service::account_login_totp(&request).await {
+                    Ok(response) => {
+                        match complete_login(&dispatch, username, response) {
+                            true => navigator.push(&Route::Home),
+                            false => status.set(LoginStatus::Failed),
+                        }
+                    },
+                    Err(ApiError::Forbidden) => {
+                        challenge.set(None);
+                        status.set(LoginStatus::ChallengeExpired);
+                    },
+                    Err(_) => status.set(LoginStatus::BadCode),
+                }
+            });
+        })
+    };
+
     html! {
         <>
             <h1>{ "login page" }</h1>
-            <LoginForm on_submit={on_submit}/>
+            if challenge.is_some() {
+                <TotpCodeForm title={"Two-factor authentication"} submit_label={"Verify"} on_submit={on_code} />
+                <p>{ "Enter the code from your authenticator app, or one of your recovery codes" }</p>
+            } else {
+                <LoginForm on_submit={on_submit}/>
+            }
             if (&*render_status).eq(&LoginStatus::Failed) {
                 <p>{ "Incorrect details" }</p>
             }
+            if (&*render_status).eq(&LoginStatus::BadCode) {
+                <p>{ "Incorrect code" }</p>
+            }
+            if (&*render_status).eq(&LoginStatus::ChallengeExpired) {
+                <p>{ "Login expired, please log in again" }</p>
+            }
         </>
     }
 }
\ No newline at end of file
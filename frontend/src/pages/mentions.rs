@@ -0,0 +1,107 @@
+use std::ops::Deref;
+
+use common::ChatRoom;
+use yew::prelude::*;
+use yew_router::prelude::{use_navigator, Redirect};
+use yewdux::use_store;
+
+use crate::{
+    api_service,
+    components::{
+        button::Button,
+        markdown::Markdown
+    },
+    router::Route,
+    store::Store,
+    widgets::list_view::ListView
+};
+
+#[derive(PartialEq, Clone, Default)]
+struct State {
+    rooms: Vec<ChatRoom>,
+    mentions: Option<Vec<common::ChatMessage>>
+}
+
+/// Messages mentioning the user that they have yet to read
+#[function_component(MentionsPage)]
+pub fn mentions_page() -> Html {
+    let (store, _) = use_store::<Store>();
+
+    if store.user.is_none() {
+        return html! {
+            <Redirect<Route> to={Route::Home}/>
+        }
+    }
+    let token = store.user.clone().unwrap().token;
+
+    let navigator = use_navigator().unwrap();
+    let component_state = use_state_eq(|| State::default());
+
+    {
+        let state_handle = component_state.clone();
+        use_effect_with(token, move |token| {
+            let token = *token;
+            wasm_bindgen_futures::spawn_local(async move {
+                let mut updated_state = state_handle.deref().clone();
+                if let Ok(rooms) = api_service::chat_get_rooms(&token).await {
+                    updated_state.rooms = rooms;
+                }
+                if let Ok(mentions) = api_service::chat_get_unread_mentions(&token).await {
+                    updated_state.mentions = Some(mentions);
+                }
+                state_handle.set(updated_state);
+            });
+        });
+    }
+
+    let mentions_html: Vec<Html> = component_state.mentions.iter()
+        .flatten()
+        .map(|message| {
+            let room_name = component_state.rooms.iter()
+                .find(|room| room.id == message.room_id)
+                .map(|room| room.name.clone())
+                .unwrap_or_default();
+            let sender_name = match message.sender_id {
+                Some(id) => store.cache.get_username_from_id(id),
+                None => "Unknown user".to_string()
+            };
+            let time_sent = match message.time_sent {
+                Some(time) => time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                None => "Unknown".to_string(),
+            };
+
+            // Replies are shown within their thread, so jump to the thread instead
+            let navigator = navigator.clone();
+            let room_id = message.room_id;
+            let focus_id = message.thread_root_id.or(message.id);
+            let on_jump = Callback::from(move |_: MouseEvent| {
+                if let Some(message_id) = focus_id {
+                    navigator.push(&Route::ChatMessage { room_id, message_id });
+                }
+            });
+
+            html! {
+                <div class={classes!("message_container", "mentioned")}>
+                    <p>{"room: "}{ room_name }</p>
+                    <p>{"sender: "}{ sender_name }</p>
+                    <Markdown source={message.body.clone()} />
+                    <p>{"time sent: "}{ time_sent }</p>
+                    <Button label={"Jump to message"} on_click={on_jump} />
+                </div>
+            }
+        })
+        .collect();
+
+    html! {
+        <>
+            <h>{ "Unread mentions" }</h>
+            if let Some(mentions) = &component_state.mentions {
+                if mentions.is_empty() {
+                    <p>{ "No unread mentions" }</p>
+                } else {
+                    <ListView children={mentions_html} />
+                }
+            }
+        </>
+    }
+}
\ No newline at end of file
@@ -4,4 +4,11 @@ pub mod login;
 pub mod registration;
 pub mod change_password;
 pub mod chat;
-pub mod associations;
\ No newline at end of file
+pub mod associations;
+pub mod search;
+pub mod invitations;
+pub mod join;
+pub mod direct;
+pub mod directory;
+pub mod mentions;
+pub mod totp;
\ No newline at end of file
@@ -0,0 +1,242 @@
+use std::ops::Deref;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use common::{ChatRoom, ChatRoomMember};
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+use yew_router::prelude::{use_navigator, Redirect};
+use yewdux::use_store;
+
+use crate::{
+    api_service,
+    components::{
+        button::Button,
+        input_field::InputField,
+        markdown::Markdown
+    },
+    router::Route,
+    store::Store,
+    widgets::list_view::ListView
+};
+
+#[derive(PartialEq, Clone, Default)]
+struct State {
+    rooms: Vec<ChatRoom>,
+    room_members: Vec<ChatRoomMember>,
+    search_text: String,
+    room_id: Option<u64>,
+    sender_id: Option<u64>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    results: Option<Vec<common::ChatMessage>>
+}
+
+/// The start of `date` in UTC time
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
+#[function_component(SearchPage)]
+pub fn search_page() -> Html {
+    let (store, _) = use_store::<Store>();
+
+    if store.user.is_none() {
+        return html! {
+            <Redirect<Route> to={Route::Home}/>
+        }
+    }
+    let token = store.user.clone().unwrap().token;
+
+    let navigator = use_navigator().unwrap();
+    let component_state = use_state_eq(|| State::default());
+
+    // Rooms to filter by
+    {
+        let state_handle = component_state.clone();
+        use_effect_with(token, move |token| {
+            let token = *token;
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(rooms) = api_service::chat_get_rooms(&token).await {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.rooms = rooms;
+                    state_handle.set(updated_state);
+                }
+            });
+        });
+    }
+
+    let state_handle = component_state.clone();
+    let on_search_text_change = Callback::from(move |text: String| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.search_text = text;
+        state_handle.set(updated_state);
+    });
+
+    // Senders can be filtered by once a room is selected
+    let state_handle = component_state.clone();
+    let on_room_change = Callback::from(move |event: Event| {
+        let value = match event.target() {
+            Some(target) => target.unchecked_into::<HtmlSelectElement>().value(),
+            None => "".to_string()
+        };
+        let state_handle = state_handle.clone();
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.room_id = value.parse::<u64>().ok();
+        updated_state.sender_id = None;
+        updated_state.room_members.clear();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(room_id) = updated_state.room_id {
+                if let Ok(members) = api_service::chat_get_members(&token, room_id).await {
+                    updated_state.room_members = members;
+                }
+            }
+            state_handle.set(updated_state);
+        });
+    });
+
+    let state_handle = component_state.clone();
+    let on_sender_change = Callback::from(move |event: Event| {
+        let value = match event.target() {
+            Some(target) => target.unchecked_into::<HtmlSelectElement>().value(),
+            None => "".to_string()
+        };
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.sender_id = value.parse::<u64>().ok();
+        state_handle.set(updated_state);
+    });
+
+    let state_handle = component_state.clone();
+    let on_from_change = Callback::from(move |event: Event| {
+        let value = match event.target() {
+            Some(target) => target.unchecked_into::<HtmlInputElement>().value(),
+            None => "".to_string()
+        };
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.from = NaiveDate::parse_from_str(&value, "%Y-%m-%d").ok();
+        state_handle.set(updated_state);
+    });
+
+    let state_handle = component_state.clone();
+    let on_to_change = Callback::from(move |event: Event| {
+        let value = match event.target() {
+            Some(target) => target.unchecked_into::<HtmlInputElement>().value(),
+            None => "".to_string()
+        };
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.to = NaiveDate::parse_from_str(&value, "%Y-%m-%d").ok();
+        state_handle.set(updated_state);
+    });
+
+    let state_handle = component_state.clone();
+    let on_search = Callback::from(move |_: MouseEvent| {
+        let state_handle = state_handle.clone();
+        let mut updated_state = state_handle.deref().clone();
+        if updated_state.search_text.trim().is_empty() {
+            return
+        }
+        // The "to" date is inclusive, so search up to the start of the next day
+        let from = updated_state.from.map(start_of_day);
+        let to = updated_state.to
+            .and_then(|date| date.checked_add_days(Days::new(1)))
+            .map(start_of_day);
+        wasm_bindgen_futures::spawn_local(async move {
+            let results = api_service::chat_search_messages(
+                &token,
+                &updated_state.search_text,
+                updated_state.room_id,
+                updated_state.sender_id,
+                from,
+                to
+            ).await;
+            if let Ok(results) = results {
+                updated_state.results = Some(results);
+                state_handle.set(updated_state);
+            }
+        });
+    });
+
+    let room_options_html: Vec<Html> = component_state.rooms.iter()
+        .map(|room| html! {
+            <option value={room.id.to_string()} selected={component_state.room_id == Some(room.id)}>
+                { room.name.clone() }
+            </option>
+        })
+        .collect();
+
+    let sender_options_html: Vec<Html> = component_state.room_members.iter()
+        .map(|member| html! {
+            <option value={member.id.to_string()} selected={component_state.sender_id == Some(member.id)}>
+                { member.username.clone() }
+            </option>
+        })
+        .collect();
+
+    let results_html: Vec<Html> = component_state.results.iter()
+        .flatten()
+        .map(|message| {
+            let room_name = component_state.rooms.iter()
+                .find(|room| room.id == message.room_id)
+                .map(|room| room.name.clone())
+                .unwrap_or_default();
+            let sender_name = match message.sender_id {
+                Some(id) => store.cache.get_username_from_id(id),
+                None => "Unknown user".to_string()
+            };
+            let time_sent = match message.time_sent {
+                Some(time) => time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                None => "Unknown".to_string(),
+            };
+
+            // Replies are shown within their thread, so jump to the thread instead
+            let navigator = navigator.clone();
+            let room_id = message.room_id;
+            let focus_id = message.thread_root_id.or(message.id);
+            let on_jump = Callback::from(move |_: MouseEvent| {
+                if let Some(message_id) = focus_id {
+                    navigator.push(&Route::ChatMessage { room_id, message_id });
+                }
+            });
+
+            html! {
+                <div class={classes!("message_container")}>
+                    <p>{"room: "}{ room_name }</p>
+                    <p>{"sender: "}{ sender_name }</p>
+                    <Markdown source={message.body.clone()} />
+                    <p>{"time sent: "}{ time_sent }</p>
+                    <Button label={"Jump to message"} on_click={on_jump} />
+                </div>
+            }
+        })
+        .collect();
+
+    html! {
+        <>
+            <h>{ "Search messages" }</h>
+            <div>
+                <InputField name={"search"} prefill={component_state.search_text.clone()}
+                    on_change={on_search_text_change} />
+                <select onchange={on_room_change}>
+                    <option value="" selected={component_state.room_id.is_none()}>{ "Any room" }</option>
+                    { for room_options_html }
+                </select>
+                if component_state.room_id.is_some() {
+                    <select onchange={on_sender_change}>
+                        <option value="" selected={component_state.sender_id.is_none()}>{ "Anyone" }</option>
+                        { for sender_options_html }
+                    </select>
+                }
+                <label>{ "From " }<input type="date" onchange={on_from_change} /></label>
+                <label>{ "To " }<input type="date" onchange={on_to_change} /></label>
+                <Button label={"Search"} on_click={on_search} />
+            </div>
+            if let Some(results) = &component_state.results {
+                if results.is_empty() {
+                    <p>{ "No matching messages" }</p>
+                } else {
+                    <ListView children={results_html} />
+                }
+            }
+        </>
+    }
+}
\ No newline at end of file
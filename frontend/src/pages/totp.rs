@@ -0,0 +1,167 @@
+use std::ops::Deref;
+
+use common::{TotpCode, TotpEnrolment, TotpStatus};
+use yew::prelude::*;
+use yew_router::prelude::Redirect;
+use yewdux::use_store;
+
+use crate::{
+    api_service,
+    components::{
+        button::Button,
+        qr_code::QrCodeView
+    },
+    router::Route,
+    store::Store,
+    widgets::totp_code_form::TotpCodeForm
+};
+
+#[derive(PartialEq, Clone, Default)]
+struct State {
+    status: Option<TotpStatus>,
+    /// A secret being enrolled, until it is confirmed with a code
+    enrolment: Option<TotpEnrolment>,
+    /// Only shown once, right after enrolment is confirmed
+    recovery_codes: Option<Vec<String>>,
+    failed: bool
+}
+
+/// Set up or turn off two-factor authentication for the user's account
+#[function_component(TotpPage)]
+pub fn totp_page() -> Html {
+    let (store, _) = use_store::<Store>();
+
+    if store.user.is_none() {
+        return html! {
+            <Redirect<Route> to={Route::Home}/>
+        }
+    }
+    let token = store.user.clone().unwrap().token;
+
+    let component_state = use_state_eq(|| State::default());
+
+    {
+        let state_handle = component_state.clone();
+        use_effect_with(token, move |token| {
+            let token = *token;
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(status) = api_service::account_get_totp_status(&token).await {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.status = Some(status);
+                    state_handle.set(updated_state);
+                }
+            });
+        });
+    }
+
+    let on_start = {
+        let state_handle = component_state.clone();
+        Callback::from(move |_: MouseEvent| {
+            let state_handle = state_handle.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let mut updated_state = state_handle.deref().clone();
+                match api_service::account_start_totp(&token).await {
+                    Ok(enrolment) => {
+                        updated_state.enrolment = Some(enrolment);
+                        updated_state.failed = false;
+                    },
+                    Err(_) => updated_state.failed = true,
+                }
+                state_handle.set(updated_state);
+            });
+        })
+    };
+
+    let on_confirm = {
+        let state_handle = component_state.clone();
+        Callback::from(move |code: String| {
+            let state_handle = state_handle.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let mut updated_state = state_handle.deref().clone();
+                match api_service::account_confirm_totp(&token, &TotpCode { code }).await {
+                    Ok(recovery_codes) => {
+                        updated_state.status = Some(TotpStatus {
+                            enabled: true,
+                            recovery_codes_remaining: recovery_codes.codes.len() as u64
+                        });
+                        updated_state.enrolment = None;
+                        updated_state.recovery_codes = Some(recovery_codes.codes);
+                        updated_state.failed = false;
+                    },
+                    Err(_) => updated_state.failed = true,
+                }
+                state_handle.set(updated_state);
+            });
+        })
+    };
+
+    let on_disable = {
+        let state_handle = component_state.clone();
+        Callback::from(move |code: String| {
+            let state_handle = state_handle.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let mut updated_state = state_handle.deref().clone();
+                match api_service::account_disable_totp(&token, &TotpCode { code }).await {
+                    Ok(()) => {
+                        updated_state.status = Some(TotpStatus::default());
+                        updated_state.failed = false;
+                    },
+                    Err(_) => updated_state.failed = true,
+                }
+                state_handle.set(updated_state);
+            });
+        })
+    };
+
+    let on_codes_saved = {
+        let state_handle = component_state.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut updated_state = state_handle.deref().clone();
+            updated_state.recovery_codes = None;
+            state_handle.set(updated_state);
+        })
+    };
+
+    let content = match (&component_state.status, &component_state.enrolment, &component_state.recovery_codes) {
+        (_, _, Some(codes)) => html! {
+            <>
+                <p>{ "Two-factor authentication is on. Save these recovery codes somewhere safe. Each can be used once in place of a code, and they will not be shown again." }</p>
+                <ul class={classes!("recovery_codes")}>
+                    { for codes.iter().map(|code| html! { <li>{ code.clone() }</li> }) }
+                </ul>
+                <Button label={"I have saved these codes"} on_click={Some(on_codes_saved)} />
+            </>
+        },
+        (Some(status), _, None) if status.enabled => html! {
+            <>
+                <p>{ format!("Two-factor authentication is on, with {} recovery codes remaining.", status.recovery_codes_remaining) }</p>
+                <TotpCodeForm title={"Turn off two-factor authentication"} submit_label={"Turn off"} on_submit={on_disable} />
+            </>
+        },
+        (Some(_), Some(enrolment), None) => html! {
+            <>
+                <p>{ "Scan this QR code with your authenticator app, or enter the key below, then enter the code it shows." }</p>
+                <QrCodeView data={enrolment.otpauth_uri.clone()} />
+                <p>{ "Key: " }<code>{ enrolment.secret.clone() }</code></p>
+                <TotpCodeForm title={"Confirm two-factor authentication"} submit_label={"Confirm"} on_submit={on_confirm} />
+            </>
+        },
+        (Some(_), None, None) => html! {
+            <>
+                <p>{ "Two-factor authentication is off. Once on, logging in also needs a code from an authenticator app." }</p>
+                <Button label={"Set up"} on_click={Some(on_start)} />
+            </>
+        },
+        (None, _, None) => html! {},
+    };
+
+    html! {
+        <>
+            <h>{ "Two-factor authentication" }</h>
+            { content }
+            if component_state.failed {
+                <p>{ "Request failed" }</p>
+            }
+        </>
+    }
+}
\ No newline at end of file
@@ -5,9 +5,16 @@ use crate::pages::account::AccountManagementPage;
 use crate::pages::associations::AssociationsPage;
 use crate::pages::change_password::ChangePasswordPage;
 use crate::pages::chat::ChatPage;
+use crate::pages::direct::DirectPage;
+use crate::pages::directory::DirectoryPage;
 use crate::pages::home::HomePage;
+use crate::pages::invitations::InvitationsPage;
+use crate::pages::join::JoinPage;
 use crate::pages::login::LoginPage;
+use crate::pages::mentions::MentionsPage;
 use crate::pages::registration::RegistrationPage;
+use crate::pages::search::SearchPage;
+use crate::pages::totp::TotpPage;
 
 #[derive(Routable, PartialEq, Clone, Debug)]
 pub enum Route {
@@ -21,8 +28,26 @@ pub enum Route {
     AccountManage,
     #[at("/account/change-password")]
     AccountChangePassword,
+    #[at("/account/2fa")]
+    AccountTotp,
     #[at("/chat")]
     Chats,
+    #[at("/chat/:room_id")]
+    ChatRoom { room_id: u64 },
+    #[at("/chat/:room_id/:message_id")]
+    ChatMessage { room_id: u64, message_id: u64 },
+    #[at("/search")]
+    Search,
+    #[at("/mentions")]
+    Mentions,
+    #[at("/rooms")]
+    Directory,
+    #[at("/invitations")]
+    Invitations,
+    #[at("/join/:code")]
+    Join { code: String },
+    #[at("/direct/:user_id")]
+    Direct { user_id: u64 },
     #[at("/associations")]
     Associations,
     #[not_found]
@@ -37,7 +62,16 @@ pub fn switch(route: Route) -> Html {
         Route::AccountLogin => html! { <LoginPage /> },
         Route::AccountManage => html! { <AccountManagementPage /> },
         Route::AccountChangePassword => html! { <ChangePasswordPage /> },
+        Route::AccountTotp => html! { <TotpPage /> },
         Route::Chats => html! { <ChatPage /> },
+        Route::ChatRoom { room_id } => html! { <ChatPage room={Some(room_id)} /> },
+        Route::ChatMessage { room_id, message_id } => html! { <ChatPage focus={Some((room_id, message_id))} /> },
+        Route::Search => html! { <SearchPage /> },
+        Route::Mentions => html! { <MentionsPage /> },
+        Route::Directory => html! { <DirectoryPage /> },
+        Route::Invitations => html! { <InvitationsPage /> },
+        Route::Join { code } => html! { <JoinPage code={code} /> },
+        Route::Direct { user_id } => html! { <DirectPage user_id={user_id} /> },
         Route::Associations => html! { <AssociationsPage /> },
         Route::NotFound => html! { <p1>{ "404 - Not Found" }</p1> },
     }
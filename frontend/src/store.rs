@@ -1,12 +1,12 @@
 use std::{collections::BTreeMap, str::FromStr};
 
+use common::AuthToken;
 use gloo_storage::Storage;
-use uuid::Uuid;
 use gloo::{console::log, storage::LocalStorage};
 use yewdux::prelude::*;
 
 pub trait StoreDispatchExt {
-    fn login_reduce(&self, username: String, user_id: u64, token: Uuid) -> ();
+    fn login_reduce(&self, username: String, user_id: u64, token: AuthToken) -> ();
     fn logout_reduce(&self) -> ();
     fn id_to_name_set_reduce(&self, user_id: u64, username: String) -> ();
     fn room_preview_msg_set_reduce(&self, room_id: u64, message: String) -> ();
@@ -26,7 +26,7 @@ impl Default for Store {
         let local_token = LocalStorage::get::<String>("user.token");
 
         let local_parsed_token = match local_token {
-            Ok(token_str) => Uuid::from_str(&token_str).ok(),
+            Ok(token_str) => AuthToken::from_str(&token_str).ok(),
             Err(_) => None,
         };
 
@@ -45,11 +45,11 @@ impl Default for Store {
 pub struct User {
     pub username: String,
     pub user_id: u64,
-    pub token: Uuid,
+    pub token: AuthToken,
 }
 
 impl User {
-    fn from_storage(username: String, user_id: u64, token: Uuid) -> Self {
+    fn from_storage(username: String, user_id: u64, token: AuthToken) -> Self {
         Self { username, user_id, token }
     }
 }
@@ -77,7 +77,7 @@ impl CachedData {
 }
 
 impl StoreDispatchExt for Dispatch<Store> {
-    fn login_reduce(&self, username: String, user_id: u64, token: Uuid) -> () {
+    fn login_reduce(&self, username: String, user_id: u64, token: AuthToken) -> () {
         let data = User { username, user_id, token };
         let mut local_storage_failed = false;
         local_storage_failed |= LocalStorage::set("user.username", &data.username).is_err();
@@ -0,0 +1,164 @@
+use std::ops::Deref;
+
+use chrono::{Duration, Utc};
+use common::{ChatRoomInviteCode, ChatRoomInviteCodeRequest};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+use yewdux::use_store;
+
+use crate::{
+    api_service,
+    components::button::Button,
+    store::Store,
+    widgets::list_view::ListView,
+};
+
+/// Expiry choices for new codes, in hours. `None` never expires
+const EXPIRY_OPTIONS: [(&str, Option<i64>); 4] = [
+    ("Never", None),
+    ("1 hour", Some(1)),
+    ("1 day", Some(24)),
+    ("7 days", Some(24 * 7))
+];
+
+/// Usage limit choices for new codes. `None` is unlimited
+const MAX_USES_OPTIONS: [(&str, Option<u64>); 5] = [
+    ("Unlimited", None),
+    ("1 use", Some(1)),
+    ("5 uses", Some(5)),
+    ("10 uses", Some(10)),
+    ("100 uses", Some(100))
+];
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct Props {
+    pub room_id: u64
+}
+
+#[derive(PartialEq, Clone, Default)]
+struct State {
+    invite_codes: Vec<ChatRoomInviteCode>,
+    /// Index into `EXPIRY_OPTIONS`
+    expiry_option: usize,
+    /// Index into `MAX_USES_OPTIONS`
+    max_uses_option: usize
+}
+
+/// Read the index of the selected option of a `<select>` element
+fn selected_index(event: Event) -> usize {
+    match event.target() {
+        Some(target) => target.unchecked_into::<HtmlSelectElement>().selected_index().max(0) as usize,
+        None => 0
+    }
+}
+
+#[function_component(InviteCodes)]
+pub fn invite_codes(props: &Props) -> Html {
+    let (store, _) = use_store::<Store>();
+    let token = store.user.clone().unwrap().token;
+
+    let component_state = use_state_eq(|| State::default());
+
+    {
+        let state_handle = component_state.clone();
+        use_effect_with(props.room_id, move |room_id| {
+            let room_id = *room_id;
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(invite_codes) = api_service::chat_get_invite_codes(&token, room_id).await {
+                    let mut updated_state = state_handle.deref().clone();
+                    updated_state.invite_codes = invite_codes;
+                    state_handle.set(updated_state);
+                }
+            });
+        });
+    }
+
+    let state_handle = component_state.clone();
+    let on_expiry_change = Callback::from(move |event: Event| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.expiry_option = selected_index(event);
+        state_handle.set(updated_state);
+    });
+
+    let state_handle = component_state.clone();
+    let on_max_uses_change = Callback::from(move |event: Event| {
+        let mut updated_state = state_handle.deref().clone();
+        updated_state.max_uses_option = selected_index(event);
+        state_handle.set(updated_state);
+    });
+
+    let state_handle = component_state.clone();
+    let room_id = props.room_id;
+    let on_create = Callback::from(move |_: MouseEvent| {
+        let state_handle = state_handle.clone();
+        let mut updated_state = state_handle.deref().clone();
+        let request = ChatRoomInviteCodeRequest {
+            expires_at: EXPIRY_OPTIONS[updated_state.expiry_option].1
+                .map(|hours| Utc::now() + Duration::hours(hours)),
+            max_uses: MAX_USES_OPTIONS[updated_state.max_uses_option].1
+        };
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Ok(invite_code) = api_service::chat_create_invite_code(&token, room_id, request).await {
+                updated_state.invite_codes.insert(0, invite_code);
+                state_handle.set(updated_state);
+            }
+        });
+    });
+
+    let state_handle = component_state.clone();
+    let on_revoke = Callback::from(move |code: String| {
+        let state_handle = state_handle.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Ok(()) = api_service::chat_revoke_invite_code(&token, room_id, &code).await {
+                let mut updated_state = state_handle.deref().clone();
+                updated_state.invite_codes.retain(|invite_code| invite_code.code != code);
+                state_handle.set(updated_state);
+            }
+        });
+    });
+
+    let origin = gloo::utils::window().location().origin().unwrap_or_default();
+    let invite_codes_html: Vec<Html> = component_state.invite_codes.iter()
+        .map(|invite_code| {
+            let uses = match invite_code.max_uses {
+                Some(max_uses) => format!("{}/{} uses", invite_code.uses, max_uses),
+                None => format!("{} uses", invite_code.uses)
+            };
+            let expiry = match invite_code.expires_at {
+                Some(time) => format!("expires {}", time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+                None => "never expires".to_string()
+            };
+            let code = invite_code.code.clone();
+            let revoke_callback = on_revoke.clone();
+            html! {
+                <div class={classes!("message_container")}>
+                    <p>{ format!("{}/join/{}", origin, invite_code.code) }</p>
+                    <p>{ uses }{ ", " }{ expiry }</p>
+                    <Button label={"Revoke"} on_click={
+                        Callback::from(move |_: MouseEvent| {
+                            revoke_callback.emit(code.clone())
+                        })
+                    } />
+                </div>
+            }
+        })
+        .collect();
+
+    html! {
+        <>
+            <select onchange={on_expiry_change}>
+                { for EXPIRY_OPTIONS.iter().enumerate().map(|(idx, (label, _))| html! {
+                    <option selected={component_state.expiry_option == idx}>{ *label }</option>
+                }) }
+            </select>
+            <select onchange={on_max_uses_change}>
+                { for MAX_USES_OPTIONS.iter().enumerate().map(|(idx, (label, _))| html! {
+                    <option selected={component_state.max_uses_option == idx}>{ *label }</option>
+                }) }
+            </select>
+            <Button label={"Create invite link"} on_click={on_create} />
+            <ListView children={invite_codes_html} />
+        </>
+    }
+}
\ No newline at end of file
@@ -6,6 +6,8 @@ pub mod registration_form;
 pub mod password_change_form;
 pub mod user_search;
 pub mod new_room_form;
+pub mod invite_codes;
+pub mod totp_code_form;
 
 #[derive(Clone)]
 enum AccountErrorReason {
@@ -0,0 +1,43 @@
+use yew::prelude::*;
+
+use crate::components::{
+    button::Button,
+    input_field::InputField
+};
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub title: String,
+    pub submit_label: String,
+    /// Called with the entered code, which is either a TOTP code or a
+    /// recovery code
+    pub on_submit: Callback<String>
+}
+
+/// A form to enter a code from an authenticator app
+#[function_component(TotpCodeForm)]
+pub fn totp_code_form(props: &Props) -> Html {
+    let code = use_state(String::new);
+
+    let code_changed = {
+        let code = code.clone();
+        Callback::from(move |text: String| code.set(text))
+    };
+
+    let on_submit = {
+        let props_on_submit = props.on_submit.clone();
+        Callback::from(move |event: SubmitEvent| {
+            event.prevent_default();
+            props_on_submit.emit(code.trim().to_string())
+        })
+    };
+
+    html! {
+        <form onsubmit={on_submit} class={classes!("account_form")}>
+            <h1>{ props.title.clone() }</h1>
+            <InputField name="code" autofocus=true on_change={code_changed} />
+            <br />
+            <Button label={props.submit_label.clone()} />
+        </form>
+    }
+}
\ No newline at end of file